@@ -0,0 +1,91 @@
+//! 比较各空间索引后端在 1 万到 100 万个实体时的性能。
+//!
+//! 运行：cargo bench --bench spatial_index
+//! 实体密度保持为每 100 平方单位一个，区域随实体数量放大；
+//! 最近邻与半径查询各执行至多 10 万次，更新执行至多 2000 次，输出每次操作的平均耗时。
+
+use std::time::{Duration, Instant};
+use bevy::prelude::*;
+use closed_ecosphere::spatial_index::{SpatialIndex, SpatialIndexKind};
+use closed_ecosphere::type_component::TypeComponent;
+use closed_ecosphere::world_bounds::{EdgeBehavior, WorldBounds};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Component)]
+struct Marker;
+impl TypeComponent for Marker {}
+
+const SIZES: [usize; 3] = [10_000, 100_000, 1_000_000];
+const MAX_OPS: usize = 100_000;
+// k-d 树的删除随规模变慢，更新只执行较少的次数
+const MAX_UPDATES: usize = 2_000;
+const QUERY_RADIUS: f32 = 50.0;
+const MOVE_STEP: f32 = 2.0;
+
+fn per_op(elapsed: Duration, ops: usize) -> String {
+    format!("{:>10.0} ns", elapsed.as_nanos() as f64 / ops as f64)
+}
+
+fn bench(name: &str, kind: SpatialIndexKind, edge_behavior: EdgeBehavior, n: usize) {
+    let half = (n as f32 * 100.0).sqrt() / 2.0;
+    let bounds = WorldBounds::new(half * 2.0, half * 2.0, edge_behavior);
+    let mut rng = StdRng::seed_from_u64(n as u64);
+    let mut positions: Vec<Vec2> = (0..n)
+        .map(|_| Vec2::new(rng.gen_range(-half..half), rng.gen_range(-half..half)))
+        .collect();
+    let ops = n.min(MAX_OPS);
+    let updates = n.min(MAX_UPDATES);
+
+    let mut index = SpatialIndex::<Marker>::new(kind, &bounds);
+    let start = Instant::now();
+    for (i, pos) in positions.iter().enumerate() {
+        index.insert(Entity::from_raw(i as u32), *pos);
+    }
+    let insert = start.elapsed();
+
+    let start = Instant::now();
+    for (i, pos) in positions.iter_mut().enumerate().take(updates) {
+        let step = Vec2::new(rng.gen_range(-MOVE_STEP..MOVE_STEP), rng.gen_range(-MOVE_STEP..MOVE_STEP));
+        *pos = bounds.constrain(*pos + step).0;
+        index.update(Entity::from_raw(i as u32), *pos);
+    }
+    let update = start.elapsed();
+
+    let queries: Vec<Vec2> = (0..ops)
+        .map(|_| Vec2::new(rng.gen_range(-half..half), rng.gen_range(-half..half)))
+        .collect();
+    let start = Instant::now();
+    let mut found = 0;
+    for pos in queries.iter() {
+        found += index.get_nearest(*pos).is_some() as usize;
+    }
+    let nearest = start.elapsed();
+
+    let start = Instant::now();
+    for pos in queries.iter() {
+        found += index.get_in_radius(*pos, QUERY_RADIUS).len();
+    }
+    let in_radius = start.elapsed();
+
+    println!("{:<14} {:>9} {:>14} {:>14} {:>14} {:>14}   ({} found)",
+        name, n, per_op(insert, n), per_op(update, updates), per_op(nearest, ops), per_op(in_radius, ops), found);
+}
+
+fn main() {
+    let backends = [
+        ("kd-tree", SpatialIndexKind::KdTree),
+        ("grid", SpatialIndexKind::Grid { cell_size: QUERY_RADIUS }),
+    ];
+    for edge_behavior in [EdgeBehavior::Clamp, EdgeBehavior::Wrap] {
+        println!("edge behavior: {:?}", edge_behavior);
+        println!("{:<14} {:>9} {:>14} {:>14} {:>14} {:>14}",
+            "backend", "entities", "insert", "update", "nearest", "in radius");
+        for n in SIZES {
+            for (name, kind) in backends {
+                bench(name, kind, edge_behavior, n);
+            }
+        }
+        println!();
+    }
+}
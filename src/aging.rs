@@ -1,4 +1,7 @@
 use bevy::prelude::*;
+use crate::energy::Energy;
+use crate::lifecycle::{DeathCause, DeathEvent, Species};
+use crate::movemement::MyPosition;
 
 #[derive(Component, Deref, DerefMut)]
 pub struct Age(Timer);
@@ -7,16 +10,83 @@ impl Age{
     pub fn from_age(age: f32) -> Self {
         Age(Timer::from_seconds(age, TimerMode::Once))
     }
+    pub fn from_timer(timer: Timer) -> Self {
+        Age(timer)
+    }
+    // 寿命为 age、已经活了 elapsed 秒的个体。elapsed 不小于 age 时计时器停在终点，
+    // 第一次 tick 即 just_finished，个体在第一帧老死
+    pub fn with_elapsed(age: f32, elapsed: f32) -> Self {
+        let mut timer = Timer::from_seconds(age, TimerMode::Once);
+        timer.set_elapsed(std::time::Duration::from_secs_f32(elapsed.min(age)));
+        Age(timer)
+    }
 }
 
+// 删除实体的命令按查询顺序串行发出，保证实体编号的回收顺序可复现
 pub fn aging_system(time: Res<Time>,
-                    mut query: Query<(Entity, &mut Age)>,
-                    par_commands: ParallelCommands){
-    query.par_iter_mut().for_each(|(entity, mut age)| {
+                    mut query: Query<(Entity, &mut Age, &Species, &MyPosition, Option<&Energy>)>,
+                    mut death_events: EventWriter<DeathEvent>,
+                    mut commands: Commands){
+    query.iter_mut().for_each(|(entity, mut age, &species, pos, energy)| {
         if age.tick(time.delta()).just_finished() {
-            par_commands.command_scope(|mut commands| {
-                commands.entity(entity).despawn_recursive();
+            death_events.send(DeathEvent {
+                entity,
+                species,
+                cause: DeathCause::OldAge,
+                position: pos.0,
+                age: age.elapsed_secs(),
+                energy: energy.map_or(0.0, |e| e.0),
+                killer: None,
             });
+            commands.entity(entity).despawn_recursive();
         }
     });
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::life_stage::JuvenileConfig;
+    use crate::lifecycle::LifecycleStatistics;
+    use crate::reproduction::ReproductionConfig;
+    use crate::species::SpeciesDefinition;
+    use crate::test_app::headless_app;
+
+    #[test]
+    fn already_finished_age_dies_on_first_tick() {
+        let mut age = Age::with_elapsed(5.0, 8.0);
+        assert!(!age.finished());
+        assert!(age.tick(std::time::Duration::ZERO).just_finished());
+    }
+
+    // 性成熟年龄不小于寿命时，从性成熟年龄开始的初始个体在第一帧老死，不会永远活着
+    #[test]
+    fn initial_animals_older_than_lifespan_die_at_once() {
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(1),
+            species: vec![SpeciesDefinition {
+                name: "Mayfly".to_string(),
+                initial_count: 5,
+                lifespan: 5.0,
+                reproduction: Some(ReproductionConfig {
+                    juvenile: JuvenileConfig {
+                        maturity_age: 10.0,
+                        ..default()
+                    },
+                    ..default()
+                }),
+                ..default()
+            }],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        for _ in 0..3 {
+            app.update();
+        }
+        let statistics = app.world().resource::<LifecycleStatistics>();
+        assert_eq!(statistics.deaths(Species::Animal(0), DeathCause::OldAge), 5);
+        assert_eq!(app.world_mut().query::<&Age>().iter(app.world()).count(), 0);
+    }
+}
@@ -0,0 +1,428 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::aging::{aging_system, Age};
+use crate::carcass::{feed_on_carcass, find_carcass, ScavengerAgent};
+use crate::chase::{pursue, Pursuit};
+use crate::config::Config;
+use crate::energy::{energy_system, Energy};
+use crate::escape_system::{escape_from, EscapeAgent, EscapeState};
+use crate::genome::Genome;
+use crate::health::Health;
+use crate::herding::{herding, HerdAgent};
+use crate::life_stage::{give_birth, grow_up, on_juvenile_born};
+use crate::lifecycle::Species;
+use crate::movemement::{movement_update, species_index_update, Movement, MyPosition};
+use crate::pack_hunting::{flank_prey, join_pack, share_kill};
+use crate::pathfinding::NavPath;
+use crate::prey_agent::*;
+use crate::reproduction::{find_mate_when_energy_enough_and_idle, mating_conditions, reproduction_state_running, searching_mate_conditions, ReproductionAgent, ReproductionState};
+use crate::spatial_index::{on_animal_birth, on_animal_death, SpeciesIndex};
+use crate::species::{SpeciesDefinition, SpeciesRegistry};
+use crate::type_component::TypeComponent;
+use crate::wander::Wander;
+use crate::water::{drink, find_water, hydration_system, lurk_at_water, DrinkerAgent};
+use crate::world_bounds::WorldBounds;
+
+///
+/// 动物的状态，所有物种共用。物种没有的能力对应的状态不会进入，
+/// 例如不能逃跑的物种不会处于 Fleeing
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum AnimalState
+{
+    Idle,
+    Hunting,
+    AttackCooling,
+    Eating,
+    SearchingMate,
+    Mating,
+    Fleeing,
+    Scavenging,
+    SeekingWater,
+    Drinking,
+}
+impl AnimalState {
+    // 按声明顺序列出所有状态
+    pub const ALL: [AnimalState; 10] = [
+        AnimalState::Idle,
+        AnimalState::Hunting,
+        AnimalState::AttackCooling,
+        AnimalState::Eating,
+        AnimalState::SearchingMate,
+        AnimalState::Mating,
+        AnimalState::Fleeing,
+        AnimalState::Scavenging,
+        AnimalState::SeekingWater,
+        AnimalState::Drinking,
+    ];
+}
+#[derive(Component)]
+pub struct AnimalAgent
+{
+    pub state: AnimalState,
+    pub timer: Timer,
+    pub target: Option<Entity>,
+    pub last_energy_gain: f32,
+}
+impl TypeComponent for AnimalAgent{
+
+}
+impl HunterAgent for AnimalAgent{
+    fn is_idle(&self) -> bool {
+        self.state == AnimalState::Idle
+    }
+    fn is_hunting(&self) -> bool {
+        self.state == AnimalState::Hunting
+    }
+
+    fn is_attack_cooling(&self) -> bool {
+        self.state == AnimalState::AttackCooling
+    }
+
+    fn is_eating(&self) -> bool {
+        self.state == AnimalState::Eating
+    }
+
+    fn switch_to_idle(&mut self) {
+        self.state = AnimalState::Idle;
+    }
+
+    fn switch_to_hunting(&mut self, prey: Entity) {
+        self.state = AnimalState::Hunting;
+        self.target = Some(prey);
+    }
+
+    fn switch_back_to_hunting(&mut self) {
+        self.state = AnimalState::Hunting;
+    }
+
+    fn switch_to_attack_cooling(&mut self, cooling_time: f32) {
+        self.state = AnimalState::AttackCooling;
+        self.timer = Timer::from_seconds(cooling_time, TimerMode::Once);
+    }
+
+    fn switch_to_eating(&mut self, energy_gain: f32, eating_time: f32) {
+        self.state = AnimalState::Eating;
+        self.timer = Timer::from_seconds(eating_time, TimerMode::Once);
+        self.last_energy_gain = energy_gain;
+    }
+
+    fn get_last_prey_energy_gain(&self) -> f32 {
+        self.last_energy_gain
+    }
+
+    fn take_last_prey_energy_gain(&mut self) -> f32 {
+        std::mem::take(&mut self.last_energy_gain)
+    }
+
+    fn get_prey(&self) -> Option<Entity> {
+        self.target
+    }
+
+    fn get_attack_cooling_timer(&mut self) -> &mut Timer {
+        &mut self.timer
+    }
+
+    fn get_eating_timer(&mut self) -> &mut Timer {
+        &mut self.timer
+    }
+}
+
+impl ReproductionAgent for AnimalAgent{
+    fn get_state(&self) -> ReproductionState {
+        match self.state {
+            AnimalState::Idle => ReproductionState::Idle,
+            AnimalState::SearchingMate => ReproductionState::SearchingMate,
+            AnimalState::Mating => ReproductionState::Mating,
+            AnimalState::Hunting | AnimalState::Scavenging => ReproductionState::OtherCanMate,
+            _ => ReproductionState::OtherCantMate
+        }
+    }
+
+    fn switch_to_idle(&mut self) {
+        self.state = AnimalState::Idle;
+    }
+
+    fn switch_to_searching_mate(&mut self, mate: Entity) {
+        self.state = AnimalState::SearchingMate;
+        self.target = Some(mate);
+    }
+
+    fn switch_to_mating(&mut self, mating_time: f32) {
+        self.state = AnimalState::Mating;
+        self.timer = Timer::from_seconds(mating_time, TimerMode::Once);
+    }
+
+    fn get_mate(&self) -> Option<Entity> {
+        self.target
+    }
+
+    fn get_reproduction_timer(&mut self) -> &mut Timer {
+        &mut self.timer
+    }
+}
+
+impl EscapeAgent for AnimalAgent{
+    fn get_state(&self) -> EscapeState {
+        match self.state {
+            AnimalState::Fleeing => EscapeState::Fleeing,
+            AnimalState::Idle |
+            AnimalState::Hunting |
+            AnimalState::SearchingMate |
+            AnimalState::Mating |
+            AnimalState::Eating |
+            AnimalState::Scavenging |
+            AnimalState::SeekingWater |
+            AnimalState::Drinking => EscapeState::CanFlee,
+            _ => EscapeState::CantFlee
+        }
+    }
+
+    fn switch_to_fleeing(&mut self) {
+        self.state = AnimalState::Fleeing;
+    }
+
+    fn switch_to_idle(&mut self) {
+        self.state = AnimalState::Idle;
+    }
+
+    fn is_distracted(&self) -> bool {
+        self.state == AnimalState::Drinking
+    }
+}
+
+impl HerdAgent for AnimalAgent{
+    fn is_herding(&self) -> bool {
+        matches!(self.state, AnimalState::Idle | AnimalState::Hunting | AnimalState::Fleeing)
+    }
+}
+
+impl ScavengerAgent for AnimalAgent{
+    fn is_scavenging(&self) -> bool {
+        self.state == AnimalState::Scavenging
+    }
+
+    fn switch_to_scavenging(&mut self, carcass: Entity) {
+        self.state = AnimalState::Scavenging;
+        self.target = Some(carcass);
+    }
+
+    fn get_carcass(&self) -> Option<Entity> {
+        self.target
+    }
+}
+
+impl DrinkerAgent for AnimalAgent{
+    fn can_seek_water(&self) -> bool {
+        self.state == AnimalState::Idle
+    }
+
+    fn is_seeking_water(&self) -> bool {
+        self.state == AnimalState::SeekingWater
+    }
+
+    fn is_drinking(&self) -> bool {
+        self.state == AnimalState::Drinking
+    }
+
+    fn switch_to_seeking_water(&mut self) {
+        self.state = AnimalState::SeekingWater;
+    }
+
+    fn switch_to_drinking(&mut self) {
+        self.state = AnimalState::Drinking;
+    }
+
+    fn stop_drinking(&mut self) {
+        self.state = AnimalState::Idle;
+    }
+}
+
+#[derive(Bundle)]
+pub struct AnimalBundle {
+    pub health: Health,
+    pub species: Species,
+    pub age: Age,
+    // Agent
+    pub agent: AnimalAgent,
+    pub energy: Energy,
+    // 渲染相关
+    pub mesh2d: Mesh2d,
+    pub mesh_material2d: MeshMaterial2d<ColorMaterial>,
+    // 位置
+    pub transform: Transform,
+    pub my_pos: MyPosition,
+    pub movement: Movement,
+    pub wander: Wander,
+    pub nav_path: NavPath,
+    pub pursuit: Pursuit,
+    pub genome: Genome,
+}
+impl AnimalBundle {
+    // 由物种声明与给定的基因组构造个体，后声明的物种显示在上层
+    pub fn new(species: Species, definition: &SpeciesDefinition, genome: Genome, x: f32, y: f32) -> Self {
+        let layer = match species {
+            Species::Animal(i) => 1.0 + i as f32,
+            Species::Grass => 0.0,
+        };
+        AnimalBundle {
+            health: Health(definition.health),
+            species,
+            age: Age::from_age(genome.lifespan),
+            agent: AnimalAgent{
+                state: AnimalState::Idle,
+                timer: Timer::from_seconds(0.0, TimerMode::Once),
+                target: None,
+                last_energy_gain: 0.0,
+            },
+            energy: Energy(definition.energy),
+            mesh2d: Mesh2d(definition.shape_handle.clone()),
+            mesh_material2d: MeshMaterial2d(definition.material.clone()),
+            transform: Transform::from_xyz(x, y, layer),
+            my_pos: MyPosition(Vec2::new(x, y)),
+            movement: Movement{
+                speed: genome.speed,
+                direction: Vec2::ZERO,
+            },
+            wander: Wander::from_interval(definition.wander_interval),
+            nav_path: NavPath::default(),
+            pursuit: Pursuit::default(),
+            genome,
+        }
+    }
+}
+
+///
+/// 所有动物物种：捕食、逃跑、食腐、繁殖、生命阶段、群体行为与合作捕猎的系统，以及按物种分开的空间索引。
+/// 各系统对所有动物运行，按个体所属物种的声明取参数，物种没有的能力直接跳过。
+/// 需要先插入 SpeciesRegistry
+///
+pub struct AnimalPlugin;
+
+impl Plugin for AnimalPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding AnimalPlugin");
+        let bounds = WorldBounds::from_config(config);
+        let registry = app.world().get_resource::<SpeciesRegistry>()
+            .expect("SpeciesRegistry must be inserted before adding AnimalPlugin");
+        let herding_enabled = registry.iter().any(|(_, d)| d.herding.as_ref().is_some_and(|h| h.is_enabled()));
+        let pack_hunting = registry.iter().any(|(_, d)| d.pack.is_some());
+        let thirst = registry.iter().any(|(_, d)| d.thirst.is_some());
+        let ambush = registry.iter().any(|(_, d)| d.ambush.is_some());
+        let chase = registry.iter().any(|(_, d)| d.chase.is_some());
+        let index = SpeciesIndex::<AnimalAgent>::new(registry, &bounds);
+        app.insert_resource(index)
+            // 逃跑系统
+            .add_systems(FixedUpdate, escape_from::<AnimalAgent>)
+            // Prey Agent
+            .add_systems(FixedUpdate, (
+                find_prey::<AnimalAgent>,
+                attack::<AnimalAgent>,)
+                .after(energy_system)
+                .after(aging_system)
+                .after(escape_from::<AnimalAgent>))
+            .add_systems(FixedUpdate, (
+                move_to_prey::<AnimalAgent>,
+                on_attack_cooling::<AnimalAgent>,
+                on_eating::<AnimalAgent>,)
+                .after(attack::<AnimalAgent>)
+                .after(find_prey::<AnimalAgent>))
+            // 食腐：空闲时优先前往附近的尸体
+            .add_systems(FixedUpdate, (
+                find_carcass::<AnimalAgent>
+                    .after(find_mate_when_energy_enough_and_idle::<AnimalAgent>)
+                    .before(find_prey::<AnimalAgent>),
+                feed_on_carcass::<AnimalAgent>
+                    .after(find_carcass::<AnimalAgent>)
+                    .before(on_eating::<AnimalAgent>),)
+                .after(energy_system)
+                .after(aging_system))
+            // Reproduction Agent
+            .add_systems(FixedUpdate, (
+                // Idle 状态下，优先找配偶，找不到配偶再寻找食物
+                find_mate_when_energy_enough_and_idle::<AnimalAgent>
+                    .before(find_prey::<AnimalAgent>),
+                searching_mate_conditions::<AnimalAgent>,
+                mating_conditions::<AnimalAgent>)
+                .after(energy_system)
+                .after(aging_system)
+                .after(escape_from::<AnimalAgent>))
+            .add_systems(FixedUpdate, reproduction_state_running::<AnimalAgent>
+                .after(find_mate_when_energy_enough_and_idle::<AnimalAgent>)
+                .after(searching_mate_conditions::<AnimalAgent>)
+                .after(mating_conditions::<AnimalAgent>))
+            // 生命阶段：幼年个体成年，妊娠期满的母亲分娩
+            .add_systems(FixedUpdate, (
+                grow_up::<AnimalAgent>
+                    .before(find_mate_when_energy_enough_and_idle::<AnimalAgent>)
+                    .before(find_prey::<AnimalAgent>),
+                give_birth::<AnimalAgent>)
+                .after(energy_system)
+                .after(aging_system))
+            .add_systems(FixedPostUpdate, species_index_update::<AnimalAgent>.after(movement_update))
+            .add_observer(on_juvenile_born::<AnimalAgent>)
+            .add_observer(on_animal_birth::<AnimalAgent>)
+            .add_observer(on_animal_death::<AnimalAgent>);
+        // 合作捕猎：空闲个体先尝试加入猎群，再单独寻找猎物
+        if pack_hunting {
+            app.add_systems(FixedUpdate, (
+                join_pack::<AnimalAgent>
+                    .after(find_mate_when_energy_enough_and_idle::<AnimalAgent>)
+                    .after(find_carcass::<AnimalAgent>)
+                    .after(energy_system)
+                    .after(aging_system)
+                    .before(find_prey::<AnimalAgent>),
+                share_kill::<AnimalAgent>
+                    .after(attack::<AnimalAgent>)
+                    .before(move_to_prey::<AnimalAgent>)
+                    .before(on_attack_cooling::<AnimalAgent>)
+                    .before(on_eating::<AnimalAgent>),
+                flank_prey::<AnimalAgent>
+                    .after(move_to_prey::<AnimalAgent>),
+            ));
+        }
+        // 饮水：空闲时口渴优先于寻找配偶、尸体与猎物
+        if thirst {
+            app.add_systems(FixedUpdate, (
+                find_water::<AnimalAgent>
+                    .after(hydration_system)
+                    .after(aging_system)
+                    .after(escape_from::<AnimalAgent>)
+                    .after(grow_up::<AnimalAgent>)
+                    .before(find_mate_when_energy_enough_and_idle::<AnimalAgent>),
+                drink::<AnimalAgent>
+                    .after(find_water::<AnimalAgent>)
+                    .before(find_mate_when_energy_enough_and_idle::<AnimalAgent>),
+            ));
+        }
+        // 伏击：找不到猎物的空闲捕食者到水边守候
+        if ambush {
+            app.add_systems(FixedUpdate, lurk_at_water::<AnimalAgent>
+                .after(find_prey::<AnimalAgent>)
+                .after(give_birth::<AnimalAgent>)
+                .before(move_to_prey::<AnimalAgent>));
+        }
+        // 追捕过久或过远时放弃
+        if chase {
+            app.add_systems(FixedUpdate, pursue::<AnimalAgent>
+                .after(move_to_prey::<AnimalAgent>)
+                .after(on_attack_cooling::<AnimalAgent>)
+                .after(on_eating::<AnimalAgent>)
+                .after(flank_prey::<AnimalAgent>)
+                .after(hydration_system));
+        }
+        // 群体行为在其它系统给出运动方向之后叠加
+        if herding_enabled {
+            app.add_systems(FixedUpdate, herding::<AnimalAgent>
+                .after(move_to_prey::<AnimalAgent>)
+                .after(on_attack_cooling::<AnimalAgent>)
+                .after(on_eating::<AnimalAgent>)
+                .after(reproduction_state_running::<AnimalAgent>)
+                .after(flank_prey::<AnimalAgent>)
+                .after(lurk_at_water::<AnimalAgent>)
+                .after(drink::<AnimalAgent>)
+                .after(pursue::<AnimalAgent>));
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use crate::config::Config;
+use crate::lifecycle::Species;
+use crate::species::SpeciesRegistry;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+impl Season {
+    // 按一年中的顺序列出所有季节
+    pub const ALL: [Season; 4] = [Season::Spring, Season::Summer, Season::Autumn, Season::Winter];
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DayPhase {
+    Day,
+    Night,
+}
+
+///
+/// 随季节与昼夜变化的倍率。四季的值取在各季节的正中，季节之间线性过渡，
+/// 再乘以白天或夜晚的值。全部为 1 时不产生任何影响
+///
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct SeasonalCurve {
+    pub spring: f32,
+    pub summer: f32,
+    pub autumn: f32,
+    pub winter: f32,
+    pub day: f32,
+    pub night: f32,
+}
+impl Default for SeasonalCurve {
+    fn default() -> Self {
+        SeasonalCurve {
+            spring: 1.0,
+            summer: 1.0,
+            autumn: 1.0,
+            winter: 1.0,
+            day: 1.0,
+            night: 1.0,
+        }
+    }
+}
+impl SeasonalCurve {
+    fn season(&self, season: Season) -> f32 {
+        match season {
+            Season::Spring => self.spring,
+            Season::Summer => self.summer,
+            Season::Autumn => self.autumn,
+            Season::Winter => self.winter,
+        }
+    }
+    pub fn value(&self, calendar: &SimCalendar) -> f32 {
+        // 以季节正中为节点，x 落在第 i 个与第 i + 1 个节点之间
+        let x = calendar.year_fraction() * 4.0 - 0.5;
+        let i = x.floor();
+        let t = x - i;
+        let a = self.season(Season::ALL[(i as i32).rem_euclid(4) as usize]);
+        let b = self.season(Season::ALL[(i as i32 + 1).rem_euclid(4) as usize]);
+        let phase = match calendar.phase() {
+            DayPhase::Day => self.day,
+            DayPhase::Night => self.night,
+        };
+        (a + (b - a) * t) * phase
+    }
+}
+
+///
+/// 模拟日历：由固定帧的模拟时间推算。一年从春季的第一天清晨开始，四季等长；
+/// 每天的前 daylight_fraction 为白天，其余为夜晚。
+/// 只依赖模拟时间，从快照恢复后自然接续
+///
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SimCalendar {
+    day_length: f32,
+    year_length: f32,
+    daylight_fraction: f32,
+    elapsed: f32,
+}
+
+impl SimCalendar {
+    pub fn from_config(config: &Config) -> Self {
+        SimCalendar {
+            day_length: config.day_length_secs,
+            year_length: config.year_length_secs,
+            daylight_fraction: config.daylight_fraction,
+            elapsed: 0.0,
+        }
+    }
+    pub fn set_elapsed(&mut self, elapsed: f32) {
+        self.elapsed = elapsed;
+    }
+    // 一年中已经过去的比例，在 [0, 1) 内
+    pub fn year_fraction(&self) -> f32 {
+        (self.elapsed / self.year_length).fract()
+    }
+    // 一天中已经过去的比例，在 [0, 1) 内
+    pub fn time_of_day(&self) -> f32 {
+        (self.elapsed / self.day_length).fract()
+    }
+    // 从第 0 天开始的天数
+    pub fn day(&self) -> u64 {
+        (self.elapsed / self.day_length) as u64
+    }
+    pub fn season(&self) -> Season {
+        Season::ALL[((self.year_fraction() * 4.0) as usize).min(3)]
+    }
+    pub fn phase(&self) -> DayPhase {
+        if self.time_of_day() < self.daylight_fraction {
+            DayPhase::Day
+        } else {
+            DayPhase::Night
+        }
+    }
+}
+
+///
+/// 本帧各曲线的取值：草的繁殖概率、代谢消耗，以及各动物物种的速度与捕食活跃度，
+/// 每个固定帧开始时按日历更新一次
+///
+#[derive(Resource)]
+pub struct SeasonalModifiers {
+    pub grass_reproduction: f32,
+    pub metabolism: f32,
+    speed: Vec<f32>,
+    hunting: Vec<f32>,
+}
+
+impl SeasonalModifiers {
+    pub fn new(config: &Config, registry: &SpeciesRegistry, calendar: &SimCalendar) -> Self {
+        SeasonalModifiers {
+            grass_reproduction: config.grass_reproduction_curve.value(calendar),
+            metabolism: config.metabolism_curve.value(calendar),
+            speed: registry.iter().map(|(_, d)| d.speed_curve.value(calendar)).collect(),
+            hunting: registry.iter().map(|(_, d)| d.hunting_curve.value(calendar)).collect(),
+        }
+    }
+    // 速度倍率，草不移动，取 1
+    pub fn speed(&self, species: Species) -> f32 {
+        match species {
+            Species::Animal(i) => self.speed[i],
+            Species::Grass => 1.0,
+        }
+    }
+    // 捕食活跃度，按比例缩放寻找猎物的感知半径，为 0 时不捕食
+    pub fn hunting(&self, species: Species) -> f32 {
+        match species {
+            Species::Animal(i) => self.hunting[i],
+            Species::Grass => 1.0,
+        }
+    }
+}
+
+///
+/// 日历与随日历变化的倍率。需要在插入 SpeciesRegistry 之后加入
+///
+pub struct CalendarPlugin;
+
+impl Plugin for CalendarPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding CalendarPlugin");
+        let registry = app.world().get_resource::<SpeciesRegistry>()
+            .expect("SpeciesRegistry must be inserted before adding CalendarPlugin");
+        let calendar = SimCalendar::from_config(config);
+        let modifiers = SeasonalModifiers::new(config, registry, &calendar);
+        app.insert_resource(calendar)
+            .insert_resource(modifiers)
+            .add_systems(FixedFirst, advance_calendar);
+    }
+}
+
+// 应该被放在 FixedFirst 里，本帧的其它系统都使用更新后的倍率
+pub fn advance_calendar(
+    time: Res<Time<Fixed>>,
+    config: Res<Config>,
+    registry: Res<SpeciesRegistry>,
+    mut calendar: ResMut<SimCalendar>,
+    mut modifiers: ResMut<SeasonalModifiers>,
+) {
+    calendar.set_elapsed(time.elapsed_secs());
+    *modifiers = SeasonalModifiers::new(&config, &registry, &calendar);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 一天 60 秒、一年 480 秒，白天占一半
+    fn calendar(elapsed: f32) -> SimCalendar {
+        let mut calendar = SimCalendar::from_config(&Config::default());
+        calendar.set_elapsed(elapsed);
+        calendar
+    }
+
+    fn curve() -> SeasonalCurve {
+        SeasonalCurve {
+            spring: 1.0,
+            summer: 2.0,
+            autumn: 3.0,
+            winter: 4.0,
+            day: 1.0,
+            night: 1.0,
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn season_midpoints_take_season_values() {
+        let curve = curve();
+        for (elapsed, season, value) in [(60.0, Season::Spring, 1.0), (180.0, Season::Summer, 2.0),
+                                         (300.0, Season::Autumn, 3.0), (420.0, Season::Winter, 4.0)] {
+            let calendar = calendar(elapsed);
+            assert_eq!(calendar.season(), season);
+            assert_close(curve.value(&calendar), value);
+        }
+    }
+
+    #[test]
+    fn season_boundaries_interpolate_halfway() {
+        let curve = curve();
+        assert_eq!(calendar(119.9).season(), Season::Spring);
+        assert_eq!(calendar(120.0).season(), Season::Summer);
+        assert_close(curve.value(&calendar(120.0)), 1.5);
+        assert_close(curve.value(&calendar(240.0)), 2.5);
+        assert_close(curve.value(&calendar(360.0)), 3.5);
+        // 季节正中之间线性过渡
+        assert_close(curve.value(&calendar(90.0)), 1.25);
+    }
+
+    #[test]
+    fn winter_wraps_into_spring() {
+        let curve = curve();
+        // 冬季正中之后向下一年春季的正中过渡
+        assert_close(curve.value(&calendar(450.0)), 3.25);
+        assert_close(curve.value(&calendar(0.0)), 2.5);
+        assert_close(curve.value(&calendar(30.0)), 1.75);
+        assert_eq!(calendar(479.9).season(), Season::Winter);
+        assert_eq!(calendar(480.0).season(), Season::Spring);
+        assert_close(curve.value(&calendar(480.0 + 450.0)), 3.25);
+    }
+
+    #[test]
+    fn day_and_night_split() {
+        assert_eq!(calendar(0.0).phase(), DayPhase::Day);
+        assert_eq!(calendar(29.9).phase(), DayPhase::Day);
+        assert_eq!(calendar(30.0).phase(), DayPhase::Night);
+        assert_eq!(calendar(59.9).phase(), DayPhase::Night);
+        assert_eq!(calendar(60.0).phase(), DayPhase::Day);
+        assert_eq!(calendar(150.0).day(), 2);
+        let curve = SeasonalCurve {
+            day: 1.0,
+            night: 0.5,
+            ..curve()
+        };
+        assert_close(curve.value(&calendar(60.0)), 1.0);
+        assert_close(curve.value(&calendar(75.0)), 1.125);
+        assert_close(curve.value(&calendar(30.0)), 1.75 * 0.5);
+    }
+}
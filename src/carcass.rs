@@ -0,0 +1,300 @@
+use std::time::Duration;
+use bevy::prelude::*;
+use crate::config::Config;
+use crate::grass::Grass;
+use crate::grass_reproduction::{grass_reproduction_system, GrassGrowthBonus};
+use crate::lifecycle::{DeathCause, DeathEvent, Species};
+use crate::movemement::{Movement, MyPosition};
+use crate::nutrient::SoilNutrients;
+use crate::pathfinding::{steer_towards, NavPath, NavigationGrid};
+use serde::Deserialize;
+use crate::prey_agent::{HunterAgent, ATTACK_DISTANCE};
+use crate::spatial_index::{on_entity_birth, on_entity_death, SpatialIndex, SpatialIndexKind};
+use crate::species::SpeciesRegistry;
+use crate::type_component::TypeComponent;
+use crate::world_bounds::WorldBounds;
+
+///
+/// 动物死后留下的尸体，biomass 为剩余的生物量。
+/// 尸体随时间腐烂，腐烂的部分使周围的草生长得更快；生物量耗尽时尸体消失
+///
+#[derive(Component)]
+pub struct Carcass {
+    pub species: Species,
+    pub biomass: f32,
+}
+impl TypeComponent for Carcass {}
+
+#[derive(Bundle)]
+pub struct CarcassBundle {
+    pub carcass: Carcass,
+    // 渲染相关
+    pub mesh2d: Mesh2d,
+    pub mesh_material2d: MeshMaterial2d<ColorMaterial>,
+    // 位置
+    pub transform: Transform,
+    pub my_pos: MyPosition,
+}
+impl CarcassBundle {
+    pub fn new(config: &Config, species: Species, biomass: f32, pos: Vec2) -> Self {
+        CarcassBundle {
+            carcass: Carcass { species, biomass },
+            mesh2d: Mesh2d(config.carcass_shape.clone()),
+            mesh_material2d: MeshMaterial2d(config.carcass_material.clone()),
+            transform: Transform::from_xyz(pos.x, pos.y, 0.5),
+            my_pos: MyPosition(pos),
+        }
+    }
+}
+
+// 死亡后尸体的生物量。被捕食时捕食者已经吃掉了猎物的捕食收获，只留下剩余部分。
+// 封闭的养分循环中，尸体的生物量就是动物死亡时剩余的能量
+pub fn carcass_biomass(config: &Config, registry: &SpeciesRegistry, event: &DeathEvent) -> f32 {
+    if event.species == Species::Grass {
+        return 0.0;
+    }
+    if config.nutrient_cycle {
+        return event.energy.max(0.0);
+    }
+    let definition = registry.get(event.species);
+    let (biomass, eaten) = (definition.carcass_biomass, definition.gain);
+    if event.cause == DeathCause::Predation {
+        (biomass - eaten).max(0.0)
+    } else {
+        biomass
+    }
+}
+
+///
+/// 食腐者：空闲时前往附近的尸体进食
+///
+pub trait ScavengerAgent {
+    fn is_scavenging(&self) -> bool;
+    fn switch_to_scavenging(&mut self, carcass: Entity);
+    fn get_carcass(&self) -> Option<Entity>;
+}
+
+// 食腐的参数：寻找尸体的半径，以及每次进食最多吃掉的生物量
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ScavengeConfig{
+    pub radius: f32,
+    pub bite: f32,
+}
+impl Default for ScavengeConfig{
+    fn default() -> Self{
+        ScavengeConfig{
+            radius: 200.0,
+            bite: 50.0,
+        }
+    }
+}
+
+///
+/// 尸体：死亡时生成尸体、腐烂并滋养周围的草，以及尸体的空间索引
+///
+pub struct CarcassPlugin;
+
+impl Plugin for CarcassPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding CarcassPlugin");
+        let index = SpatialIndex::<Carcass>::new(SpatialIndexKind::KdTree, &WorldBounds::from_config(config));
+        app.insert_resource(index)
+            // 本帧 FixedUpdate 中的死亡都已发生
+            .add_systems(FixedPostUpdate, spawn_carcasses)
+            .add_systems(FixedUpdate, decompose.before(grass_reproduction_system))
+            .add_observer(on_entity_birth::<Carcass>)
+            .add_observer(on_entity_death::<Carcass>);
+    }
+}
+
+// 为本帧死亡的动物生成尸体，生成命令按事件顺序串行发出
+pub fn spawn_carcasses(
+    mut death_events: EventReader<DeathEvent>,
+    config: Res<Config>,
+    registry: Res<SpeciesRegistry>,
+    mut commands: Commands,
+) {
+    for event in death_events.read() {
+        let biomass = carcass_biomass(&config, &registry, event);
+        if biomass > 0.0 {
+            commands.spawn(CarcassBundle::new(&config, event.species, biomass, event.position));
+        }
+    }
+}
+
+// 尸体以 carcass_decay_rate 的速度腐烂，腐烂的生物量平均分给 carcass_nutrient_radius 内的草，
+// 每单位生物量使草的繁殖计时器多走 carcass_nutrient_boost 秒。
+// 存在土壤养分场时腐烂的生物量还给所在位置的土壤，否则附近没有草时养分流失
+pub fn decompose(
+    mut carcass_query: Query<(Entity, &mut Carcass, &MyPosition)>,
+    mut grass_query: Query<&mut GrassGrowthBonus, With<Grass>>,
+    grass_index: Res<SpatialIndex<Grass>>,
+    mut soil: Option<ResMut<SoilNutrients>>,
+    config: Res<Config>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    carcass_query.iter_mut().for_each(|(entity, mut carcass, pos)| {
+        let decayed = (config.carcass_decay_rate * time.delta_secs()).min(carcass.biomass.max(0.0));
+        carcass.biomass -= decayed;
+        if let Some(soil) = soil.as_mut() {
+            soil.deposit(pos.0, decayed);
+        }
+        let nearby = grass_index.get_in_radius(pos.0, config.carcass_nutrient_radius);
+        if !nearby.is_empty() {
+            let bonus = decayed * config.carcass_nutrient_boost / nearby.len() as f32;
+            for &(_, grass) in nearby.iter() {
+                if let Ok(mut growth) = grass_query.get_mut(*grass) {
+                    growth.0 += Duration::from_secs_f32(bonus);
+                }
+            }
+        }
+        if carcass.biomass <= 0.0 {
+            commands.entity(entity).despawn();
+        }
+    });
+}
+
+// 应该被放在 find_prey 之前，声明了 scavenge 的物种空闲时优先前往附近最近的尸体
+pub fn find_carcass<TH>(mut hunter_query: Query<(&mut TH, &Species, &MyPosition)>,
+                        carcass_query: Query<&Carcass>,
+                        index: Res<SpatialIndex<Carcass>>,
+                        registry: Res<SpeciesRegistry>) where TH: Component + HunterAgent + ScavengerAgent + TypeComponent
+{
+    hunter_query.par_iter_mut().for_each(|(mut hunter_agent, &species, hunter_pos)| {
+        let Some(scavenge) = registry.get(species).scavenge.as_ref() else {
+            return;
+        };
+        if hunter_agent.is_idle()
+        {
+            let edible = |carcass: Entity| carcass_query.get(carcass).is_ok_and(|c| c.biomass > 0.0);
+            if let Some((_, &carcass)) = index.get_nearest_matching(hunter_pos.0, scavenge.radius, edible) {
+                hunter_agent.switch_to_scavenging(carcass);
+            }
+        }
+    });
+}
+
+// 走向尸体，到达后吃掉至多 bite 的生物量并开始进食。尸体已经消失或被吃完时回到 idle。
+// 多个个体可能同时吃同一具尸体，按查询顺序串行扣除生物量
+pub fn feed_on_carcass<TH>(mut hunter_query: Query<(&mut TH, &Species, &mut Movement, &mut NavPath, &MyPosition)>,
+                           mut carcass_query: Query<(&mut Carcass, &MyPosition)>,
+                           registry: Res<SpeciesRegistry>,
+                           nav: Option<Res<NavigationGrid>>,
+                           bounds: Res<WorldBounds>) where TH: Component + HunterAgent + ScavengerAgent + TypeComponent
+{
+    hunter_query.iter_mut().for_each(|(mut hunter_agent, &species, mut movement, mut path, hunter_pos)| {
+        if !hunter_agent.is_scavenging() {
+            return;
+        }
+        let definition = registry.get(species);
+        let Some(scavenge) = definition.scavenge.as_ref() else {
+            hunter_agent.switch_to_idle();
+            return;
+        };
+        let Some(target) = hunter_agent.get_carcass() else {
+            hunter_agent.switch_to_idle();
+            return;
+        };
+        let Ok((mut carcass, carcass_pos)) = carcass_query.get_mut(target) else {
+            hunter_agent.switch_to_idle();
+            return;
+        };
+        if carcass.biomass <= 0.0 {
+            hunter_agent.switch_to_idle();
+        } else if bounds.distance(hunter_pos.0, carcass_pos.0) < ATTACK_DISTANCE {
+            let amount = scavenge.bite.min(carcass.biomass);
+            carcass.biomass -= amount;
+            hunter_agent.switch_to_eating(amount, definition.eating_time);
+        } else {
+            movement.direction = steer_towards(nav.as_deref(), &mut path, &bounds, hunter_pos.0, carcass_pos.0);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animal::{AnimalAgent, AnimalState};
+    use crate::lifecycle::LifecycleStatistics;
+    use crate::species::SpeciesDefinition;
+    use crate::test_app::{headless_app, place_animals};
+
+    const DEER: Species = Species::Animal(0);
+    const WOLF: Species = Species::Animal(1);
+    const VULTURE: Species = Species::Animal(2);
+
+    // 狼咬死鹿后尸体留下 carcass_biomass - gain 的生物量，附近的秃鹫前来食腐。尸体不腐烂，生物量只因进食减少
+    #[test]
+    fn predation_leaves_carcass_for_scavengers() {
+        let deer = SpeciesDefinition {
+            name: "Deer".to_string(),
+            initial_count: 1,
+            energy: 1000.0,
+            lifespan: 1000.0,
+            speed: 0.0,
+            gain: 30.0,
+            carcass_biomass: 80.0,
+            ..default()
+        };
+        let wolf = SpeciesDefinition {
+            name: "Wolf".to_string(),
+            initial_count: 1,
+            energy: 1000.0,
+            lifespan: 1000.0,
+            damage: 100.0,
+            diet: vec!["Deer".to_string()],
+            ..default()
+        };
+        let vulture = SpeciesDefinition {
+            name: "Vulture".to_string(),
+            initial_count: 1,
+            energy: 1000.0,
+            lifespan: 1000.0,
+            scavenge: Some(ScavengeConfig {
+                radius: 200.0,
+                bite: 20.0,
+            }),
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(14),
+            carcass_decay_rate: 0.0,
+            species: vec![deer, wolf, vulture],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        place_animals(&mut app, DEER, &[Vec2::ZERO]);
+        place_animals(&mut app, WOLF, &[Vec2::new(30.0, 0.0)]);
+        let vulture = place_animals(&mut app, VULTURE, &[Vec2::new(-100.0, 0.0)])[0];
+        let mut frames = 0;
+        while app.world().resource::<LifecycleStatistics>().deaths(DEER, DeathCause::Predation) == 0 {
+            app.update();
+            frames += 1;
+            assert!(frames < 1000, "the wolf never caught the deer");
+        }
+        let biomass = |app: &mut App| -> Vec<f32> {
+            app.world_mut().query::<&Carcass>().iter(app.world()).map(|c| c.biomass).collect()
+        };
+        assert_eq!(biomass(&mut app), vec![50.0]);
+        let mut history = vec![50.0];
+        let mut fed = false;
+        while app.world().resource::<Time<Fixed>>().elapsed_secs() < 20.0 {
+            app.update();
+            let agent = app.world().get::<AnimalAgent>(vulture).unwrap();
+            fed |= agent.state == AnimalState::Eating && agent.last_energy_gain > 0.0;
+            let remaining = biomass(&mut app).first().copied().unwrap_or(0.0);
+            if history.last() != Some(&remaining) {
+                history.push(remaining);
+            }
+        }
+        assert!(fed, "the vulture never fed on the carcass");
+        // 每口吃掉 bite，最后一口吃掉剩下的 10，吃完后尸体消失
+        assert_eq!(history, vec![50.0, 30.0, 10.0, 0.0]);
+        assert!(biomass(&mut app).is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use crate::energy::Energy;
+use crate::lifecycle::Species;
+use crate::movemement::MyPosition;
+use crate::nutrient::SoilNutrients;
+use crate::prey_agent::HunterAgent;
+use crate::species::SpeciesRegistry;
+use crate::world_bounds::WorldBounds;
+
+///
+/// 追捕与攻击的参数。追捕（含攻击冷却）超过 max_duration 秒或跑过 max_distance 的距离时放弃，
+/// 放弃后休息 rest_time 秒才能再寻找猎物，两个上限为 0 时不限制；追捕时每秒额外消耗 energy_cost 的能量。
+/// 攻击以一定概率命中：对同样快、正在逃跑的猎物为 success_rate，
+/// 按速度优势 2 * 捕食者速度 / (捕食者速度 + 猎物速度) 缩放，猎物没有在逃跑时再乘以 unaware_bonus，
+/// 随追捕时间接近 max_duration 最多降低 fatigue_penalty 的比例
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ChaseConfig {
+    pub max_duration: f32,
+    pub max_distance: f32,
+    pub rest_time: f32,
+    pub energy_cost: f32,
+    pub success_rate: f32,
+    pub unaware_bonus: f32,
+    pub fatigue_penalty: f32,
+}
+impl Default for ChaseConfig {
+    fn default() -> Self {
+        ChaseConfig {
+            max_duration: 20.0,
+            max_distance: 600.0,
+            rest_time: 5.0,
+            energy_cost: 1.0,
+            success_rate: 0.5,
+            unaware_bonus: 1.5,
+            fatigue_penalty: 0.5,
+        }
+    }
+}
+impl ChaseConfig {
+    ///
+    /// 一次攻击命中的概率，速度取本帧的实际速度，静止的猎物（例如草）速度为 0
+    ///
+    pub fn strike_probability(&self, hunter_speed: f32, prey_speed: f32, prey_fleeing: bool, pursuit: &Pursuit) -> f32 {
+        let total = hunter_speed + prey_speed;
+        let advantage = if total > 0.0 { 2.0 * hunter_speed / total } else { 1.0 };
+        let awareness = if prey_fleeing { 1.0 } else { self.unaware_bonus };
+        let fatigue = if self.max_duration > 0.0 {
+            1.0 - self.fatigue_penalty * (pursuit.elapsed / self.max_duration).min(1.0)
+        } else {
+            1.0
+        };
+        (self.success_rate * advantage * awareness * fatigue).clamp(0.0, 1.0)
+    }
+    fn exhausted(&self, pursuit: &Pursuit) -> bool {
+        (self.max_duration > 0.0 && pursuit.elapsed >= self.max_duration)
+            || (self.max_distance > 0.0 && pursuit.distance >= self.max_distance)
+    }
+}
+
+///
+/// 个体当前这次追捕已经持续的时间与跑过的距离，以及放弃追捕后剩余的休息时间
+///
+#[derive(Component, Default, Clone, Copy)]
+pub struct Pursuit {
+    pub elapsed: f32,
+    pub distance: f32,
+    // 上一帧的位置，用于累计跑过的距离，不在追捕时为 None
+    pub last_position: Option<Vec2>,
+    pub rest: f32,
+}
+impl Pursuit {
+    pub fn is_resting(&self) -> bool {
+        self.rest > 0.0
+    }
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.distance = 0.0;
+        self.last_position = None;
+    }
+}
+
+// 应该被放在 attack 与 move_to_prey 之后。只有声明了 chase 的物种会放弃追捕。
+// 追捕消耗的能量不会扣到负数，存在土壤养分场时还给所在位置的土壤，能量耗尽的个体由 energy_system 在下一帧处理
+pub fn pursue<TH>(mut query: Query<(&mut TH, &mut Pursuit, &mut Energy, &Species, &MyPosition)>,
+                  registry: Res<SpeciesRegistry>,
+                  mut soil: Option<ResMut<SoilNutrients>>,
+                  bounds: Res<WorldBounds>,
+                  time: Res<Time>) where TH: Component + HunterAgent
+{
+    let delta = time.delta_secs();
+    query.iter_mut().for_each(|(mut agent, mut pursuit, mut energy, &species, pos)| {
+        let Some(chase) = registry.get(species).chase.as_ref() else {
+            return;
+        };
+        pursuit.rest = (pursuit.rest - delta).max(0.0);
+        if !agent.is_hunting() && !agent.is_attack_cooling() {
+            pursuit.reset();
+            return;
+        }
+        if let Some(last) = pursuit.last_position {
+            pursuit.distance += bounds.distance(last, pos.0);
+        }
+        pursuit.last_position = Some(pos.0);
+        pursuit.elapsed += delta;
+        if agent.is_hunting() {
+            let cost = (chase.energy_cost * delta).min(energy.0.max(0.0));
+            energy.0 -= cost;
+            if let Some(soil) = soil.as_mut() {
+                soil.deposit(pos.0, cost);
+            }
+        }
+        if chase.exhausted(&pursuit) {
+            agent.switch_to_idle();
+            pursuit.reset();
+            pursuit.rest = chase.rest_time;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animal::{AnimalAgent, AnimalState};
+    use crate::config::Config;
+    use crate::escape_system::EscapeConfig;
+    use crate::species::SpeciesDefinition;
+    use crate::test_app::{headless_app, place_animals};
+
+    const EPSILON: f32 = 1e-5;
+
+    fn pursuit(elapsed: f32) -> Pursuit {
+        Pursuit { elapsed, ..default() }
+    }
+
+    #[test]
+    fn equal_speed_fleeing_prey_gives_success_rate() {
+        let chase = ChaseConfig::default();
+        let p = chase.strike_probability(20.0, 20.0, true, &pursuit(0.0));
+        assert!((p - chase.success_rate).abs() < EPSILON);
+        // 静止的猎物速度为 0，速度优势为 2
+        let p = chase.strike_probability(20.0, 0.0, true, &pursuit(0.0));
+        assert!((p - chase.success_rate * 2.0).abs() < EPSILON);
+        // 双方都不动时不计速度优势
+        let p = chase.strike_probability(0.0, 0.0, true, &pursuit(0.0));
+        assert!((p - chase.success_rate).abs() < EPSILON);
+    }
+
+    #[test]
+    fn unaware_bonus_and_fatigue_scale_probability() {
+        let chase = ChaseConfig {
+            max_duration: 10.0,
+            success_rate: 0.4,
+            unaware_bonus: 1.5,
+            fatigue_penalty: 0.5,
+            ..default()
+        };
+        let p = chase.strike_probability(20.0, 20.0, false, &pursuit(0.0));
+        assert!((p - 0.6).abs() < EPSILON);
+        // 疲劳随追捕时间线性增加，到 max_duration 后不再增加
+        let p = chase.strike_probability(20.0, 20.0, true, &pursuit(5.0));
+        assert!((p - 0.4 * 0.75).abs() < EPSILON);
+        let p = chase.strike_probability(20.0, 20.0, true, &pursuit(30.0));
+        assert!((p - 0.4 * 0.5).abs() < EPSILON);
+        // 不限制追捕时间时没有疲劳
+        let unlimited = ChaseConfig { max_duration: 0.0, ..chase.clone() };
+        let p = unlimited.strike_probability(20.0, 20.0, true, &pursuit(30.0));
+        assert!((p - 0.4).abs() < EPSILON);
+    }
+
+    #[test]
+    fn probability_is_clamped() {
+        let eager = ChaseConfig {
+            success_rate: 0.9,
+            unaware_bonus: 3.0,
+            ..default()
+        };
+        assert_eq!(eager.strike_probability(40.0, 0.0, false, &pursuit(0.0)), 1.0);
+        let hopeless = ChaseConfig {
+            success_rate: 0.5,
+            fatigue_penalty: 3.0,
+            ..default()
+        };
+        assert_eq!(hopeless.strike_probability(20.0, 20.0, true, &pursuit(hopeless.max_duration)), 0.0);
+    }
+
+    // 跑得比狼快、会逃跑的鹿，以及咬不伤鹿的狼，狼只能一直追下去直到放弃
+    fn chase_app(chase: ChaseConfig) -> (App, Entity) {
+        let deer = SpeciesDefinition {
+            name: "Deer".to_string(),
+            initial_count: 1,
+            energy: 1000.0,
+            lifespan: 1000.0,
+            speed: 30.0,
+            predators: vec!["Wolf".to_string()],
+            flee: Some(EscapeConfig::default()),
+            ..default()
+        };
+        let wolf = SpeciesDefinition {
+            name: "Wolf".to_string(),
+            initial_count: 1,
+            energy: 1000.0,
+            lifespan: 1000.0,
+            damage: 0.0,
+            perception_radius: 300.0,
+            diet: vec!["Deer".to_string()],
+            chase: Some(chase),
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(4),
+            species: vec![deer, wolf],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        place_animals(&mut app, Species::Animal(0), &[Vec2::new(60.0, 0.0)]);
+        let wolf = place_animals(&mut app, Species::Animal(1), &[Vec2::ZERO])[0];
+        (app, wolf)
+    }
+
+    // 运行 secs 秒，按帧记录狼的状态、追捕进度与能量
+    fn record(app: &mut App, wolf: Entity, secs: f32) -> Vec<(f32, AnimalState, Pursuit, f32)> {
+        let mut frames = Vec::new();
+        while app.world().resource::<Time<Fixed>>().elapsed_secs() < secs {
+            app.update();
+            let entity = app.world().entity(wolf);
+            frames.push((
+                app.world().resource::<Time<Fixed>>().elapsed_secs(),
+                entity.get::<AnimalAgent>().unwrap().state,
+                *entity.get::<Pursuit>().unwrap(),
+                entity.get::<Energy>().unwrap().0,
+            ));
+        }
+        frames
+    }
+
+    fn is_chasing(state: AnimalState) -> bool {
+        matches!(state, AnimalState::Hunting | AnimalState::AttackCooling)
+    }
+
+    #[test]
+    fn hunter_gives_up_after_max_duration_and_rests() {
+        let chase = ChaseConfig {
+            max_duration: 5.0,
+            max_distance: 0.0,
+            rest_time: 3.0,
+            energy_cost: 10.0,
+            ..default()
+        };
+        let (mut app, wolf) = chase_app(chase);
+        let frames = record(&mut app, wolf, 12.0);
+        let start = frames.iter().position(|f| is_chasing(f.1)).expect("the wolf never started chasing");
+        let stop = start + frames[start..].iter().position(|f| !is_chasing(f.1)).expect("the wolf never gave up");
+        let duration = frames[stop].0 - frames[start].0;
+        assert!((duration - 5.0).abs() < 0.05, "chased for {} seconds", duration);
+        assert!((frames[stop].2.rest - 3.0).abs() < EPSILON);
+        // 追捕时每秒多消耗 energy_cost，外加 1 的代谢消耗
+        let spent = frames[start].3 - frames[stop].3;
+        assert!(spent >= 5.0 * 10.0, "spent {} energy", spent);
+        // 休息时不再追捕，休息结束后重新追捕
+        let rested = stop + frames[stop..].iter().position(|f| !f.2.is_resting()).expect("the wolf never stopped resting");
+        assert!(frames[stop..rested].iter().all(|f| f.1 == AnimalState::Idle));
+        assert!((frames[rested].0 - frames[stop].0 - 3.0).abs() < 0.05);
+        assert!(frames[rested..].iter().any(|f| is_chasing(f.1)));
+    }
+
+    #[test]
+    fn hunter_gives_up_after_max_distance() {
+        let chase = ChaseConfig {
+            max_duration: 0.0,
+            max_distance: 50.0,
+            rest_time: 3.0,
+            ..default()
+        };
+        let (mut app, wolf) = chase_app(chase);
+        let frames = record(&mut app, wolf, 8.0);
+        let start = frames.iter().position(|f| is_chasing(f.1)).expect("the wolf never started chasing");
+        let stop = start + frames[start..].iter().position(|f| !is_chasing(f.1)).expect("the wolf never gave up");
+        // 速度为 20 时跑过 50 的距离需要约 2.5 秒
+        let last_chase = frames[stop - 1].2;
+        assert!(last_chase.distance < 50.0 && last_chase.distance > 45.0, "ran {}", last_chase.distance);
+        let duration = frames[stop].0 - frames[start].0;
+        assert!((duration - 2.5).abs() < 0.1, "chased for {} seconds", duration);
+        assert!(frames[stop].2.is_resting());
+    }
+}
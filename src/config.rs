@@ -1,18 +1,102 @@
+use std::path::Path;
 use bevy::asset::Handle;
 use bevy::prelude::*;
+use serde::Deserialize;
+use crate::calendar::SeasonalCurve;
+use crate::spatial_index::SpatialIndexKind;
+use crate::terrain::{TerrainConfig, TerrainGrid};
+use crate::water::WaterSource;
+use crate::species::{default_species, load_species, SpeciesDefinition};
+use crate::world_bounds::EdgeBehavior;
 
-#[derive(Resource)]
+// 配置文件中声明的模型
+#[derive(Deserialize, Clone)]
+pub enum ShapeConfig{
+    Circle{ radius: f32 },
+    Rectangle{ width: f32, height: f32 },
+    RegularPolygon{ circumradius: f32, sides: u32 },
+}
+impl ShapeConfig{
+    pub fn to_mesh(&self) -> Mesh {
+        match *self {
+            ShapeConfig::Circle{ radius } => Circle::new(radius).into(),
+            ShapeConfig::Rectangle{ width, height } => Rectangle::new(width, height).into(),
+            ShapeConfig::RegularPolygon{ circumradius, sides } => RegularPolygon::new(circumradius, sides).into(),
+        }
+    }
+}
+// 配置文件中声明的颜色，sRGB
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct ColorConfig(pub f32, pub f32, pub f32);
+impl ColorConfig{
+    pub fn to_color(self) -> Color {
+        Color::srgb(self.0, self.1, self.2)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError{
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Terrain(String),
+    Invalid(String),
+}
+impl std::fmt::Display for ConfigError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "failed to parse config file: {}", e),
+            ConfigError::Terrain(e) => write!(f, "failed to load terrain map: {}", e),
+            ConfigError::Invalid(e) => write!(f, "invalid config: {}", e),
+        }
+    }
+}
+impl std::error::Error for ConfigError{}
+
+// 所有数值均可由 RON 配置文件给出，缺省字段取 Default 中的值。
+// 句柄字段不参与反序列化，由 create_handles 根据 *_shape_config / *_color_config 以及各物种的模型与颜色生成。
+#[derive(Resource, Deserialize)]
+#[serde(default)]
 pub struct Config{
     // 模拟区域大小
     pub width: f32,
     pub height: f32,
+    // 实体到达区域边界时的处理方式
+    pub edge_behavior: EdgeBehavior,
+    // 地形图路径，相对配置文件所在的目录，图像拉伸后铺满整个区域
+    pub terrain_path: Option<String>,
+    // 各地形的颜色、草的肥力与移动代价
+    pub terrain: TerrainConfig,
+    // 从 terrain_path 读取的地形格子，为 None 时整个区域都是可通行的草地
+    #[serde(skip)]
+    pub terrain_map: Option<TerrainGrid>,
+    // 存在地形图时，追捕、寻找配偶与食腐沿 A* 路径绕过障碍，逃跑时避开前方的障碍
+    pub pathfinding: bool,
+    // 一次 A* 最多展开的格子数，超过时放弃寻路改走直线
+    pub pathfinding_node_budget: usize,
+    // 目标离规划时的位置超过这个距离才重新规划，至少为一个格子的对角线
+    pub pathfinding_replan_distance: f32,
+    // 圆形水源，地形图中水面的岸边也可以饮水
+    pub water_sources: Vec<WaterSource>,
     // 摄像机速度
     pub camera_speed: f32,
     pub camera_zoom_speed: f32,
+    // 无窗口模式及其模拟时长，按秒
+    pub headless: bool,
+    pub headless_duration_secs: f32,
+    // 种群数据记录的 CSV 文件路径，不指定则不记录
+    pub record_path: Option<String>,
+    // 记录的采样间隔，按模拟时间的秒
+    pub record_interval_secs: f32,
+    // 随机数种子，不指定时每次运行随机选取
+    pub seed: Option<u64>,
+    // 快照文件路径，指定后按模拟时间定时保存，退出时也会保存一次
+    pub snapshot_path: Option<String>,
+    pub snapshot_interval_secs: f32,
+    // 从快照文件恢复模拟，指定后不再随机生成初始实体
+    pub load_snapshot: Option<String>,
     // 初始参数
     pub initial_grass_count: usize,
-    pub initial_cow_count: usize,
-    pub initial_tiger_count: usize,
 
     // For Grass
     pub grass_health: f32,
@@ -27,98 +111,113 @@ pub struct Config{
     pub grass_reproduction_radius: f32,
     // 草的捕食收获
     pub grass_gain: f32,
+    // 草的繁殖概率随季节与昼夜变化的倍率
+    pub grass_reproduction_curve: SeasonalCurve,
+    // 草的空间索引后端
+    pub grass_spatial_index: SpatialIndexKind,
     // 草的模型
+    #[serde(skip)]
     pub grass_shape: Handle<Mesh>,
     // 草的材质
+    #[serde(skip)]
     pub grass_material: Handle<ColorMaterial>,
 
-    // For Cow
-    pub cow_health: f32,
-    pub cow_age: f32, // in seconds
-    // 牛初始能量
-    pub cow_energy: f32,
-    // 牛的捕食收获
-    pub cow_gain: f32,
-    // 牛的伤害
-    pub cow_damage: f32,
-    // 牛的攻击冷却时间
-    pub cow_attack_cooling_time: f32,
-    // 牛的吃草时间
-    pub cow_eating_time: f32,
-    // 牛的速度
-    pub cow_speed: f32,
-    // 牛的繁殖能量阈值
-    pub cow_reproduction_energy_threshold: f32,
-    // 牛的繁殖能量消耗
-    pub cow_reproduction_cost: f32,
-    // 牛的寻找伴侣半径
-    pub cow_search_radius: f32,
-    // 牛的繁殖半径
-    pub cow_reproduction_radius: f32,
-    // 牛的繁殖时间
-    pub cow_mating_time: f32,
-    // 牛的逃跑检测半径
-    pub cow_escape_radius: f32,
-    // 牛的模型
-    pub cow_shape: Handle<Mesh>,
-
-    // For Tiger
-    pub tiger_health: f32,
-    pub tiger_age: f32, // in seconds
-    // 虎初始能量
-    pub tiger_energy: f32,
-    // 虎的捕食收获
-    pub tiger_gain: f32,
-    // 虎的伤害
-    pub tiger_damage: f32,
-    // 虎的攻击冷却时间
-    pub tiger_attack_cooling_time: f32,
-    // 虎的进食时间
-    pub tiger_eating_time: f32,
-    // 虎的速度
-    pub tiger_speed: f32,
-    // 虎的繁殖能量阈值
-    pub tiger_reproduction_energy_threshold: f32,
-    // 虎的繁殖能量消耗
-    pub tiger_reproduction_cost: f32,
-    // 虎的寻找伴侣半径
-    pub tiger_search_radius: f32,
-    // 虎的繁殖半径
-    pub tiger_reproduction_radius: f32,
-    // 虎的繁殖时间
-    pub tiger_mating_time: f32,
-    // 虎的模型
-    pub tiger_shape: Handle<Mesh>,
-
-    // 动物各状态下颜色
-    pub idle_color: Handle<ColorMaterial>,
+    // 动物物种的声明，各物种的初始数量、数值与能力见 SpeciesDefinition
+    pub species: Vec<SpeciesDefinition>,
+    // 物种文件路径，相对配置文件所在的目录，指定后代替 species
+    pub species_path: Option<String>,
+
+    // 动物各状态下颜色，空闲时显示物种自身的颜色
+    #[serde(skip)]
     pub hunting_color: Handle<ColorMaterial>,
+    #[serde(skip)]
     pub attack_cooling_color: Handle<ColorMaterial>,
+    #[serde(skip)]
     pub eating_color: Handle<ColorMaterial>,
+    #[serde(skip)]
     pub searching_mate_color: Handle<ColorMaterial>,
+    #[serde(skip)]
     pub mating_color: Handle<ColorMaterial>,
+    #[serde(skip)]
     pub escaping_color: Handle<ColorMaterial>,
+    #[serde(skip)]
+    pub drinking_color: Handle<ColorMaterial>,
 
-    pub escape_update_delta_secs: f32
+    // 逃跑方向的更新间隔，为 0 时每个固定帧都更新
+    pub escape_update_delta_secs: f32,
+
+    // 模拟日历：一天与一年的长度，按模拟秒，以及一天中白天所占的比例
+    pub day_length_secs: f32,
+    pub year_length_secs: f32,
+    pub daylight_fraction: f32,
+    // 所有动物代谢消耗随季节与昼夜变化的倍率
+    pub metabolism_curve: SeasonalCurve,
+
+    // 基因组每个性状的突变概率与最大相对变化幅度
+    pub genome_mutation_rate: f32,
+    pub genome_mutation_scale: f32,
+
+    // 尸体每秒腐烂的生物量，死后的生物量见各物种的 carcass_biomass
+    pub carcass_decay_rate: f32,
+    // 腐烂的养分滋养的草的范围，以及每单位生物量使草的繁殖计时器多走的秒数
+    pub carcass_nutrient_radius: f32,
+    pub carcass_nutrient_boost: f32,
+    // 封闭的养分循环：开启后草繁殖需要消耗土壤养分，代谢、死亡与腐烂把养分还给土壤
+    pub nutrient_cycle: bool,
+    // 土壤养分场的格子边长与每格的初始养分
+    pub soil_cell_size: f32,
+    pub soil_initial_nutrient: f32,
+    // 是否在每帧检查养分总量守恒，以及允许的相对误差
+    pub nutrient_conservation_check: bool,
+    pub nutrient_tolerance: f32,
+    // 尸体的模型与材质
+    #[serde(skip)]
+    pub carcass_shape: Handle<Mesh>,
+    #[serde(skip)]
+    pub carcass_material: Handle<ColorMaterial>,
+    // 圆形水源的材质
+    #[serde(skip)]
+    pub water_material: Handle<ColorMaterial>,
+
+    // 模型与颜色的声明，启动时据此生成上面的句柄
+    pub grass_shape_config: ShapeConfig,
+    pub grass_color_config: ColorConfig,
+    pub hunting_color_config: ColorConfig,
+    pub attack_cooling_color_config: ColorConfig,
+    pub eating_color_config: ColorConfig,
+    pub searching_mate_color_config: ColorConfig,
+    pub mating_color_config: ColorConfig,
+    pub escaping_color_config: ColorConfig,
+    pub drinking_color_config: ColorConfig,
+    pub carcass_shape_config: ShapeConfig,
+    pub carcass_color_config: ColorConfig,
+    pub water_color_config: ColorConfig,
 }
 
-impl Config {
-    pub fn from(
-        width: f32,
-        height: f32,
-        initial_grass_count: usize,
-        initial_cow_count: usize,
-        initial_tiger_count: usize,
-        world: &mut World,
-    ) -> Self {
+impl Default for Config {
+    fn default() -> Self {
         Config {
-            width: width,
-            height: height,
+            width: 1000.0,
+            height: 1000.0,
+            edge_behavior: EdgeBehavior::Clamp,
+            terrain_path: None,
+            terrain: TerrainConfig::default(),
+            terrain_map: None,
+            pathfinding: true,
+            pathfinding_node_budget: 10000,
+            pathfinding_replan_distance: 20.0,
+            water_sources: Vec::new(),
             camera_speed: 128.0,
             camera_zoom_speed: 0.2,
-            initial_grass_count,
-            initial_cow_count,
-            initial_tiger_count,
+            headless: false,
+            headless_duration_secs: 600.0,
+            record_path: None,
+            record_interval_secs: 1.0,
+            seed: None,
+            snapshot_path: None,
+            snapshot_interval_secs: 60.0,
+            load_snapshot: None,
+            initial_grass_count: 100,
             grass_health: 10.0,
             grass_age: 30.0,
             grass_reproduction_delta: 8.0,
@@ -126,57 +225,181 @@ impl Config {
             grass_reproduction_rate_2: 0.2,
             grass_reproduction_radius: 50.0,
             grass_gain: 15.0,
-            grass_shape: world.get_resource_mut::<Assets<Mesh>>()
-                .unwrap().add(Circle::new(5.0)),
-            grass_material: world.get_resource_mut::<Assets<ColorMaterial>>()
-                .unwrap().add(Color::srgb(0.0,1.0,0.0)),
-            cow_health: 50.0,
-            cow_age: 100.0,
-            cow_gain: 50.0,
-            cow_damage: 10.0,
-            cow_attack_cooling_time: 1.0,
-            cow_eating_time: 2.0,
-            cow_energy: 50.0,
-            cow_speed: 20.0,
-            cow_reproduction_energy_threshold: 100.0,
-            cow_reproduction_cost: 40.0,
-            cow_search_radius: 500.0,
-            cow_reproduction_radius: 40.0,
-            cow_mating_time: 5.0,
-            cow_escape_radius: 100.0,
-            cow_shape: world.get_resource_mut::<Assets<Mesh>>()
-                .unwrap().add(Rectangle::new(20.0, 20.0)),
-            tiger_health: 100.0,
-            tiger_age: 200.0,
-            tiger_gain: 100.0,
-            tiger_damage: 20.0,
-            tiger_attack_cooling_time: 2.0,
-            tiger_eating_time: 5.0,
-            tiger_energy: 100.0,
-            tiger_speed: 40.0,
-            tiger_reproduction_energy_threshold: 200.0,
-            tiger_reproduction_cost: 80.0,
-            tiger_search_radius: 1000.0,
-            tiger_reproduction_radius: 80.0,
-            tiger_mating_time: 10.0,
-            tiger_shape: world.get_resource_mut::<Assets<Mesh>>()
-                .unwrap().add(RegularPolygon::new(20.0, 6)),
-            idle_color: world.get_resource_mut::<Assets<ColorMaterial>>()
-                .unwrap().add(Color::srgb(1.0,1.0,1.0)),
-            hunting_color: world.get_resource_mut::<Assets<ColorMaterial>>()
-                .unwrap().add(Color::srgb(0.0,0.0,1.0)),
-            attack_cooling_color: world.get_resource_mut::<Assets<ColorMaterial>>()
-                .unwrap().add(Color::srgb(0.0,0.5,1.0)),
-            eating_color: world.get_resource_mut::<Assets<ColorMaterial>>()
-                .unwrap().add(Color::srgb(0.0,1.0,1.0)),
-            searching_mate_color: world.get_resource_mut::<Assets<ColorMaterial>>()
-                .unwrap().add(Color::srgb(0.5,0.0,1.0)),
-            mating_color: world.get_resource_mut::<Assets<ColorMaterial>>()
-                .unwrap().add(Color::srgb(1.0,0.0,1.0)),
-            escaping_color: world.get_resource_mut::<Assets<ColorMaterial>>()
-                .unwrap().add(Color::srgb(1.0,0.5,0.0)),
-
-            escape_update_delta_secs: 1.0
+            grass_reproduction_curve: SeasonalCurve::default(),
+            grass_spatial_index: SpatialIndexKind::KdTree,
+            grass_shape: Handle::default(),
+            grass_material: Handle::default(),
+            species: default_species(),
+            species_path: None,
+            hunting_color: Handle::default(),
+            attack_cooling_color: Handle::default(),
+            eating_color: Handle::default(),
+            searching_mate_color: Handle::default(),
+            mating_color: Handle::default(),
+            escaping_color: Handle::default(),
+            drinking_color: Handle::default(),
+
+            escape_update_delta_secs: 0.0,
+
+            day_length_secs: 60.0,
+            year_length_secs: 480.0,
+            daylight_fraction: 0.5,
+            metabolism_curve: SeasonalCurve::default(),
+
+            genome_mutation_rate: 0.1,
+            genome_mutation_scale: 0.1,
+
+            carcass_decay_rate: 2.0,
+            carcass_nutrient_radius: 50.0,
+            carcass_nutrient_boost: 0.5,
+            nutrient_cycle: false,
+            soil_cell_size: 50.0,
+            soil_initial_nutrient: 50.0,
+            nutrient_conservation_check: false,
+            nutrient_tolerance: 1e-4,
+            carcass_shape: Handle::default(),
+            carcass_material: Handle::default(),
+            water_material: Handle::default(),
+
+            grass_shape_config: ShapeConfig::Circle{ radius: 5.0 },
+            grass_color_config: ColorConfig(0.0, 1.0, 0.0),
+            hunting_color_config: ColorConfig(0.0, 0.0, 1.0),
+            attack_cooling_color_config: ColorConfig(0.0, 0.5, 1.0),
+            eating_color_config: ColorConfig(0.0, 1.0, 1.0),
+            searching_mate_color_config: ColorConfig(0.5, 0.0, 1.0),
+            mating_color_config: ColorConfig(1.0, 0.0, 1.0),
+            escaping_color_config: ColorConfig(1.0, 0.5, 0.0),
+            drinking_color_config: ColorConfig(0.0, 0.8, 0.6),
+            carcass_shape_config: ShapeConfig::Circle{ radius: 6.0 },
+            carcass_color_config: ColorConfig(0.5, 0.3, 0.1),
+            water_color_config: ColorConfig(0.0, 0.0, 1.0),
+        }
+    }
+}
+
+impl Config {
+    ///
+    /// 从 RON 配置文件读取参数，文件中未给出的字段使用默认值。
+    /// 指定了 species_path、terrain_path 时再读取物种文件与地形图，相对路径以配置文件所在的目录为准
+    ///
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let mut config: Config = ron::from_str(&text).map_err(ConfigError::Parse)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        if let Some(species_path) = config.species_path.as_ref() {
+            config.species = load_species(dir.join(species_path))?;
+        }
+        if let Some(terrain_path) = config.terrain_path.as_ref() {
+            config.terrain_map = Some(TerrainGrid::load(dir.join(terrain_path), &config.terrain)?);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+    ///
+    /// 检查无法运行的取值：日与年的长度必须为正，各物种的性成熟年龄必须小于寿命
+    ///
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(self.day_length_secs > 0.0 && self.year_length_secs > 0.0) {
+            return Err(ConfigError::Invalid(format!("day_length_secs ({}) and year_length_secs ({}) must be positive",
+                self.day_length_secs, self.year_length_secs)));
+        }
+        if let Some(definition) = self.species.iter().find(|d| d.maturity_age() >= d.lifespan) {
+            return Err(ConfigError::Invalid(format!("species {:?}: maturity_age ({}) must be less than lifespan ({})",
+                definition.name, definition.maturity_age(), definition.lifespan)));
         }
+        Ok(())
     }
-}
\ No newline at end of file
+    ///
+    /// 根据模型与颜色的声明创建网格和材质资源，需要在 App 加入渲染插件后调用
+    ///
+    pub fn create_handles(&mut self, world: &mut World) {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        self.grass_shape = meshes.add(self.grass_shape_config.to_mesh());
+        self.carcass_shape = meshes.add(self.carcass_shape_config.to_mesh());
+        for definition in self.species.iter_mut() {
+            definition.shape_handle = meshes.add(definition.shape.to_mesh());
+        }
+        let mut materials = world.resource_mut::<Assets<ColorMaterial>>();
+        self.grass_material = materials.add(self.grass_color_config.to_color());
+        self.hunting_color = materials.add(self.hunting_color_config.to_color());
+        self.attack_cooling_color = materials.add(self.attack_cooling_color_config.to_color());
+        self.eating_color = materials.add(self.eating_color_config.to_color());
+        self.searching_mate_color = materials.add(self.searching_mate_color_config.to_color());
+        self.mating_color = materials.add(self.mating_color_config.to_color());
+        self.escaping_color = materials.add(self.escaping_color_config.to_color());
+        self.drinking_color = materials.add(self.drinking_color_config.to_color());
+        self.carcass_material = materials.add(self.carcass_color_config.to_color());
+        self.water_material = materials.add(self.water_color_config.to_color());
+        for definition in self.species.iter_mut() {
+            definition.material = materials.add(definition.color.to_color());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_app::{headless_app, run_until};
+
+    fn shipped_config() -> Config {
+        Config::from_file(Path::new(env!("CARGO_MANIFEST_DIR")).join("config.ron"))
+            .expect("the shipped config.ron should parse")
+    }
+
+    // 仓库根目录的 config.ron 与 species.ron 能够解析，规模与内置默认值相同
+    #[test]
+    fn shipped_config_matches_defaults() {
+        let config = shipped_config();
+        let defaults = Config::default();
+        assert_eq!((config.width, config.height), (defaults.width, defaults.height));
+        assert_eq!(config.initial_grass_count, defaults.initial_grass_count);
+        let builtin = default_species();
+        assert_eq!(config.species.len(), builtin.len());
+        for (shipped, builtin) in config.species.iter().zip(builtin.iter()) {
+            assert_eq!(shipped.name, builtin.name);
+            assert_eq!(shipped.initial_count, builtin.initial_count, "{}", shipped.name);
+            assert_eq!(shipped.diet, builtin.diet, "{}", shipped.name);
+            assert_eq!(shipped.predators, builtin.predators, "{}", shipped.name);
+            let numbers = |d: &SpeciesDefinition| [d.health, d.lifespan, d.energy, d.gain, d.damage, d.speed, d.perception_radius];
+            assert_eq!(numbers(shipped), numbers(builtin), "{}", shipped.name);
+        }
+        let counts: Vec<(&str, usize)> = config.species.iter().map(|d| (d.name.as_str(), d.initial_count)).collect();
+        assert_eq!(counts, vec![("Cow", 20), ("Tiger", 2)]);
+    }
+
+    // 用仓库中的配置无窗口运行一小段时间
+    #[test]
+    fn shipped_config_runs_headless() {
+        let config = Config {
+            seed: Some(1),
+            ..shipped_config()
+        };
+        let mut app = headless_app(config);
+        run_until(&mut app, 5.0);
+    }
+
+    #[test]
+    fn rejects_non_positive_calendar_lengths() {
+        assert!(Config::default().validate().is_ok());
+        for (day, year) in [(0.0, 480.0), (60.0, 0.0), (-1.0, 480.0), (f32::NAN, 480.0)] {
+            let config = Config {
+                day_length_secs: day,
+                year_length_secs: year,
+                ..default()
+            };
+            assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))), "day {} year {}", day, year);
+        }
+    }
+
+    #[test]
+    fn rejects_maturity_not_before_lifespan() {
+        let mut species = default_species();
+        species[0].reproduction.as_mut().unwrap().juvenile.maturity_age = species[0].lifespan;
+        let config = Config {
+            species,
+            ..default()
+        };
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+}
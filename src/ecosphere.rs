@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use rand::Rng;
+use crate::aging::{aging_system, Age};
+use crate::animal::{AnimalBundle, AnimalPlugin};
+use crate::calendar::CalendarPlugin;
+use crate::camera_control::camera_control;
+use crate::carcass::CarcassPlugin;
+use crate::config::Config;
+use crate::energy::energy_system;
+use crate::escape_system::EscapeTimer;
+use crate::from_config::FromConfig;
+use crate::genome::{Genome, GenomeMutation};
+use crate::grass::{GrassBundle, GrassPlugin};
+use crate::lifecycle::{count_lifecycle_events, BirthEvent, DeathEvent, LifecycleStatistics};
+use crate::nutrient::NutrientPlugin;
+use crate::movemement::{movement_sync, movement_update};
+use crate::pathfinding::PathfindingPlugin;
+use crate::population_recorder::PopulationRecorderPlugin;
+use crate::sex::Sex;
+use crate::sim_rng::{advance_sim_rng, assign_rng_key, SimRng, SETUP_STREAM};
+use crate::snapshot::SnapshotPlugin;
+use crate::species::SpeciesRegistry;
+use crate::state_display::animal_state_display;
+use crate::terrain::{setup_terrain_display, TerrainMap, TerrainPlugin};
+use crate::water::{setup_water_display, Hydration, WaterPlugin};
+use crate::world_bounds::WorldBounds;
+
+///
+/// 整个生态模拟：公共资源、公共系统，日历、地形、水源、草、配置中声明的各动物物种以及尸体的插件。
+/// 不包含任何渲染相关的系统，需要显示时另外加入 EcosphereDisplayPlugin。
+///
+pub struct EcospherePlugin;
+
+impl Plugin for EcospherePlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding EcospherePlugin");
+        let escape_timer = EscapeTimer::new(config.escape_update_delta_secs);
+        let bounds = WorldBounds::from_config(config);
+        let mutation = GenomeMutation::from_config(config);
+        let registry = SpeciesRegistry::new(&config.species);
+        app.insert_resource(escape_timer)
+            .insert_resource(registry)
+            .insert_resource(bounds)
+            .insert_resource(mutation)
+            // 出生与死亡事件及其统计
+            .add_event::<BirthEvent>()
+            .add_event::<DeathEvent>()
+            .init_resource::<LifecycleStatistics>()
+            .add_systems(FixedLast, count_lifecycle_events)
+            // 配置 Update 系统
+            .add_systems(FixedFirst, advance_sim_rng)
+            .add_observer(assign_rng_key)
+            .add_systems(FixedUpdate,
+                // aging, grass reproduction, energym
+                (aging_system, energy_system).chain())
+            .add_systems(FixedPostUpdate, movement_update)
+            .add_plugins((CalendarPlugin, TerrainPlugin, PathfindingPlugin, WaterPlugin, GrassPlugin, AnimalPlugin, CarcassPlugin, NutrientPlugin, PopulationRecorderPlugin, SnapshotPlugin));
+        // 从快照恢复时，随机数状态与初始实体都由 SnapshotPlugin 恢复
+        let config = app.world().resource::<Config>();
+        if config.load_snapshot.is_none() {
+            let sim_rng = SimRng::from_optional_seed(config.seed);
+            app.insert_resource(sim_rng)
+                // 配置 StartUp 系统
+                .add_systems(Startup, setup);
+        }
+    }
+}
+
+///
+/// 渲染与交互：根据配置创建网格和材质，生成摄像机，同步显示位置与状态颜色。
+/// 需要在 DefaultPlugins 之后加入。
+///
+pub struct EcosphereDisplayPlugin;
+
+impl Plugin for EcosphereDisplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.world_mut().resource_scope(|world, mut config: Mut<Config>| {
+            config.create_handles(world);
+        });
+        app.add_systems(Startup, (setup_camera, setup_terrain_display, setup_water_display))
+            .add_systems(Update, (
+                movement_sync,
+                ))
+            .add_systems(Update, camera_control)
+            .add_systems(Update, animal_state_display);
+    }
+}
+
+fn setup_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2d,
+        Transform::from_xyz(0.0, 0.0, 1.0),
+    ));
+}
+
+// 初始实体在地形上选取位置的最大尝试次数
+const MAX_PLACEMENT_ATTEMPTS: usize = 10000;
+
+// 在区域范围内随机选取一个位置，存在地形图时重新选取直到 accept 接受
+fn random_position(rng: &mut impl Rng, config: &Config, terrain: Option<&TerrainMap>, accept: impl Fn(&TerrainMap, Vec2) -> bool) -> (f32, f32) {
+    for _ in 0..MAX_PLACEMENT_ATTEMPTS {
+        let x = rng.gen::<f32>() * config.width - config.width / 2.0;
+        let y = rng.gen::<f32>() * config.height - config.height / 2.0;
+        if terrain.is_none_or(|terrain| accept(terrain, Vec2::new(x, y))) {
+            return (x, y);
+        }
+    }
+    panic!("Error in random_position, no suitable terrain found after {} attempts", MAX_PLACEMENT_ATTEMPTS);
+}
+
+fn setup(
+    mut commands: Commands,
+    config: Res<Config>,
+    registry: Res<SpeciesRegistry>,
+    sim_rng: Res<SimRng>,
+    mutation: Res<GenomeMutation>,
+    terrain: Option<Res<TerrainMap>>,
+) {
+    let mut rng = sim_rng.stream_with_key(SETUP_STREAM, 0);
+    let terrain = terrain.as_deref();
+    // 在区域范围内随机生成指定数量个草，存在地形图时只生成在肥力大于 0 的地形上
+    for _ in 0..config.initial_grass_count {
+        let (x, y) = random_position(&mut rng, &config, terrain, |terrain, pos| terrain.fertility(pos) > 0.0);
+        commands.spawn(GrassBundle::from_config(&config, x, y));
+    }
+    // 按声明顺序生成各物种的初始个体，初始个体的基因组在物种声明的基础上突变一次，使种群带有差异。
+    // 初始个体已经成年，年龄从性成熟年龄开始；能繁殖的物种带有随机的性别。动物只生成在可通行的地形上
+    for (species, definition) in registry.iter() {
+        for _ in 0..definition.initial_count {
+            let (x, y) = random_position(&mut rng, &config, terrain, |terrain, pos| terrain.is_passable(pos));
+            let genome = Genome::from_definition(definition).mutated(&mutation, &mut rng);
+            let mut animal = commands.spawn(AnimalBundle::new(species, definition, genome, x, y));
+            if let Some(reproduction) = definition.reproduction.as_ref() {
+                animal.insert(Sex::random(reproduction.sex.male_ratio, &mut rng));
+            }
+            animal.insert(Age::with_elapsed(genome.lifespan, definition.maturity_age()));
+            if let Some(thirst) = definition.thirst.as_ref() {
+                animal.insert(Hydration(thirst.capacity));
+            }
+        }
+    }
+}
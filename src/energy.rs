@@ -1,15 +1,39 @@
 use bevy::prelude::*;
+use crate::aging::Age;
+use crate::calendar::SeasonalModifiers;
+use crate::lifecycle::{DeathCause, DeathEvent, Species};
+use crate::movemement::MyPosition;
+use crate::nutrient::SoilNutrients;
 
 #[derive(Component)]
 pub struct Energy(pub f32);
 
-pub fn energy_system(time: Res<Time>, mut query: Query<(Entity, &mut Energy)>, par_commands: ParallelCommands) {
-    query.par_iter_mut().for_each(|(entity, mut energy)| {
-        energy.0 -= 1.0 * time.delta_secs();
+// 删除实体的命令按查询顺序串行发出，保证实体编号的回收顺序可复现。
+// 代谢消耗乘以日历给出的季节与昼夜倍率。
+// 能量不会被扣到负数；存在土壤养分场时，代谢消耗的能量还给所在位置的土壤
+pub fn energy_system(time: Res<Time>,
+                     modifiers: Res<SeasonalModifiers>,
+                     mut query: Query<(Entity, &mut Energy, &Species, &MyPosition, &Age)>,
+                     mut soil: Option<ResMut<SoilNutrients>>,
+                     mut death_events: EventWriter<DeathEvent>,
+                     mut commands: Commands) {
+    query.iter_mut().for_each(|(entity, mut energy, &species, pos, age)| {
+        let cost = (modifiers.metabolism * time.delta_secs()).min(energy.0.max(0.0));
+        energy.0 -= cost;
+        if let Some(soil) = soil.as_mut() {
+            soil.deposit(pos.0, cost);
+        }
         if energy.0 <= 0.0 {
-            par_commands.command_scope(|mut commands| {
-                commands.entity(entity).despawn_recursive();
+            death_events.send(DeathEvent {
+                entity,
+                species,
+                cause: DeathCause::Starvation,
+                position: pos.0,
+                age: age.elapsed_secs(),
+                energy: energy.0,
+                killer: None,
             });
+            commands.entity(entity).despawn_recursive();
         }
     });
 }
\ No newline at end of file
@@ -1,8 +1,14 @@
 use std::time::Duration;
-use crate::spatial_index::SpatialIndex;
+use crate::spatial_index::{SpatialIndex, SpeciesIndex};
 use crate::type_component::TypeComponent;
 use bevy::prelude::*;
+use serde::Deserialize;
+use crate::lifecycle::Species;
+use crate::species::SpeciesRegistry;
 use crate::movemement::{Movement, MyPosition};
+use crate::pathfinding::NavigationGrid;
+use crate::world_bounds::WorldBounds;
+use crate::genome::Genome;
 
 pub enum EscapeState{
     Fleeing,
@@ -13,11 +19,34 @@ pub trait EscapeAgent{
     fn get_state(&self) -> EscapeState;
     fn switch_to_fleeing(&mut self);
     fn switch_to_idle(&mut self);
+    // 正在做分散注意力的事（例如饮水），发现捕食者的距离按物种的配置缩短
+    fn is_distracted(&self) -> bool {
+        false
+    }
 }
-#[derive(Resource)]
-pub struct EscapeConfig<T: EscapeAgent + TypeComponent>{
+///
+/// 逃跑的参数：在 flee_distance 内发现捕食者时逃跑，
+/// 并按权重朝同伴中心与最近的庇护所偏移
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct EscapeConfig{
     pub flee_distance: f32,
-    pub _marker: std::marker::PhantomData<T>,
+    // 朝 flee_distance 内同伴中心偏移的权重
+    pub herd_weight: f32,
+    // 朝最近庇护所偏移的权重
+    pub shelter_weight: f32,
+    pub shelters: Vec<(f32, f32)>,
+}
+impl Default for EscapeConfig{
+    fn default() -> Self{
+        EscapeConfig{
+            flee_distance: 100.0,
+            herd_weight: 0.0,
+            shelter_weight: 0.0,
+            shelters: Vec::new(),
+        }
+    }
 }
 #[derive(Resource, Deref, DerefMut)]
 pub struct EscapeTimer(pub Timer);
@@ -28,36 +57,143 @@ impl EscapeTimer{
         EscapeTimer(Timer::new(Duration::from_secs_f32(update_delta_secs), TimerMode::Repeating))
     }
 }
-// 该状态机过于简单，条件判断和状态机合并进一个系统里
-pub fn escape_from<TP: EscapeAgent + TypeComponent, TH: TypeComponent>(
-    mut query: Query<(&mut Movement, &mut TP, &MyPosition)>,
-    hunter_index: Res<SpatialIndex<TH>>,
-    config: Res<EscapeConfig<TP>>,
+// 与 flee_distance 内所有捕食者的距离加权排斥，越近的捕食者权重越大
+fn repulsion(pos: Vec2, hunters: &[(f32, Vec2)], bounds: &WorldBounds) -> Vec2 {
+    hunters.iter()
+        .map(|&(distance, hunter_pos)| bounds.direction(hunter_pos, pos) / distance.max(1.0))
+        .sum()
+}
+// 朝同伴中心与最近庇护所的偏移
+fn escape_bias<T: TypeComponent>(entity: Entity, pos: Vec2, config: &EscapeConfig, herd_index: &SpatialIndex<T>, bounds: &WorldBounds) -> Vec2 {
+    let mut bias = Vec2::ZERO;
+    if config.herd_weight > 0.0 {
+        let mates = herd_index.get_in_radius(pos, config.flee_distance);
+        let offsets: Vec<Vec2> = mates.iter()
+            .filter(|(_, e)| **e != entity)
+            .filter_map(|(_, e)| herd_index.get_pos(**e))
+            .map(|p| bounds.delta(pos, p))
+            .collect();
+        if !offsets.is_empty() {
+            let center = offsets.iter().sum::<Vec2>() / offsets.len() as f32;
+            bias += center.normalize_or_zero() * config.herd_weight;
+        }
+    }
+    if config.shelter_weight > 0.0 {
+        let nearest = config.shelters.iter()
+            .map(|&(x, y)| Vec2::new(x, y))
+            .min_by(|a, b| bounds.distance(pos, *a).total_cmp(&bounds.distance(pos, *b)));
+        if let Some(shelter) = nearest {
+            bias += bounds.direction(pos, shelter) * config.shelter_weight;
+        }
+    }
+    bias
+}
+type EscapeData<T> = (Entity, &'static mut Movement, &'static mut T, &'static Species, &'static MyPosition, Option<&'static Genome>);
+
+// 该状态机过于简单，条件判断和状态机合并进一个系统里。
+// 只有声明了 flee 的物种会逃跑，躲避的是物种声明中 predators 列出的物种。
+// 逃跑方向为所有范围内捕食者的排斥方向，再按配置朝同伴与庇护所偏移，存在导航网格时再绕开前方的障碍。
+// 饮水时发现捕食者的距离乘以物种的 drinking_vigilance。
+// EscapeTimer 的间隔为 0 时每个固定帧都更新，否则只在计时器到时更新
+pub fn escape_from<T: EscapeAgent + TypeComponent>(
+    mut query: Query<EscapeData<T>>,
+    index: Res<SpeciesIndex<T>>,
+    registry: Res<SpeciesRegistry>,
+    nav: Option<Res<NavigationGrid>>,
+    bounds: Res<WorldBounds>,
     time: Res<Time>,
     mut timer: ResMut<EscapeTimer>
 ){
-    if timer.tick(time.delta()).just_finished() {
-        query.par_iter_mut().for_each(|(mut movement, mut agent, pos)| {
-            let hunter = hunter_index.get_in_radius(pos.0, config.flee_distance);
+    if timer.duration().is_zero() || timer.tick(time.delta()).just_finished() {
+        query.par_iter_mut().for_each(|(entity, mut movement, mut agent, &species, pos, genome)| {
+            let definition = registry.get(species);
+            let Some(config) = definition.flee.as_ref() else {
+                return;
+            };
+            // 带有基因组的个体使用自身的逃跑检测半径
+            let mut flee_distance = genome.map_or(config.flee_distance, |g| g.escape_radius);
+            if agent.is_distracted() {
+                flee_distance *= definition.thirst.as_ref().map_or(1.0, |t| t.drinking_vigilance);
+            }
+            let hunters: Vec<(f32, Vec2)> = definition.predator_species.iter()
+                .filter(|predator| matches!(predator, Species::Animal(_)))
+                .flat_map(|&predator| {
+                    let hunter_index = index.get(predator);
+                    hunter_index.get_in_radius(pos.0, flee_distance).into_iter()
+                        .filter_map(|(distance, hunter)| hunter_index.get_pos(*hunter).map(|p| (distance, p)))
+                })
+                .collect();
             match agent.get_state() {
-                EscapeState::CanFlee => {
-                    if !hunter.is_empty() {
-                        agent.switch_to_fleeing();
-                        let hunter_pos = hunter_index.get_pos(*hunter[0].1).unwrap();
-                        movement.direction = (pos.0 - hunter_pos).normalize();
-                    }
+                EscapeState::CanFlee | EscapeState::Fleeing if !hunters.is_empty() => {
+                    agent.switch_to_fleeing();
+                    let away = repulsion(pos.0, &hunters, &bounds).normalize_or_zero();
+                    let bias = escape_bias(entity, pos.0, config, index.get(species), &bounds);
+                    // 排斥相互抵消时（例如被前后夹击）仍按偏移方向移动
+                    let direction = (away + bias).normalize_or(away);
+                    movement.direction = nav.as_ref().map_or(direction, |nav| nav.avoid(pos.0, direction));
                 }
                 EscapeState::Fleeing => {
-                    if hunter.is_empty() {
-                        agent.switch_to_idle();
-                        movement.direction = Vec2::ZERO;
-                    } else {
-                        let hunter_pos = hunter_index.get_pos(*hunter[0].1).unwrap();
-                        movement.direction = (pos.0 - hunter_pos).normalize();
-                    }
+                    agent.switch_to_idle();
+                    movement.direction = Vec2::ZERO;
                 }
-                EscapeState::CantFlee => {}
+                _ => {}
             }
         });
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animal::{AnimalAgent, AnimalState};
+    use crate::config::Config;
+    use crate::species::SpeciesDefinition;
+    use crate::test_app::{headless_app, place_animals, run_until};
+
+    const COW: Species = Species::Animal(0);
+    const TIGER: Species = Species::Animal(1);
+
+    // 两只不动也不捕食的虎一左一右，夹在中间的牛朝远离两者的方向逃跑
+    #[test]
+    fn flees_away_from_all_nearby_predators() {
+        let cow = SpeciesDefinition {
+            name: "Cow".to_string(),
+            initial_count: 1,
+            energy: 1000.0,
+            lifespan: 1000.0,
+            predators: vec!["Tiger".to_string()],
+            flee: Some(EscapeConfig::default()),
+            ..default()
+        };
+        let tiger = SpeciesDefinition {
+            name: "Tiger".to_string(),
+            initial_count: 2,
+            energy: 1000.0,
+            lifespan: 1000.0,
+            speed: 0.0,
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(13),
+            species: vec![cow, tiger],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        let tigers = [Vec2::new(-60.0, -20.0), Vec2::new(60.0, -20.0)];
+        place_animals(&mut app, TIGER, &tigers);
+        let cow = place_animals(&mut app, COW, &[Vec2::ZERO])[0];
+        app.update();
+        let entity = app.world().entity(cow);
+        assert_eq!(entity.get::<AnimalAgent>().unwrap().state, AnimalState::Fleeing);
+        // 两只虎在左右方向上的排斥相互抵消，合起来指向上方
+        let direction = entity.get::<Movement>().unwrap().direction;
+        assert!(direction.x.abs() < 1e-4 && direction.y > 0.99, "fled towards {}", direction);
+        run_until(&mut app, 2.0);
+        let pos = app.world().get::<MyPosition>(cow).unwrap().0;
+        for tiger in tigers {
+            assert!(pos.distance(tiger) > Vec2::ZERO.distance(tiger) + 20.0, "still close to {} at {}", tiger, pos);
+        }
+    }
+}
@@ -3,4 +3,5 @@ use crate::config::Config;
 
 pub trait FromConfig: Bundle{
     fn from_config(config: &Res<Config>, x: f32, y: f32) -> Self;
-}
\ No newline at end of file
+}
+
@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use crate::species::SpeciesDefinition;
+
+///
+/// 可遗传的性状。出生时决定个体的速度与寿命，
+/// 逃跑、感知与繁殖相关的系统在个体带有 Genome 时使用其中的值代替物种配置。
+///
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Genome {
+    pub speed: f32,
+    // 逃跑检测半径，不会逃跑的物种为 0
+    pub escape_radius: f32,
+    pub perception_radius: f32,
+    // 开始寻找配偶所需的能量阈值
+    pub reproduction_threshold: f32,
+    // 寿命，按秒
+    pub lifespan: f32,
+}
+
+// 性状的名称，顺序与 Genome::traits 一致
+pub const TRAIT_NAMES: [&str; 5] = [
+    "speed",
+    "escape_radius",
+    "perception_radius",
+    "reproduction_threshold",
+    "lifespan",
+];
+
+///
+/// 突变参数：每个性状以 rate 的概率突变，突变时按比例变化，变化幅度在 ±scale 内均匀分布
+///
+#[derive(Resource, Clone, Copy)]
+pub struct GenomeMutation {
+    pub rate: f32,
+    pub scale: f32,
+}
+
+impl GenomeMutation {
+    pub fn from_config(config: &Config) -> Self {
+        GenomeMutation {
+            rate: config.genome_mutation_rate,
+            scale: config.genome_mutation_scale,
+        }
+    }
+}
+
+impl Genome {
+    ///
+    /// 物种声明中的初始性状，不会逃跑或不能繁殖的物种对应的性状为 0
+    ///
+    pub fn from_definition(definition: &SpeciesDefinition) -> Self {
+        Genome {
+            speed: definition.speed,
+            escape_radius: definition.flee.as_ref().map_or(0.0, |f| f.flee_distance),
+            perception_radius: definition.perception_radius,
+            reproduction_threshold: definition.reproduction.as_ref().map_or(0.0, |r| r.energy_threshold),
+            lifespan: definition.lifespan,
+        }
+    }
+    pub fn traits(&self) -> [f32; 5] {
+        [
+            self.speed,
+            self.escape_radius,
+            self.perception_radius,
+            self.reproduction_threshold,
+            self.lifespan,
+        ]
+    }
+    fn from_traits(traits: [f32; 5]) -> Self {
+        Genome {
+            speed: traits[0],
+            escape_radius: traits[1],
+            perception_radius: traits[2],
+            reproduction_threshold: traits[3],
+            lifespan: traits[4],
+        }
+    }
+    ///
+    /// 由双亲的基因组得到后代的基因组：每个性状随机取自父母一方，然后突变
+    ///
+    pub fn inherit(a: &Genome, b: &Genome, mutation: &GenomeMutation, rng: &mut impl Rng) -> Self {
+        let (a, b) = (a.traits(), b.traits());
+        let mut traits = [0.0; 5];
+        for (value, (&x, &y)) in traits.iter_mut().zip(a.iter().zip(b.iter())) {
+            *value = if rng.gen::<bool>() { x } else { y };
+        }
+        Genome::from_traits(traits).mutated(mutation, rng)
+    }
+    pub fn mutated(&self, mutation: &GenomeMutation, rng: &mut impl Rng) -> Self {
+        let mut traits = self.traits();
+        for value in traits.iter_mut() {
+            if rng.gen::<f32>() < mutation.rate {
+                // 按比例变化，性状为 0 时保持为 0
+                *value = (*value * (1.0 + rng.gen_range(-1.0..=1.0) * mutation.scale)).max(0.0);
+            }
+        }
+        Genome::from_traits(traits)
+    }
+}
@@ -1,10 +1,13 @@
 use bevy::prelude::*;
+use crate::lifecycle::Species;
 use crate::health::*;
 use crate::grass_reproduction::*;
 use crate::aging::*;
 use crate::config::*;
 use crate::from_config::FromConfig;
 use crate::movemement::MyPosition;
+use crate::spatial_index::SpatialIndex;
+use crate::world_bounds::WorldBounds;
 use crate::type_component::TypeComponent;
 
 #[derive(Component)]
@@ -15,9 +18,11 @@ impl TypeComponent for Grass {}
 #[derive(Bundle)]
 pub struct GrassBundle {
     pub grass: Grass,
+    pub species: Species,
     pub health: Health,
     pub timer: GrassReproductionTimer,
     pub neighbor_count: GrassNeighborCount,
+    pub growth_bonus: GrassGrowthBonus,
     pub age: Age,
     // 渲染相关
     pub mesh2d: Mesh2d,
@@ -30,9 +35,11 @@ impl FromConfig for GrassBundle {
     fn from_config(config: &Res<Config>, x: f32, y: f32) -> Self {
         GrassBundle {
             grass: Grass,
+            species: Species::Grass,
             health: Health(config.grass_health.clone()),
             timer: GrassReproductionTimer::from_reproduction_delta(config.grass_reproduction_delta),
             neighbor_count: GrassNeighborCount(0),
+            growth_bonus: GrassGrowthBonus::default(),
             age: Age::from_age(config.grass_age),
             mesh2d: Mesh2d(config.grass_shape.clone()),
             mesh_material2d: MeshMaterial2d(config.grass_material.clone()),
@@ -40,4 +47,22 @@ impl FromConfig for GrassBundle {
             my_pos: MyPosition(Vec2::new(x, y)),
         }
     }
-}
\ No newline at end of file
+}
+///
+/// 草：空间索引、繁殖系统以及维护邻居计数的观察者
+///
+pub struct GrassPlugin;
+
+impl Plugin for GrassPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding GrassPlugin");
+        let index = SpatialIndex::<Grass>::new(config.grass_spatial_index, &WorldBounds::from_config(config));
+        app.insert_resource(index)
+            // 草的繁殖系统
+            .add_systems(FixedUpdate, grass_reproduction_system)
+            // grass reproduction
+            .add_observer(on_grass_death)
+            .add_observer(on_grass_birth);
+    }
+}
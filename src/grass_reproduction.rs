@@ -1,9 +1,17 @@
+use std::time::Duration;
 use bevy::prelude::*;
 use crate::grass::*;
+use crate::lifecycle::{BirthEvent, Species};
+use crate::world_bounds::WorldBounds;
+use crate::calendar::SeasonalModifiers;
 use crate::config::*;
 use crate::from_config::FromConfig;
 use crate::movemement::MyPosition;
+use crate::nutrient::SoilNutrients;
 use crate::spatial_index::*;
+use crate::terrain::TerrainMap;
+use crate::sim_rng::{RngKey, SimRng, GRASS_REPRODUCTION_STREAM};
+use rand::Rng;
 // 草的繁殖
 
 // 繁殖间隔计时器
@@ -11,10 +19,16 @@ use crate::spatial_index::*;
 pub struct GrassReproductionTimer(Timer);
 #[derive(Component, Deref, DerefMut)]
 pub struct GrassNeighborCount(pub usize);
+// 本帧额外的生长时间，由附近腐烂的尸体提供，繁殖系统使用后清零
+#[derive(Component, Deref, DerefMut, Default)]
+pub struct GrassGrowthBonus(pub Duration);
 impl GrassReproductionTimer {
     pub fn from_reproduction_delta(delta: f32) -> Self {
         GrassReproductionTimer(Timer::from_seconds(delta, TimerMode::Repeating))
     }
+    pub fn from_timer(timer: Timer) -> Self {
+        GrassReproductionTimer(timer)
+    }
 }
 pub fn on_grass_death(
     trigger: Trigger<OnRemove, GrassNeighborCount>,
@@ -61,23 +75,57 @@ pub fn on_grass_birth(
 // 如果草周边草的数量小于 3，草会以一阶段概率繁殖，
 // 如果草周边草的数量为 3-6，它会以二阶段概率繁殖，
 // 如果草周边草的数量 ≥ 7，草将停止繁殖。
+// 两阶段概率都乘以日历给出的季节与昼夜倍率。
+// 存在地形图时，新草以出生点所在地形的肥力为概率存活，肥力为 0 的地形上不会长草。
+// 每株草使用自己的随机流，生成命令按 RngKey 的顺序串行发出，新草也按这个顺序分配 RngKey，
+// 使结果不依赖查询顺序，从快照恢复后同样可以复现。
+// 存在土壤养分场时，新草需要从出生点的土壤取走 grass_gain 的养分，养分不足则不繁殖。
 pub fn grass_reproduction_system(time: Res<Time>,
                                  config: Res<Config>,
-                                 mut query: Query<(&mut GrassReproductionTimer,
+                                 modifiers: Res<SeasonalModifiers>,
+                                 sim_rng: Res<SimRng>,
+                                 bounds: Res<WorldBounds>,
+                                 terrain: Option<Res<TerrainMap>>,
+                                 mut soil: Option<ResMut<SoilNutrients>>,
+                                 mut query: Query<(&RngKey,
+                                                   &mut GrassReproductionTimer,
+                                                   &mut GrassGrowthBonus,
                                                    &GrassNeighborCount,
                                                    &MyPosition)>,
-                                 par_commands: ParallelCommands,
+                                 mut birth_events: EventWriter<BirthEvent>,
+                                 mut commands: Commands,
 ){
-    query.par_iter_mut().for_each(|(mut timer, count, pos)|{
-        if timer.tick(time.delta()).just_finished(){
-            let seed = rand::random::<f32>();
-            if (count.0 < 3 && seed < config.grass_reproduction_rate_1)
-                || (count.0 >= 3 && count.0 <= 6 && seed < config.grass_reproduction_rate_2){
+    let mut grass: Vec<_> = query.iter_mut().collect();
+    grass.sort_unstable_by_key(|&(&key, ..)| key);
+    grass.into_iter().for_each(|(&key, mut timer, mut bonus, count, pos)|{
+        let delta = time.delta() + std::mem::take(&mut bonus.0);
+        if timer.tick(delta).just_finished(){
+            let mut rng = sim_rng.stream(GRASS_REPRODUCTION_STREAM, key);
+            let seed = rng.gen::<f32>();
+            let factor = modifiers.grass_reproduction;
+            if (count.0 < 3 && seed < config.grass_reproduction_rate_1 * factor)
+                || (count.0 >= 3 && count.0 <= 6 && seed < config.grass_reproduction_rate_2 * factor){
                 // 在生成范围内随机选一个点作为生成坐标
-                let x = pos.x + rand::random::<f32>() * 2.0 * config.grass_reproduction_radius - config.grass_reproduction_radius;
-                let y = pos.y + rand::random::<f32>() * 2.0 * config.grass_reproduction_radius - config.grass_reproduction_radius;
-                par_commands.command_scope(|mut commands|{
-                    commands.spawn(GrassBundle::from_config(&config, x, y));
+                let x = pos.x + rng.gen::<f32>() * 2.0 * config.grass_reproduction_radius - config.grass_reproduction_radius;
+                let y = pos.y + rng.gen::<f32>() * 2.0 * config.grass_reproduction_radius - config.grass_reproduction_radius;
+                // 生成点不能超出区域
+                let (child_pos, _) = bounds.constrain(Vec2::new(x, y));
+                if let Some(terrain) = terrain.as_ref() {
+                    let fertility = terrain.fertility(child_pos);
+                    if fertility <= 0.0 || rng.gen::<f32>() >= fertility {
+                        return;
+                    }
+                }
+                if let Some(soil) = soil.as_mut() {
+                    if !soil.take(child_pos, config.grass_gain) {
+                        return;
+                    }
+                }
+                let child = commands.spawn(GrassBundle::from_config(&config, child_pos.x, child_pos.y)).id();
+                birth_events.send(BirthEvent {
+                    entity: child,
+                    species: Species::Grass,
+                    position: child_pos,
                 });
             }
         }
@@ -0,0 +1,221 @@
+use bevy::ecs::entity::EntityHashMap;
+use bevy::math::{I64Vec2, IVec2, Vec2};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use crate::spatial_index::{sort_and_dedup, sort_by_distance, SpatialBackend};
+use crate::world_bounds::WorldBounds;
+
+///
+/// 均匀网格（空间哈希）后端。实体按所在格子分桶，只有跨格移动时才需要换桶，
+/// 查询只检查与查询范围相交的格子。
+/// 环面区域上格子边长会微调为恰好整除区域大小，格子坐标按格子数取模
+///
+pub struct GridIndex {
+    // 配置的格子边长
+    cell_size: f32,
+    // 实际使用的格子大小与网格原点
+    cell: Vec2,
+    origin: Vec2,
+    // 环面区域及每个方向的格子数；为 None 时网格无边界
+    wrap: Option<(WorldBounds, IVec2)>,
+    cells: HashMap<IVec2, Vec<(Entity, Vec2)>>,
+    entity_map: EntityHashMap<(IVec2, Vec2)>,
+}
+
+impl GridIndex {
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "Grid cell_size must be positive");
+        GridIndex {
+            cell_size,
+            cell: Vec2::splat(cell_size),
+            origin: Vec2::ZERO,
+            wrap: None,
+            cells: HashMap::default(),
+            entity_map: EntityHashMap::default(),
+        }
+    }
+    // 不取模的格子坐标
+    fn raw_cell_of(&self, pos: Vec2) -> IVec2 {
+        ((pos - self.origin) / self.cell).floor().as_ivec2()
+    }
+    fn wrap_cell(&self, cell: IVec2) -> IVec2 {
+        match self.wrap {
+            Some((_, count)) => cell.rem_euclid(count),
+            None => cell,
+        }
+    }
+    fn cell_of(&self, pos: Vec2) -> IVec2 {
+        self.wrap_cell(self.raw_cell_of(pos))
+    }
+    fn distance(&self, a: Vec2, b: Vec2) -> f32 {
+        match self.wrap {
+            Some((bounds, _)) => bounds.distance(a, b),
+            None => a.distance(b),
+        }
+    }
+    // 把 [lo, hi] 范围内的格子坐标映射到实际的格子，环面上范围超过一整圈时取全部格子
+    fn axis_cells(&self, lo: i32, hi: i32, axis: usize) -> Vec<i32> {
+        match self.wrap {
+            Some((_, count)) if hi as i64 - lo as i64 + 1 >= count[axis] as i64 => (0..count[axis]).collect(),
+            Some((_, count)) => (lo..=hi).map(|c| c.rem_euclid(count[axis])).collect(),
+            None => (lo..=hi).collect(),
+        }
+    }
+    // 以查询点所在格子为中心、切比雪夫距离为 ring 的一圈格子
+    fn ring(center: IVec2, ring: i32) -> Vec<IVec2> {
+        if ring == 0 {
+            return vec![center];
+        }
+        let mut cells = Vec::with_capacity(8 * ring as usize);
+        for x in -ring..=ring {
+            cells.push(center + IVec2::new(x, -ring));
+            cells.push(center + IVec2::new(x, ring));
+        }
+        for y in -ring + 1..ring {
+            cells.push(center + IVec2::new(-ring, y));
+            cells.push(center + IVec2::new(ring, y));
+        }
+        cells
+    }
+    // 最近的 num 个实体，按距离从近到远排列。
+    // 从查询点所在格子向外一圈一圈地搜索，尚未搜索的实体到查询点的距离不小于
+    // 查询点到已搜索区域边缘的距离，已找到的第 num 近的距离不超过该值时即可停止
+    fn nearest(&self, pos: Vec2, num: usize) -> Vec<(f32,&Entity)> {
+        let mut result = Vec::new();
+        if self.entity_map.is_empty() || num == 0 {
+            return result;
+        }
+        let center = self.raw_cell_of(pos);
+        let mut visited = 0;
+        let mut ring = 0;
+        loop {
+            for cell in Self::ring(center, ring) {
+                if let Some(bucket) = self.cells.get(&self.wrap_cell(cell)) {
+                    visited += bucket.len();
+                    result.extend(bucket.iter().map(|(e, p)| (self.distance(pos, *p), e)));
+                }
+            }
+            let covered = match self.wrap {
+                // 环面上搜索范围覆盖所有格子后，所有实体都已找到，此后的格子会重复
+                Some((_, count)) => 2 * ring + 1 >= count.max_element(),
+                None => visited >= self.entity_map.len(),
+            };
+            if covered {
+                sort_and_dedup(&mut result);
+                result.truncate(num);
+                break;
+            }
+            // 环面上某个方向的格子数少于搜索宽度时，同一实体会被找到多次，需要去重
+            let repeated = matches!(self.wrap, Some((_, count)) if 2 * ring + 1 > count.min_element());
+            if repeated {
+                sort_and_dedup(&mut result);
+                result.truncate(num);
+            } else if result.len() > num {
+                // 只保留最近的 num 个
+                result.select_nth_unstable_by(num - 1, |a, b| a.0.total_cmp(&b.0).then(a.1.cmp(b.1)));
+                result.truncate(num);
+            }
+            let searched_min = self.origin + (center - IVec2::splat(ring)).as_vec2() * self.cell;
+            let searched_max = self.origin + (center + IVec2::splat(ring + 1)).as_vec2() * self.cell;
+            let to_edge = (pos - searched_min).min(searched_max - pos).min_element();
+            if result.len() == num && result.iter().all(|r| r.0 <= to_edge) {
+                sort_by_distance(&mut result);
+                break;
+            }
+            ring += 1;
+        }
+        result
+    }
+    fn remove_from_cell(&mut self, entity: Entity, cell: IVec2) {
+        let bucket = self.cells.get_mut(&cell).unwrap();
+        let i = bucket.iter().position(|(e, _)| *e == entity).unwrap();
+        bucket.swap_remove(i);
+        if bucket.is_empty() {
+            self.cells.remove(&cell);
+        }
+    }
+}
+
+impl SpatialBackend for GridIndex {
+    fn set_bounds(&mut self, bounds: &WorldBounds) {
+        let (cell, origin, wrap) = if bounds.is_wrapping() {
+            let count = (bounds.size() / self.cell_size).ceil().max(Vec2::ONE).as_ivec2();
+            (bounds.size() / count.as_vec2(), bounds.min, Some((*bounds, count)))
+        } else {
+            (Vec2::splat(self.cell_size), bounds.min, None)
+        };
+        if cell == self.cell && origin == self.origin && wrap.map(|w| w.1) == self.wrap.map(|w| w.1) {
+            self.wrap = wrap;
+            return;
+        }
+        // 网格划分变化时重新分桶
+        self.cell = cell;
+        self.origin = origin;
+        self.wrap = wrap;
+        let entities: Vec<_> = self.entity_map.drain().map(|(e, (_, p))| (e, p)).collect();
+        self.cells.clear();
+        for (entity, pos) in entities {
+            self.insert(entity, pos);
+        }
+    }
+    fn insert(&mut self, entity: Entity, pos: Vec2) {
+        let cell = self.cell_of(pos);
+        self.cells.entry(cell).or_default().push((entity, pos));
+        self.entity_map.insert(entity, (cell, pos));
+    }
+    fn remove(&mut self, entity: Entity) {
+        let (cell, _) = self.entity_map.remove(&entity).unwrap();
+        self.remove_from_cell(entity, cell);
+    }
+    fn update(&mut self, entity: Entity, pos: Vec2) {
+        let (old_cell, _) = self.entity_map[&entity];
+        let cell = self.cell_of(pos);
+        if cell == old_cell {
+            // 仍在原来的格子里，只更新坐标
+            let bucket = self.cells.get_mut(&cell).unwrap();
+            bucket.iter_mut().find(|(e, _)| *e == entity).unwrap().1 = pos;
+            self.entity_map.insert(entity, (cell, pos));
+        } else {
+            self.remove_from_cell(entity, old_cell);
+            self.insert(entity, pos);
+        }
+    }
+    fn get_nearest(&self, pos: Vec2) -> Option<(f32,&Entity)> {
+        self.nearest(pos, 1).first().copied()
+    }
+    fn get_second_nearest(&self, pos: Vec2) -> Option<(f32,&Entity)> {
+        self.nearest(pos, 2).get(1).copied()
+    }
+    fn get_k_nearest(&self, pos: Vec2, k: usize) -> Vec<(f32,&Entity)> {
+        self.nearest(pos, k)
+    }
+    fn get_in_radius(&self, pos: Vec2, radius: f32) -> Vec<(f32,&Entity)> {
+        let lo = self.raw_cell_of(pos - Vec2::splat(radius));
+        let hi = self.raw_cell_of(pos + Vec2::splat(radius));
+        let span = hi.as_i64vec2() - lo.as_i64vec2() + I64Vec2::ONE;
+        let buckets: Vec<&Vec<(Entity, Vec2)>> = if span.x * span.y > self.cells.len() as i64 {
+            // 查询范围内的格子比已占用的格子还多时，直接遍历已占用的格子
+            self.cells.values().collect()
+        } else {
+            let ys = self.axis_cells(lo.y, hi.y, 1);
+            self.axis_cells(lo.x, hi.x, 0).into_iter()
+                .flat_map(|x| ys.iter().map(move |&y| IVec2::new(x, y)))
+                .filter_map(|cell| self.cells.get(&cell))
+                .collect()
+        };
+        let mut result = Vec::new();
+        for bucket in buckets {
+            for (e, p) in bucket.iter() {
+                let distance = self.distance(pos, *p);
+                if distance <= radius {
+                    result.push((distance, e));
+                }
+            }
+        }
+        sort_by_distance(&mut result);
+        result
+    }
+    fn get_pos(&self, entity: Entity) -> Option<Vec2> {
+        self.entity_map.get(&entity).map(|(_, p)| *p)
+    }
+}
@@ -0,0 +1,53 @@
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use crate::config::Config;
+use crate::lifecycle::{DeathCause, LifecycleStatistics, Species};
+use crate::nutrient::NutrientLedger;
+use crate::species::SpeciesRegistry;
+
+///
+/// 无窗口模式：只加载 MinimalPlugins，每次 update 推进一个固定时间步长，
+/// 运行到配置中的模拟时长后退出
+///
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((MinimalPlugins, LogPlugin::default()))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(
+                Time::<Fixed>::default().timestep()))
+            .add_systems(FixedLast, exit_after_duration);
+    }
+}
+
+pub fn exit_after_duration(
+    time: Res<Time<Fixed>>,
+    config: Res<Config>,
+    statistics: Res<LifecycleStatistics>,
+    registry: Res<SpeciesRegistry>,
+    ledger: Option<Res<NutrientLedger>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if time.elapsed_secs() >= config.headless_duration_secs {
+        info!("Headless simulation finished after {} simulated seconds", time.elapsed_secs());
+        let species = std::iter::once(Species::Grass).chain(registry.iter().map(|(species, _)| species));
+        for species in species {
+            info!("{}: {} births, deaths by old age {}, starvation {}, predation {}, dehydration {}",
+                registry.name(species),
+                statistics.births(species),
+                statistics.deaths(species, DeathCause::OldAge),
+                statistics.deaths(species, DeathCause::Starvation),
+                statistics.deaths(species, DeathCause::Predation),
+                statistics.deaths(species, DeathCause::Dehydration));
+        }
+        if let Some(ledger) = ledger {
+            let animals: Vec<String> = registry.iter().zip(ledger.animals.iter())
+                .map(|((_, definition), amount)| format!("{} {:.3}", definition.name, amount))
+                .collect();
+            info!("Nutrients: soil {:.3}, grass {:.3}, {}, carcasses {:.3}, total {:.3}, drift {:.6}",
+                ledger.soil, ledger.grass, animals.join(", "), ledger.carcasses, ledger.total(), ledger.drift());
+        }
+        exit.send(AppExit::Success);
+    }
+}
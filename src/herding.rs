@@ -0,0 +1,235 @@
+use bevy::prelude::*;
+use crate::escape_system::{EscapeAgent, EscapeState};
+use crate::movemement::{Movement, MyPosition};
+use serde::Deserialize;
+use crate::lifecycle::Species;
+use crate::spatial_index::SpeciesIndex;
+use crate::species::SpeciesRegistry;
+use crate::type_component::TypeComponent;
+use crate::world_bounds::WorldBounds;
+
+pub trait HerdAgent{
+    // 处于受群体行为影响的状态
+    fn is_herding(&self) -> bool;
+}
+
+///
+/// 类 boids 的群体行为参数：分离、对齐、聚合三个分量的权重与邻居半径。
+/// vigilance 开启时，邻居在逃跑的个体也会跟着逃跑，即群体警戒
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct HerdingConfig{
+    pub neighbor_radius: f32,
+    pub separation_weight: f32,
+    pub alignment_weight: f32,
+    pub cohesion_weight: f32,
+    pub vigilance: bool,
+}
+impl Default for HerdingConfig{
+    fn default() -> Self{
+        HerdingConfig{
+            neighbor_radius: 60.0,
+            separation_weight: 0.0,
+            alignment_weight: 0.0,
+            cohesion_weight: 0.0,
+            vigilance: false,
+        }
+    }
+}
+impl HerdingConfig{
+    pub fn is_enabled(&self) -> bool{
+        self.separation_weight > 0.0 || self.alignment_weight > 0.0 || self.cohesion_weight > 0.0 || self.vigilance
+    }
+}
+
+#[derive(Deref, DerefMut, Default)]
+pub struct HerdingCache(Vec<(Entity, Vec2, bool)>);
+
+// 应该被放在设置运动方向的各系统之后、movement_update 之前。
+// 先根据本帧其它系统给出的方向与状态算出所有个体的新方向，再统一写回，结果与遍历顺序无关。
+// 只有声明了 herding 且已开启的物种受群体行为影响，邻居只取同物种的个体
+pub fn herding<T: HerdAgent + EscapeAgent + TypeComponent>(
+    mut query: Query<(Entity, &mut T, &Species, &mut Movement, &MyPosition)>,
+    index: Res<SpeciesIndex<T>>,
+    registry: Res<SpeciesRegistry>,
+    bounds: Res<WorldBounds>,
+    mut updates: Local<HerdingCache>,
+){
+    query.iter().for_each(|(entity, agent, &species, movement, pos)| {
+        let Some(config) = registry.get(species).herding.as_ref().filter(|c| c.is_enabled()) else {
+            return;
+        };
+        let fleeing = matches!(agent.get_state(), EscapeState::Fleeing);
+        let alarmed = config.vigilance && matches!(agent.get_state(), EscapeState::CanFlee);
+        if !agent.is_herding() && !alarmed {
+            return;
+        }
+        let (mut separation, mut alignment, mut cohesion) = (Vec2::ZERO, Vec2::ZERO, Vec2::ZERO);
+        let (mut flee_direction, mut neighbors) = (Vec2::ZERO, 0);
+        for &(distance, &neighbor) in index.get(species).get_in_radius(pos.0, config.neighbor_radius).iter() {
+            if neighbor == entity {
+                continue;
+            }
+            let Ok((_, neighbor_agent, _, neighbor_movement, neighbor_pos)) = query.get(neighbor) else {
+                continue;
+            };
+            let delta = bounds.delta(pos.0, neighbor_pos.0);
+            separation -= delta / distance.max(1.0).powi(2);
+            alignment += neighbor_movement.direction;
+            cohesion += delta;
+            neighbors += 1;
+            if matches!(neighbor_agent.get_state(), EscapeState::Fleeing) {
+                flee_direction += neighbor_movement.direction;
+            }
+        }
+        if neighbors == 0 {
+            return;
+        }
+        // 群体警戒：邻居在逃跑时跟着逃跑，方向取逃跑邻居的平均方向
+        if alarmed && flee_direction != Vec2::ZERO {
+            updates.push((entity, flee_direction.normalize(), true));
+            return;
+        }
+        if !agent.is_herding() {
+            return;
+        }
+        let steering = separation.normalize_or_zero() * config.separation_weight
+            + alignment.normalize_or_zero() * config.alignment_weight
+            + cohesion.normalize_or_zero() * config.cohesion_weight;
+        // 逃跑中的个体以逃跑方向为主，不会因为群体行为停下
+        let direction = (movement.direction + steering).normalize_or_zero();
+        let direction = if fleeing && direction == Vec2::ZERO { movement.direction } else { direction };
+        updates.push((entity, direction, false));
+    });
+    for &(entity, direction, alarmed) in updates.iter() {
+        let (_, mut agent, _, mut movement, _) = query.get_mut(entity).unwrap();
+        if alarmed {
+            agent.switch_to_fleeing();
+        }
+        movement.direction = direction;
+    }
+    updates.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animal::{AnimalAgent, AnimalState};
+    use crate::config::Config;
+    use crate::escape_system::EscapeConfig;
+    use crate::species::SpeciesDefinition;
+    use crate::test_app::{headless_app, place_animals, run_until};
+
+    const DEER: Species = Species::Animal(0);
+    const WOLF: Species = Species::Animal(1);
+
+    fn deer(initial_count: usize, herding: Option<HerdingConfig>) -> SpeciesDefinition {
+        SpeciesDefinition {
+            name: "Deer".to_string(),
+            initial_count,
+            energy: 1000.0,
+            predators: vec!["Wolf".to_string()],
+            flee: Some(EscapeConfig {
+                flee_distance: 50.0,
+                ..default()
+            }),
+            herding,
+            ..default()
+        }
+    }
+
+    // 不动也不捕食的狼，只用来吓跑附近的鹿
+    fn wolf() -> SpeciesDefinition {
+        SpeciesDefinition {
+            name: "Wolf".to_string(),
+            initial_count: 1,
+            energy: 1000.0,
+            speed: 0.0,
+            perception_radius: 0.0,
+            ..default()
+        }
+    }
+
+    fn herd_app(herding: Option<HerdingConfig>) -> App {
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(3),
+            species: vec![deer(12, herding), wolf()],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        // 狼放在远处，鹿排成间距 20 的方阵
+        place_animals(&mut app, WOLF, &[Vec2::new(400.0, 400.0)]);
+        let positions: Vec<Vec2> = (0..12).map(|i| Vec2::new((i % 4) as f32, (i / 4) as f32) * 20.0).collect();
+        place_animals(&mut app, DEER, &positions);
+        app
+    }
+
+    // 鹿群到中心的平均距离，以及运动方向的一致程度（单位方向平均值的长度）
+    fn spread_and_polarization(app: &mut App) -> (f32, f32) {
+        let world = app.world_mut();
+        let deer: Vec<(Vec2, Vec2)> = world.query::<(&Species, &MyPosition, &Movement)>()
+            .iter(world)
+            .filter(|(&species, ..)| species == DEER)
+            .map(|(_, pos, movement)| (pos.0, movement.direction.normalize_or_zero()))
+            .collect();
+        let n = deer.len() as f32;
+        let center = deer.iter().map(|(pos, _)| *pos).sum::<Vec2>() / n;
+        let spread = deer.iter().map(|(pos, _)| pos.distance(center)).sum::<f32>() / n;
+        let polarization = (deer.iter().map(|(_, direction)| *direction).sum::<Vec2>() / n).length();
+        (spread, polarization)
+    }
+
+    #[test]
+    fn herd_stays_together_and_aligned() {
+        let config = HerdingConfig {
+            separation_weight: 0.2,
+            alignment_weight: 0.5,
+            cohesion_weight: 0.5,
+            ..default()
+        };
+        let mut herd = herd_app(Some(config));
+        let mut loners = herd_app(None);
+        run_until(&mut herd, 30.0);
+        run_until(&mut loners, 30.0);
+        let (herd_spread, herd_polarization) = spread_and_polarization(&mut herd);
+        let (loner_spread, loner_polarization) = spread_and_polarization(&mut loners);
+        assert!(herd_spread < loner_spread * 0.5, "herd spread {} vs {}", herd_spread, loner_spread);
+        assert!(herd_polarization > loner_polarization + 0.2,
+                "herd polarization {} vs {}", herd_polarization, loner_polarization);
+    }
+
+    // 群体警戒：离狼太远、自己发现不了狼的鹿看到邻居逃跑时也跟着逃跑
+    fn alarmed_by_neighbor(vigilance: bool) -> bool {
+        let config = HerdingConfig {
+            vigilance,
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(3),
+            species: vec![deer(2, Some(config)), wolf()],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        place_animals(&mut app, WOLF, &[Vec2::ZERO]);
+        let deer = place_animals(&mut app, DEER, &[Vec2::new(30.0, 0.0), Vec2::new(80.0, 0.0)]);
+        let mut alarmed = false;
+        for _ in 0..5 {
+            app.update();
+            let agent = |entity: Entity| app.world().get::<AnimalAgent>(entity).unwrap().state;
+            assert_eq!(agent(deer[0]), AnimalState::Fleeing);
+            alarmed |= agent(deer[1]) == AnimalState::Fleeing;
+        }
+        alarmed
+    }
+
+    #[test]
+    fn vigilance_spreads_alarm() {
+        assert!(alarmed_by_neighbor(true));
+        assert!(!alarmed_by_neighbor(false));
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet};
+use bevy::math::Vec2;
+use bevy::prelude::*;
+use crate::spatial_index::{sort_and_dedup, sort_by_distance, SpatialBackend};
+use crate::world_bounds::WorldBounds;
+use kdtree::KdTree;
+use num_traits::Float;
+
+pub fn euclidean<T: Float>(a: &[T], b: &[T]) -> T {
+    debug_assert_eq!(a.len(), b.len());
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| ((*x) - (*y)) * ((*x) - (*y)))
+        .fold(T::zero(), ::std::ops::Add::add)
+        .sqrt()
+}
+
+///
+/// k-d 树后端。实体移动时需要先从树中删除再重新插入
+///
+pub struct KdTreeIndex {
+    kd_tree: KdTree<f32, Entity, [f32;2]>,
+    // 实体插入时的坐标，以及它在树中的键，两者只在坐标重复时不同
+    entity_map: HashMap<Entity, (Vec2, Vec2)>,
+    // 树中已被占用的坐标，见 unique_point
+    occupied: HashSet<(u32, u32)>,
+    // 键被挪动过的实体数
+    nudged: usize,
+    // 环面区域，查询时按环绕距离计算；为 None 时使用普通欧氏距离
+    wrap_bounds: Option<WorldBounds>,
+}
+
+impl SpatialBackend for KdTreeIndex {
+    fn set_bounds(&mut self, bounds: &WorldBounds) {
+        self.wrap_bounds = if bounds.is_wrapping() { Some(*bounds) } else { None };
+    }
+    fn insert(&mut self, entity: Entity, pos: Vec2) {
+        let key = self.unique_point(pos);
+        self.kd_tree.add([key.x, key.y], entity).unwrap();
+        self.occupied.insert((key.x.to_bits(), key.y.to_bits()));
+        if key != pos {
+            self.nudged += 1;
+        }
+        self.entity_map.insert(entity, (pos, key));
+    }
+    fn remove(&mut self, entity: Entity) {
+        let (pos, key) = self.entity_map.remove(&entity).unwrap();
+        if key != pos {
+            self.nudged -= 1;
+        }
+        self.kd_tree.remove(&key.into(), &entity).unwrap();
+        self.occupied.remove(&(key.x.to_bits(), key.y.to_bits()));
+    }
+    fn update(&mut self, entity: Entity, pos: Vec2) {
+        self.remove(entity);
+        self.insert(entity, pos);
+    }
+    fn get_nearest(&self, pos: Vec2) -> Option<(f32,&Entity)> {
+        self.nearest(pos, 1).first().copied()
+    }
+    fn get_second_nearest(&self, pos: Vec2) -> Option<(f32,&Entity)> {
+        self.nearest(pos, 2).get(1).copied()
+    }
+    fn get_k_nearest(&self, pos: Vec2, k: usize) -> Vec<(f32,&Entity)> {
+        self.nearest(pos, k)
+    }
+    fn get_in_radius(&self, pos: Vec2, radius: f32) -> Vec<(f32,&Entity)> {
+        let mut result = Vec::new();
+        for image in self.images(pos, radius) {
+            result.extend(self.kd_tree.within(&[image.x, image.y], radius, &euclidean).unwrap_or_else(|e| {
+                panic!("Error in get_in_radius: {:?}", e);
+            }));
+        }
+        sort_and_dedup(&mut result);
+        self.restore_distances(pos, &mut result);
+        result
+    }
+    fn get_pos(&self, entity: Entity) -> Option<Vec2> {
+        self.entity_map.get(&entity).map(|&(pos, _)| pos)
+    }
+}
+
+impl KdTreeIndex {
+    // kdtree 的 remove 在同一个桶里有坐标完全相同的其它实体时会陷入死循环，
+    // 因此保证树中的坐标互不相同：坐标已被占用时，把 x 向正方向挪动一个最小单位。
+    // 挪动后的坐标只作为树中的键，get_pos 仍返回插入时的坐标
+    fn unique_point(&self, pos: Vec2) -> Vec2 {
+        let mut pos = pos;
+        while self.occupied.contains(&(pos.x.to_bits(), pos.y.to_bits())) {
+            pos.x = next_up(pos.x);
+        }
+        pos
+    }
+    // 最近的 num 个实体，按距离从近到远排列
+    fn nearest(&self, pos: Vec2, num: usize) -> Vec<(f32,&Entity)> {
+        let mut result = self.kd_tree.nearest(&[pos.x, pos.y], num, &euclidean).unwrap_or_else(|e| {
+            panic!("Error in nearest: {:?}", e);
+        });
+        sort_by_distance(&mut result);
+        if let Some(bounds) = self.wrap_bounds {
+            // 环面上，更近的实体可能在区域另一侧。只有当平移后的查询点到区域的距离
+            // 小于当前第 num 近的距离时，该方向上才可能有更近的实体
+            let size = bounds.size();
+            for dx in [-size.x, 0.0, size.x] {
+                for dy in [-size.y, 0.0, size.y] {
+                    if dx == 0.0 && dy == 0.0 {
+                        continue;
+                    }
+                    let image = pos + Vec2::new(dx, dy);
+                    let to_bounds = image.distance(image.clamp(bounds.min, bounds.max));
+                    if result.len() >= num && to_bounds >= result[num - 1].0 {
+                        continue;
+                    }
+                    result.extend(self.kd_tree.nearest(&[image.x, image.y], num, &euclidean).unwrap_or_else(|e| {
+                        panic!("Error in nearest: {:?}", e);
+                    }));
+                    sort_and_dedup(&mut result);
+                    result.truncate(num);
+                }
+            }
+        }
+        self.restore_distances(pos, &mut result);
+        result
+    }
+    // 树中有挪动过的键时，按插入时的坐标重新计算距离并排序
+    fn restore_distances(&self, pos: Vec2, result: &mut [(f32,&Entity)]) {
+        if self.nudged == 0 {
+            return;
+        }
+        for (distance, entity) in result.iter_mut() {
+            let original = self.entity_map[*entity].0;
+            *distance = match self.wrap_bounds {
+                Some(bounds) => bounds.distance(pos, original),
+                None => pos.distance(original),
+            };
+        }
+        sort_by_distance(result);
+    }
+    // 环面上需要额外查询的平移后的查询点。非环面时只有查询点本身
+    fn images(&self, pos: Vec2, radius: f32) -> Vec<Vec2> {
+        let Some(bounds) = self.wrap_bounds else {
+            return vec![pos];
+        };
+        let size = bounds.size();
+        let mut xs = vec![0.0];
+        if pos.x - radius < bounds.min.x { xs.push(size.x); }
+        if pos.x + radius > bounds.max.x { xs.push(-size.x); }
+        let mut ys = vec![0.0];
+        if pos.y - radius < bounds.min.y { ys.push(size.y); }
+        if pos.y + radius > bounds.max.y { ys.push(-size.y); }
+        xs.iter().flat_map(|&dx| ys.iter().map(move |&dy| pos + Vec2::new(dx, dy))).collect()
+    }
+}
+impl Default for KdTreeIndex {
+    fn default() -> Self {
+        KdTreeIndex {
+            kd_tree: KdTree::new(2),
+            entity_map: HashMap::default(),
+            occupied: HashSet::default(),
+            nudged: 0,
+            wrap_bounds: None,
+        }
+    }
+}
+// 比 x 大的最小 f32
+fn next_up(x: f32) -> f32 {
+    if x == 0.0 {
+        f32::from_bits(1)
+    } else if x > 0.0 {
+        f32::from_bits(x.to_bits() + 1)
+    } else {
+        f32::from_bits(x.to_bits() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_bounds::EdgeBehavior;
+
+    // 坐标重复的实体在树中的键被挪动，但位置与距离仍按插入时的坐标给出
+    #[test]
+    fn duplicate_positions_keep_original_coordinates() {
+        let mut index = KdTreeIndex::default();
+        index.set_bounds(&WorldBounds::new(100.0, 100.0, EdgeBehavior::Clamp));
+        let pos = Vec2::new(10.0, -20.0);
+        let entities: Vec<Entity> = (0..3).map(Entity::from_raw).collect();
+        for &entity in entities.iter() {
+            index.insert(entity, pos);
+        }
+        for &entity in entities.iter() {
+            assert_eq!(index.get_pos(entity), Some(pos));
+        }
+        let found = index.get_in_radius(pos, 1.0);
+        assert_eq!(found.iter().map(|&(d, e)| (d, *e)).collect::<Vec<_>>(),
+                   entities.iter().map(|&e| (0.0, e)).collect::<Vec<_>>());
+        index.remove(entities[0]);
+        index.update(entities[1], pos);
+        assert_eq!(index.get_pos(entities[1]), Some(pos));
+        assert_eq!(index.get_nearest(pos), Some((0.0, &entities[1])));
+    }
+}
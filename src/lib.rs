@@ -0,0 +1,50 @@
+//! 封闭生态球模拟。
+//!
+//! 通过 [`EcospherePlugin`] 把整个模拟加入一个 bevy App，
+//! 加入插件前需要先插入 [`config::Config`] 资源。
+
+pub mod grass_reproduction;
+pub mod aging;
+pub mod health;
+pub mod movemement;
+pub mod energy;
+pub mod config;
+pub mod spatial_index;
+pub mod kd_tree_index;
+pub mod grid_index;
+pub mod grass;
+pub mod prey_agent;
+pub mod type_component;
+pub mod reproduction;
+pub mod from_config;
+pub mod camera_control;
+pub mod state_display;
+pub mod escape_system;
+pub mod headless;
+pub mod sim_rng;
+pub mod ecosphere;
+pub mod population_recorder;
+pub mod lifecycle;
+pub mod world_bounds;
+pub mod wander;
+pub mod genome;
+pub mod snapshot;
+pub mod herding;
+pub mod pack_hunting;
+pub mod carcass;
+pub mod nutrient;
+pub mod sex;
+pub mod life_stage;
+pub mod species;
+pub mod animal;
+pub mod calendar;
+pub mod terrain;
+pub mod pathfinding;
+pub mod water;
+pub mod chase;
+#[cfg(test)]
+mod test_app;
+
+pub use ecosphere::{EcosphereDisplayPlugin, EcospherePlugin};
+pub use grass::GrassPlugin;
+pub use animal::AnimalPlugin;
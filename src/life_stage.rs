@@ -0,0 +1,329 @@
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use rand::Rng;
+use serde::Deserialize;
+use crate::aging::Age;
+use crate::animal::AnimalBundle;
+use crate::config::Config;
+use crate::energy::Energy;
+use crate::genome::{Genome, GenomeMutation};
+use crate::health::Health;
+use crate::lifecycle::{BirthEvent, Species};
+use crate::movemement::{Movement, MyPosition};
+use crate::reproduction::ReproductionAgent;
+use crate::sex::Sex;
+use crate::sim_rng::{RngKey, SimRng, REPRODUCTION_STREAM};
+use crate::species::SpeciesRegistry;
+use crate::water::Hydration;
+
+///
+/// 妊娠期的母亲。father 为父亲的基因组，母亲的基因组在分娩时读取；
+/// energy 为封闭的养分循环中留给这一窝后代的能量，其它情况下为 0
+///
+#[derive(Component)]
+pub struct Pregnancy {
+    pub timer: Timer,
+    pub father: Genome,
+    pub energy: f32,
+}
+
+// 幼年个体：速度与生命值降低，不能繁殖，只吃草不捕食其它动物，到达性成熟年龄后成年
+#[derive(Component)]
+pub struct Juvenile;
+
+///
+/// 妊娠参数：妊娠时间，以及每窝后代数量的分布 (数量, 权重)。
+/// 妊娠时间不大于 0 时交配完成即在双亲中间出生
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct GestationConfig{
+    pub gestation_time: f32,
+    pub litter_sizes: Vec<(usize, f32)>,
+}
+impl Default for GestationConfig{
+    fn default() -> Self{
+        GestationConfig{
+            gestation_time: 0.0,
+            litter_sizes: vec![(1, 1.0)],
+        }
+    }
+}
+impl GestationConfig{
+    // 按权重抽取一窝的数量，只有一种取值时不消耗随机数；分布为空时为 1
+    pub fn litter_size(&self, rng: &mut impl Rng) -> usize {
+        match self.litter_sizes.as_slice() {
+            [] => 1,
+            [(size, _)] => *size,
+            sizes => {
+                let total: f32 = sizes.iter().map(|(_, weight)| weight.max(0.0)).sum();
+                let mut pick = rng.gen::<f32>() * total;
+                for &(size, weight) in sizes {
+                    pick -= weight.max(0.0);
+                    if pick < 0.0 {
+                        return size;
+                    }
+                }
+                sizes[sizes.len() - 1].0
+            }
+        }
+    }
+}
+
+///
+/// 幼年参数：性成熟年龄（按秒），以及幼年时速度与生命值相对成年的比例
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct JuvenileConfig{
+    pub maturity_age: f32,
+    pub speed_factor: f32,
+    pub health_factor: f32,
+}
+impl Default for JuvenileConfig{
+    fn default() -> Self{
+        JuvenileConfig{
+            maturity_age: 0.0,
+            speed_factor: 0.5,
+            health_factor: 0.5,
+        }
+    }
+}
+
+///
+/// 生下后代所需的资源：后代由双亲的基因组遗传得到，性别随机，
+/// 性成熟年龄大于 0 时以幼年个体出生
+///
+#[derive(SystemParam)]
+pub struct Offspring<'w, 's> {
+    pub commands: Commands<'w, 's>,
+    pub registry: Res<'w, SpeciesRegistry>,
+    birth_events: EventWriter<'w, BirthEvent>,
+    mutation: Res<'w, GenomeMutation>,
+    pub app_config: Res<'w, Config>,
+}
+
+impl Offspring<'_, '_> {
+    ///
+    /// 在 pos 处生下一窝 species 的后代，该物种需要声明 reproduction。
+    /// 封闭的养分循环中 energy 由这一窝平分，最后一个后代拿走剩余的部分，使各份之和恰好等于 energy
+    ///
+    pub fn spawn_litter(&mut self, parents: (&Genome, &Genome), species: Species,
+                        pos: Vec2, energy: f32, rng: &mut impl Rng) {
+        let definition = self.registry.get(species);
+        let reproduction = definition.reproduction.as_ref()
+            .expect("Only species with reproduction can give birth");
+        let size = reproduction.gestation.litter_size(rng);
+        let share = energy / size.max(1) as f32;
+        for i in 0..size {
+            let genome = Genome::inherit(parents.0, parents.1, &self.mutation, rng);
+            let sex = Sex::random(reproduction.sex.male_ratio, rng);
+            let mut child = self.commands.spawn((AnimalBundle::new(species, definition, genome, pos.x, pos.y), sex));
+            if self.app_config.nutrient_cycle {
+                let energy = if i + 1 == size { energy - share * i as f32 } else { share };
+                child.insert(Energy(energy));
+            }
+            if reproduction.juvenile.maturity_age > 0.0 {
+                child.insert(Juvenile);
+            }
+            if let Some(thirst) = definition.thirst.as_ref() {
+                child.insert(Hydration(thirst.capacity));
+            }
+            let child = child.id();
+            self.birth_events.send(BirthEvent {
+                entity: child,
+                species,
+                position: pos,
+            });
+        }
+    }
+}
+
+type MotherData = (Entity, &'static mut Pregnancy, &'static Genome, &'static MyPosition, &'static Species, &'static RngKey);
+
+// 妊娠期结束时母亲在原地分娩，分娩命令按母亲的 RngKey 的顺序串行发出，使后代的 RngKey 不依赖查询顺序
+pub fn give_birth<T: ReproductionAgent>(
+    mut query: Query<MotherData, With<T>>,
+    time: Res<Time>,
+    sim_rng: Res<SimRng>,
+    mut offspring: Offspring,
+){
+    let mut mothers: Vec<_> = query.iter_mut().collect();
+    mothers.sort_unstable_by_key(|&(.., &key)| key);
+    mothers.into_iter().for_each(|(mother, mut pregnancy, genome, pos, &species, &key)| {
+        if pregnancy.timer.tick(time.delta()).just_finished() {
+            let mut rng = sim_rng.stream(REPRODUCTION_STREAM, key);
+            // 先取走能量，移除 Pregnancy 时就不会再把它还给土壤
+            let energy = std::mem::take(&mut pregnancy.energy);
+            offspring.spawn_litter((genome, &pregnancy.father), species, pos.0, energy, &mut rng);
+            offspring.commands.entity(mother).remove::<Pregnancy>();
+        }
+    });
+}
+
+// 幼年个体出生时按物种声明的比例降低速度与生命值
+pub fn on_juvenile_born<T: ReproductionAgent>(
+    trigger: Trigger<OnAdd, Juvenile>,
+    mut query: Query<(&mut Movement, &mut Health, &Species), With<T>>,
+    registry: Res<SpeciesRegistry>,
+){
+    if let Ok((mut movement, mut health, &species)) = query.get_mut(trigger.entity()) {
+        if let Some(reproduction) = registry.get(species).reproduction.as_ref() {
+            movement.speed *= reproduction.juvenile.speed_factor;
+            health.0 *= reproduction.juvenile.health_factor;
+        }
+    }
+}
+
+type GrowUpData<T> = (Entity, &'static T, &'static Species, &'static Age, &'static Genome, &'static mut Movement, &'static mut Health);
+
+// 到达性成熟年龄的幼年个体成年，速度恢复为基因组中的值，生命值按比例恢复
+pub fn grow_up<T: ReproductionAgent>(
+    mut query: Query<GrowUpData<T>, With<Juvenile>>,
+    registry: Res<SpeciesRegistry>,
+    mut commands: Commands,
+){
+    query.iter_mut().for_each(|(entity, agent, &species, age, genome, mut movement, mut health)| {
+        let definition = registry.get(species);
+        if agent.is_mature(age, definition.maturity_age()) {
+            movement.speed = genome.speed;
+            let health_factor = definition.reproduction.as_ref().map_or(1.0, |r| r.juvenile.health_factor);
+            if health_factor > 0.0 {
+                health.0 /= health_factor;
+            }
+            commands.entity(entity).remove::<Juvenile>();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animal::{AnimalAgent, AnimalState};
+    use crate::energy::Energy;
+    use crate::lifecycle::{DeathCause, LifecycleStatistics};
+    use crate::reproduction::ReproductionConfig;
+    use crate::species::{default_species, SpeciesDefinition};
+    use crate::test_app::{headless_app, place_animals};
+
+    const DEER: Species = Species::Animal(0);
+
+    // 一雄一雌两只鹿，交配 2 秒、妊娠 5 秒后一窝生下两只幼鹿，幼鹿 10 秒后成年。
+    // 繁殖一次后双亲的能量低于阈值，不会再次繁殖
+    #[test]
+    fn pregnancy_gives_birth_to_juveniles_that_grow_up() {
+        let deer = SpeciesDefinition {
+            name: "Deer".to_string(),
+            initial_count: 2,
+            energy: 130.0,
+            health: 40.0,
+            lifespan: 1000.0,
+            reproduction: Some(ReproductionConfig {
+                mating_time: 2.0,
+                gestation: GestationConfig {
+                    gestation_time: 5.0,
+                    litter_sizes: vec![(2, 1.0)],
+                },
+                juvenile: JuvenileConfig {
+                    maturity_age: 10.0,
+                    speed_factor: 0.5,
+                    health_factor: 0.5,
+                },
+                ..default()
+            }),
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(9),
+            species: vec![deer],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        let parents = place_animals(&mut app, DEER, &[Vec2::ZERO, Vec2::new(20.0, 0.0)]);
+        *app.world_mut().get_mut::<Sex>(parents[0]).unwrap() = Sex::Male;
+        *app.world_mut().get_mut::<Sex>(parents[1]).unwrap() = Sex::Female;
+
+        let mut pregnant = false;
+        let mut born_at = None;
+        let mut children = Vec::new();
+        for _ in 0..2000 {
+            app.update();
+            let world = app.world_mut();
+            let elapsed = world.resource::<Time<Fixed>>().elapsed_secs();
+            pregnant |= world.get::<Pregnancy>(parents[1]).is_some();
+            assert!(world.get::<Pregnancy>(parents[0]).is_none(), "only the mother carries the litter");
+            let juveniles: Vec<(Entity, &Movement, &Health, &Genome, &AnimalAgent)> = world
+                .query_filtered::<(Entity, &Movement, &Health, &Genome, &AnimalAgent), With<Juvenile>>()
+                .iter(world)
+                .collect();
+            for &(_, movement, health, genome, agent) in juveniles.iter() {
+                assert_eq!(movement.speed, genome.speed * 0.5);
+                assert!(health.0 <= 20.0);
+                assert!(!matches!(agent.state, AnimalState::SearchingMate | AnimalState::Mating));
+            }
+            if born_at.is_none() && !juveniles.is_empty() {
+                assert!(pregnant, "the litter is born after a pregnancy");
+                assert_eq!(juveniles.len(), 2);
+                assert!(world.get::<Pregnancy>(parents[1]).is_none());
+                born_at = Some(elapsed);
+                children = juveniles.iter().map(|(entity, ..)| *entity).collect();
+            }
+            if born_at.is_some_and(|born| elapsed > born + 10.5) {
+                break;
+            }
+        }
+        // 交配与妊娠都需要时间
+        assert!(born_at.expect("no litter was born") >= 7.0);
+        assert_eq!(app.world_mut().query::<&Species>().iter(app.world()).count(), 4);
+        for child in children {
+            let entity = app.world().entity(child);
+            assert!(!entity.contains::<Juvenile>());
+            assert_eq!(entity.get::<Movement>().unwrap().speed, entity.get::<Genome>().unwrap().speed);
+            assert_eq!(entity.get::<Health>().unwrap().0, 40.0);
+        }
+    }
+
+    // 内置的牛以 60 秒的幼年期出生，出生时只有 50 的能量，幼年时靠吃草活到成年
+    #[test]
+    fn juveniles_graze_until_maturity() {
+        let mut cow = default_species().remove(0);
+        cow.initial_count = 2;
+        cow.predators.clear();
+        cow.reproduction.as_mut().unwrap().juvenile.maturity_age = 60.0;
+        let config = Config {
+            initial_grass_count: 400,
+            seed: Some(6),
+            species: vec![cow],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        let cows = Species::Animal(0);
+        let parents = place_animals(&mut app, cows, &[Vec2::ZERO, Vec2::new(20.0, 0.0)]);
+        for (&parent, sex) in parents.iter().zip([Sex::Male, Sex::Female]) {
+            *app.world_mut().get_mut::<Sex>(parent).unwrap() = sex;
+            app.world_mut().get_mut::<Energy>(parent).unwrap().0 = 200.0;
+        }
+        let mut litter: Vec<Entity> = Vec::new();
+        let mut born_at = 0.0;
+        for _ in 0..20000 {
+            app.update();
+            let world = app.world_mut();
+            let elapsed = world.resource::<Time<Fixed>>().elapsed_secs();
+            if litter.is_empty() {
+                litter = world.query_filtered::<Entity, With<Juvenile>>().iter(world).collect();
+                born_at = elapsed;
+            } else if elapsed > born_at + 61.0 {
+                break;
+            }
+        }
+        assert!(!litter.is_empty(), "no calf was born");
+        assert_eq!(app.world().resource::<LifecycleStatistics>().deaths(cows, DeathCause::Starvation), 0);
+        for calf in litter {
+            let entity = app.world().get_entity(calf).expect("the calf starved before growing up");
+            assert!(!entity.contains::<Juvenile>());
+        }
+    }
+}
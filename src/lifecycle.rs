@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+///
+/// 实体所属的物种。动物物种由物种文件声明，按声明顺序编号，名称与参数见 SpeciesRegistry
+///
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Species {
+    Grass,
+    Animal(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum DeathCause {
+    // 寿命耗尽
+    OldAge,
+    // 能量耗尽
+    Starvation,
+    // 被捕食
+    Predation,
+    // 水分耗尽
+    Dehydration,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct BirthEvent {
+    pub entity: Entity,
+    pub species: Species,
+    pub position: Vec2,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub species: Species,
+    pub cause: DeathCause,
+    pub position: Vec2,
+    // 死亡时的年龄，按秒
+    pub age: f32,
+    // 死亡时剩余的能量，草为 0
+    pub energy: f32,
+    // 被捕食时为捕食者
+    pub killer: Option<Entity>,
+}
+
+///
+/// 出生与各死因的累计计数
+///
+#[derive(Resource, Default)]
+pub struct LifecycleStatistics {
+    births: HashMap<Species, usize>,
+    deaths: HashMap<(Species, DeathCause), usize>,
+}
+
+impl LifecycleStatistics {
+    pub fn births(&self, species: Species) -> usize {
+        self.births.get(&species).copied().unwrap_or(0)
+    }
+    pub fn deaths(&self, species: Species, cause: DeathCause) -> usize {
+        self.deaths.get(&(species, cause)).copied().unwrap_or(0)
+    }
+    // 按物种排序的出生计数，用于保存快照
+    pub fn birth_counts(&self) -> Vec<(Species, usize)> {
+        let mut counts: Vec<_> = self.births.iter().map(|(&s, &c)| (s, c)).collect();
+        counts.sort();
+        counts
+    }
+    // 按物种与死因排序的死亡计数，用于保存快照
+    pub fn death_counts(&self) -> Vec<(Species, DeathCause, usize)> {
+        let mut counts: Vec<_> = self.deaths.iter().map(|(&(s, d), &c)| (s, d, c)).collect();
+        counts.sort();
+        counts
+    }
+    pub fn from_counts(births: &[(Species, usize)], deaths: &[(Species, DeathCause, usize)]) -> Self {
+        LifecycleStatistics {
+            births: births.iter().copied().collect(),
+            deaths: deaths.iter().map(|&(s, d, c)| ((s, d), c)).collect(),
+        }
+    }
+}
+
+// 应该被放在 FixedLast 里，统计本帧内发出的所有出生与死亡事件
+pub fn count_lifecycle_events(
+    mut births: EventReader<BirthEvent>,
+    mut deaths: EventReader<DeathEvent>,
+    mut statistics: ResMut<LifecycleStatistics>,
+) {
+    for birth in births.read() {
+        *statistics.births.entry(birth.species).or_insert(0) += 1;
+    }
+    for death in deaths.read() {
+        *statistics.deaths.entry((death.species, death.cause)).or_insert(0) += 1;
+    }
+}
@@ -1,6 +1,10 @@
 use bevy::prelude::*;
+use crate::calendar::SeasonalModifiers;
 use crate::spatial_index::*;
+use crate::lifecycle::Species;
+use crate::terrain::TerrainMap;
 use crate::type_component::TypeComponent;
+use crate::world_bounds::WorldBounds;
 
 #[derive(Component)]
 pub struct Movement {
@@ -17,11 +21,42 @@ pub struct MovementBundle {
     pub position: MyPosition,
 }
 
+// 动物的速度乘以本物种随季节与昼夜变化的倍率，存在地形图时再除以所在格子的移动代价
+fn effective_speed(movement: &Movement, pos: Vec2, species: Option<&Species>,
+                   modifiers: &SeasonalModifiers, terrain: Option<&TerrainMap>) -> f32 {
+    let speed = movement.speed * species.map_or(1.0, |&s| modifiers.speed(s));
+    match terrain {
+        Some(terrain) => speed / terrain.movement_cost(pos),
+        None => speed,
+    }
+}
+
 // 应该被放在 post fixedupdate 里
-pub fn movement_update(time: Res<Time>, mut query: Query<(&mut MyPosition, &Movement)>) {
-    query.par_iter_mut().for_each(|(mut pos, movement)| {
+// 移动后把位置约束回区域内，反射时运动方向随之反向。
+// 不可通行的格子挡住去路时沿墙滑动，滑不动则停在原地；只有最终位置确实来自边界反射的轴才反向
+pub fn movement_update(time: Res<Time>,
+                       bounds: Res<WorldBounds>,
+                       modifiers: Res<SeasonalModifiers>,
+                       terrain: Option<Res<TerrainMap>>,
+                       mut query: Query<(&mut MyPosition, &mut Movement, Option<&Species>)>) {
+    query.par_iter_mut().for_each(|(mut pos, mut movement, species)| {
         if movement.direction != Vec2::ZERO {
-            pos.0 += movement.direction * movement.speed * time.delta_secs();
+            let speed = effective_speed(&movement, pos.0, species, &modifiers, terrain.as_deref());
+            let (target, flip) = bounds.constrain(pos.0 + movement.direction * speed * time.delta_secs());
+            let new_pos = match terrain.as_ref() {
+                Some(terrain) => match terrain.step(pos.0, target, &bounds) {
+                    Some(new_pos) => new_pos,
+                    None => return,
+                },
+                None => target,
+            };
+            pos.0 = new_pos;
+            if flip.x && new_pos.x == target.x {
+                movement.direction.x = -movement.direction.x;
+            }
+            if flip.y && new_pos.y == target.y {
+                movement.direction.y = -movement.direction.y;
+            }
         }
     });
 }
@@ -31,14 +66,105 @@ pub fn index_update<T: Component + TypeComponent>(query: Query<(Entity, &MyPosit
         index.update(entity, pos.0);
     });
 }
-pub fn movement_sync(time: Res<Time<Fixed>>, mut query: Query<(&mut Transform, &Movement, &MyPosition)>) {
-    query.par_iter_mut().for_each(|(mut xf, movement, pos)| {
+type MovedAnimal = (Entity, &'static Species, &'static MyPosition);
+// 同 index_update，按实体所属的物种更新各自的索引
+pub fn species_index_update<T: Component + TypeComponent>(query: Query<MovedAnimal, (With<T>,Changed<MyPosition>)>, mut index: ResMut<SpeciesIndex<T>>) {
+    query.iter().for_each(|(entity, &species, pos)| {
+        index.get_mut(species).update(entity, pos.0);
+    });
+}
+// 按 movement_update 相同的速度在两个固定帧之间插值显示位置
+pub fn movement_sync(time: Res<Time<Fixed>>,
+                     modifiers: Res<SeasonalModifiers>,
+                     terrain: Option<Res<TerrainMap>>,
+                     mut query: Query<(&mut Transform, &Movement, &MyPosition, Option<&Species>)>) {
+    query.par_iter_mut().for_each(|(mut xf, movement, pos, species)| {
         if xf.translation.x != pos.0.x || xf.translation.y != pos.0.y {
             let a = time.overstep_fraction();
-            let future_position = pos.0 + movement.speed * movement.direction * time.delta_secs();
+            let speed = effective_speed(movement, pos.0, species, &modifiers, terrain.as_deref());
+            let future_position = pos.0 + speed * movement.direction * time.delta_secs();
             let xy = pos.0.lerp(future_position, a);
             xf.translation.x = xy.x;
             xf.translation.y = xy.y;
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+    use super::*;
+    use crate::calendar::SimCalendar;
+    use crate::config::Config;
+    use crate::species::SpeciesRegistry;
+    use crate::terrain::{TerrainConfig, TerrainGrid, TerrainKind};
+    use crate::world_bounds::EdgeBehavior;
+
+    const CELL: f32 = 10.0;
+
+    // 由字符画构造地形，'.' 为草地，'^' 为森林，'#' 为岩石，第一行在最上方，每个格子边长为 CELL
+    fn build_world(rows: &[&str], edge_behavior: EdgeBehavior) -> World {
+        let (width, height) = (rows[0].len(), rows.len());
+        let cells = rows.iter()
+            .flat_map(|row| row.chars().map(|c| match c {
+                '#' => TerrainKind::Rock,
+                '^' => TerrainKind::Forest,
+                _ => TerrainKind::Grass,
+            }))
+            .collect();
+        let bounds = WorldBounds::new(width as f32 * CELL, height as f32 * CELL, edge_behavior);
+        let terrain = TerrainMap::new(TerrainGrid { width, height, cells }, TerrainConfig::default(), &bounds);
+        let config = Config::default();
+        let modifiers = SeasonalModifiers::new(&config, &SpeciesRegistry::new(&[]), &SimCalendar::from_config(&config));
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs(1));
+        // movement_update 并行遍历查询，需要计算任务池
+        ComputeTaskPool::get_or_init(TaskPool::default);
+        let mut world = World::new();
+        world.insert_resource(bounds);
+        world.insert_resource(terrain);
+        world.insert_resource(modifiers);
+        world.insert_resource(time);
+        world
+    }
+
+    // 以 speed 朝 direction 走一秒，返回新的位置与运动方向
+    fn move_once(world: &mut World, pos: Vec2, speed: f32, direction: Vec2) -> (Vec2, Vec2) {
+        let entity = world.spawn((MyPosition(pos), Movement { speed, direction })).id();
+        world.run_system_once(movement_update).unwrap();
+        let entity = world.entity(entity);
+        (entity.get::<MyPosition>().unwrap().0, entity.get::<Movement>().unwrap().direction)
+    }
+
+    #[test]
+    fn reflects_at_open_boundary() {
+        let mut world = build_world(&["....", "...."], EdgeBehavior::Reflect);
+        let (pos, direction) = move_once(&mut world, Vec2::new(-15.0, 0.0), 10.0, Vec2::new(-1.0, 0.0));
+        assert_eq!(pos, Vec2::new(-15.0, 0.0));
+        assert_eq!(direction, Vec2::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn blocked_reflection_keeps_direction() {
+        // 反射回来的位置落在岩石里，沿墙滑动后没有碰到边界，x 方向不反向
+        let mut world = build_world(&["#...", "#..."], EdgeBehavior::Reflect);
+        let (pos, direction) = move_once(&mut world, Vec2::new(-9.0, -5.0), 20.0, Vec2::new(-0.8, 0.6));
+        assert_eq!(pos, Vec2::new(-9.0, 7.0));
+        assert_eq!(direction, Vec2::new(-0.8, 0.6));
+        // 正对着墙时原地不动，方向也不变
+        let (pos, direction) = move_once(&mut world, Vec2::new(-9.0, -5.0), 20.0, Vec2::new(-1.0, 0.0));
+        assert_eq!(pos, Vec2::new(-9.0, -5.0));
+        assert_eq!(direction, Vec2::new(-1.0, 0.0));
+    }
+
+    #[test]
+    fn movement_cost_slows_agents() {
+        let mut world = build_world(&["^^..", "^^.."], EdgeBehavior::Clamp);
+        let (pos, _) = move_once(&mut world, Vec2::new(-15.0, 0.0), 4.0, Vec2::new(1.0, 0.0));
+        assert_eq!(pos, Vec2::new(-13.0, 0.0));
+        let (pos, _) = move_once(&mut world, Vec2::new(5.0, 0.0), 4.0, Vec2::new(1.0, 0.0));
+        assert_eq!(pos, Vec2::new(9.0, 0.0));
+    }
+}
@@ -0,0 +1,281 @@
+use bevy::prelude::*;
+use crate::animal::AnimalAgent;
+use crate::config::Config;
+use crate::carcass::Carcass;
+use crate::energy::Energy;
+use crate::grass::Grass;
+use crate::life_stage::Pregnancy;
+use crate::lifecycle::{DeathCause, DeathEvent, Species};
+use crate::movemement::MyPosition;
+use crate::prey_agent::HunterAgent;
+use crate::species::SpeciesRegistry;
+use crate::world_bounds::WorldBounds;
+
+///
+/// 土壤养分场：把区域划分为边长 cell_size 的格子，每格保存一份养分。
+/// 草繁殖时从出生点所在的格子取走养分，动物的代谢、死亡与尸体的腐烂把养分还给所在的格子
+///
+#[derive(Resource)]
+pub struct SoilNutrients {
+    min: Vec2,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<f64>,
+}
+
+impl SoilNutrients {
+    pub fn new(bounds: &WorldBounds, cell_size: f32, initial: f32) -> Self {
+        let size = bounds.size();
+        let columns = ((size.x / cell_size).ceil() as usize).max(1);
+        let rows = ((size.y / cell_size).ceil() as usize).max(1);
+        SoilNutrients {
+            min: bounds.min,
+            cell_size,
+            columns,
+            rows,
+            cells: vec![initial as f64; columns * rows],
+        }
+    }
+    // 位置所在的格子，区域外的位置归入最近的格子
+    fn cell(&self, pos: Vec2) -> usize {
+        let cell = ((pos - self.min) / self.cell_size).floor();
+        let x = (cell.x.max(0.0) as usize).min(self.columns - 1);
+        let y = (cell.y.max(0.0) as usize).min(self.rows - 1);
+        y * self.columns + x
+    }
+    pub fn get(&self, pos: Vec2) -> f64 {
+        self.cells[self.cell(pos)]
+    }
+    pub fn deposit(&mut self, pos: Vec2, amount: f32) {
+        let cell = self.cell(pos);
+        self.cells[cell] += amount as f64;
+    }
+    ///
+    /// 从位置所在的格子取走 amount 的养分，养分不足时不取并返回 false
+    ///
+    pub fn take(&mut self, pos: Vec2, amount: f32) -> bool {
+        let cell = self.cell(pos);
+        if self.cells[cell] < amount as f64 {
+            return false;
+        }
+        self.cells[cell] -= amount as f64;
+        true
+    }
+    pub fn total(&self) -> f64 {
+        self.cells.iter().fold(0.0, |sum, c| sum + c)
+    }
+    // 按行排列的所有格子，用于保存快照
+    pub fn cells(&self) -> &[f64] {
+        &self.cells
+    }
+    pub fn set_cells(&mut self, cells: &[f64]) {
+        assert_eq!(cells.len(), self.cells.len(), "Soil nutrient grid size does not match the configuration");
+        self.cells.copy_from_slice(cells);
+    }
+}
+
+///
+/// 养分账本：每个固定帧结束时土壤、草、各动物物种与尸体中的养分总量，animals 按物种编号排列。
+/// 动物的养分为能量加上正在进食、尚未消化的部分，以及妊娠中留给后代的部分。
+/// initial_total 为第一次统计时的总量，封闭配置下之后的总量应当与它相等
+///
+#[derive(Resource, Default, Clone, Debug)]
+pub struct NutrientLedger {
+    pub soil: f64,
+    pub grass: f64,
+    pub animals: Vec<f64>,
+    pub carcasses: f64,
+    pub initial_total: Option<f64>,
+}
+
+impl NutrientLedger {
+    pub fn total(&self) -> f64 {
+        self.soil + self.grass + self.animals.iter().fold(0.0, |sum, a| sum + a) + self.carcasses
+    }
+    // 相对第一次统计时总量的偏差
+    pub fn drift(&self) -> f64 {
+        self.initial_total.map_or(0.0, |initial| self.total() - initial)
+    }
+}
+
+///
+/// 封闭的养分循环：土壤养分场、各个去向的养分归还以及养分账本。
+/// 配置中 nutrient_cycle 为 false 时不做任何事，草的繁殖与动物的代谢都不受养分约束
+///
+pub struct NutrientPlugin;
+
+impl Plugin for NutrientPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding NutrientPlugin");
+        if !config.nutrient_cycle {
+            return;
+        }
+        let soil = SoilNutrients::new(&WorldBounds::from_config(config), config.soil_cell_size, config.soil_initial_nutrient);
+        app.insert_resource(soil)
+            .init_resource::<NutrientLedger>()
+            .add_systems(FixedPostUpdate, (
+                return_grass_nutrients,
+                return_interrupted_meals::<AnimalAgent>,
+            ))
+            .add_systems(FixedLast, (update_nutrient_ledger, check_nutrient_conservation).chain())
+            .add_observer(return_undigested_meal::<AnimalAgent>)
+            .add_observer(return_lost_litter);
+    }
+}
+
+// 老死的草把自身的养分还给土壤，被吃掉的草的养分已经交给了捕食者
+pub fn return_grass_nutrients(
+    mut death_events: EventReader<DeathEvent>,
+    mut soil: ResMut<SoilNutrients>,
+    config: Res<Config>,
+) {
+    for event in death_events.read() {
+        if event.species == Species::Grass && event.cause != DeathCause::Predation {
+            soil.deposit(event.position, config.grass_gain);
+        }
+    }
+}
+
+// 进食被打断（例如逃跑）时，没吃完的食物落回土壤
+pub fn return_interrupted_meals<T: Component + HunterAgent>(
+    mut query: Query<(&mut T, &MyPosition)>,
+    mut soil: ResMut<SoilNutrients>,
+) {
+    query.iter_mut().for_each(|(mut agent, pos)| {
+        if !agent.is_eating() && agent.get_last_prey_energy_gain() != 0.0 {
+            soil.deposit(pos.0, agent.take_last_prey_energy_gain());
+        }
+    });
+}
+
+// 动物死亡时正在消化的食物落回土壤，剩余的能量由尸体保留
+pub fn return_undigested_meal<T: Component + HunterAgent>(
+    trigger: Trigger<OnRemove, T>,
+    query: Query<(&T, &MyPosition)>,
+    mut soil: ResMut<SoilNutrients>,
+) {
+    if let Ok((agent, pos)) = query.get(trigger.entity()) {
+        soil.deposit(pos.0, agent.get_last_prey_energy_gain());
+    }
+}
+
+// 妊娠中的母亲死亡时留给后代的能量落回土壤，分娩时这部分能量已经被取走
+pub fn return_lost_litter(
+    trigger: Trigger<OnRemove, Pregnancy>,
+    query: Query<(&Pregnancy, &MyPosition)>,
+    mut soil: ResMut<SoilNutrients>,
+) {
+    if let Ok((pregnancy, pos)) = query.get(trigger.entity()) {
+        soil.deposit(pos.0, pregnancy.energy);
+    }
+}
+
+// 应该被放在 FixedLast 里，此时本帧的增删实体与养分转移均已完成
+pub fn update_nutrient_ledger(
+    mut ledger: ResMut<NutrientLedger>,
+    soil: Res<SoilNutrients>,
+    config: Res<Config>,
+    registry: Res<SpeciesRegistry>,
+    grass_query: Query<(), With<Grass>>,
+    animal_query: Query<(&AnimalAgent, &Species, &Energy, Option<&Pregnancy>)>,
+    carcass_query: Query<&Carcass>,
+) {
+    ledger.soil = soil.total();
+    ledger.grass = grass_query.iter().len() as f64 * config.grass_gain as f64;
+    let mut animals = vec![0.0; registry.len()];
+    animal_query.iter().for_each(|(agent, &species, energy, pregnancy)| {
+        if let Species::Animal(i) = species {
+            animals[i] += animal_nutrients(agent, energy, pregnancy);
+        }
+    });
+    ledger.animals = animals;
+    ledger.carcasses = carcass_query.iter().fold(0.0, |sum, c| sum + c.biomass as f64);
+    if ledger.initial_total.is_none() {
+        ledger.initial_total = Some(ledger.total());
+    }
+}
+
+fn animal_nutrients(agent: &impl HunterAgent, energy: &Energy, pregnancy: Option<&Pregnancy>) -> f64 {
+    energy.0 as f64 + agent.get_last_prey_energy_gain() as f64 + pregnancy.map_or(0.0, |p| p.energy as f64)
+}
+
+// nutrient_conservation_check 为 true 时，总量的相对偏差超过 nutrient_tolerance 即终止模拟
+pub fn check_nutrient_conservation(
+    ledger: Res<NutrientLedger>,
+    config: Res<Config>,
+    time: Res<Time>,
+) {
+    if !config.nutrient_conservation_check {
+        return;
+    }
+    let initial = ledger.initial_total.unwrap_or(0.0);
+    if ledger.drift().abs() > config.nutrient_tolerance as f64 * initial.abs().max(1.0) {
+        panic!("Nutrient conservation violated at {} simulated seconds: total {} differs from initial {} ({:?})",
+            time.elapsed_secs(), ledger.total(), initial, *ledger);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lifecycle::LifecycleStatistics;
+    use crate::species::SpeciesDefinition;
+    use crate::test_app::{headless_app, place_animals};
+
+    const DEER: Species = Species::Animal(0);
+    const WOLF: Species = Species::Animal(1);
+
+    // 吃草的鹿被狼咬死后留下尸体，尸体腐烂后养分回到土壤，整个过程中养分总量不变
+    #[test]
+    fn predation_and_decomposition_conserve_nutrients() {
+        let deer = SpeciesDefinition {
+            name: "Deer".to_string(),
+            initial_count: 3,
+            energy: 200.0,
+            gain: 50.0,
+            lifespan: 1000.0,
+            diet: vec!["Grass".to_string()],
+            ..default()
+        };
+        let wolf = SpeciesDefinition {
+            name: "Wolf".to_string(),
+            initial_count: 1,
+            energy: 200.0,
+            lifespan: 1000.0,
+            damage: 100.0,
+            diet: vec!["Deer".to_string()],
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 200,
+            seed: Some(8),
+            nutrient_cycle: true,
+            carcass_decay_rate: 10.0,
+            species: vec![deer, wolf],
+            ..default()
+        };
+        let tolerance = config.nutrient_tolerance as f64;
+        let mut app = headless_app(config);
+        app.update();
+        place_animals(&mut app, DEER, &[Vec2::new(20.0, 0.0), Vec2::new(300.0, 300.0), Vec2::new(-300.0, 300.0)]);
+        place_animals(&mut app, WOLF, &[Vec2::ZERO]);
+        let (mut max_carcasses, mut final_carcasses) = (0.0, 0.0);
+        while app.world().resource::<Time<Fixed>>().elapsed_secs() < 60.0 {
+            app.update();
+            let ledger = app.world().resource::<NutrientLedger>();
+            let initial = ledger.initial_total.expect("the ledger is filled every fixed frame");
+            assert!(ledger.drift().abs() <= tolerance * initial.abs().max(1.0),
+                    "total {} drifted from {}: {:?}", ledger.total(), initial, ledger);
+            max_carcasses = ledger.carcasses.max(max_carcasses);
+            final_carcasses = ledger.carcasses;
+        }
+        let statistics = app.world().resource::<LifecycleStatistics>();
+        assert!(statistics.deaths(DEER, DeathCause::Predation) >= 1);
+        assert!(statistics.deaths(Species::Grass, DeathCause::Predation) >= 1, "the deer never ate grass");
+        // 尸体出现过，并且已经腐烂了一部分
+        assert!(max_carcasses > 0.0);
+        assert!(final_carcasses < max_carcasses);
+    }
+}
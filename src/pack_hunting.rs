@@ -0,0 +1,309 @@
+use std::f32::consts::TAU;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::Deserialize;
+use crate::calendar::SeasonalModifiers;
+use crate::chase::Pursuit;
+use crate::life_stage::Juvenile;
+use crate::lifecycle::{DeathCause, DeathEvent, Species};
+use crate::movemement::{Movement, MyPosition};
+use crate::pathfinding::NavigationGrid;
+use crate::prey_agent::{HunterAgent, ATTACK_DISTANCE};
+use crate::sim_rng::RngKey;
+use crate::spatial_index::SpeciesIndex;
+use crate::species::SpeciesRegistry;
+use crate::type_component::TypeComponent;
+use crate::world_bounds::WorldBounds;
+
+// 猎物被猎群捕杀后能量的分配方式
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub enum PackSharing {
+    // 在场的成员平分
+    #[default]
+    Equal,
+    // 击杀者先拿走 killer 比例，剩余的由其它在场成员平分；没有其它成员时击杀者独得
+    KillerShare { killer: f32 },
+}
+impl PackSharing {
+    // 返回击杀者与其余每个成员分得的能量，others 为击杀者以外在场成员的数量。
+    // 击杀者拿走分给其它成员后剩下的全部，使各份之和恰好等于 energy_gain
+    pub fn split(&self, energy_gain: f32, others: usize) -> (f32, f32) {
+        if others == 0 {
+            return (energy_gain, 0.0);
+        }
+        let member_share = match *self {
+            PackSharing::Equal => energy_gain / (others + 1) as f32,
+            PackSharing::KillerShare { killer } => energy_gain * (1.0 - killer.clamp(0.0, 1.0)) / others as f32,
+        };
+        (energy_gain - member_share * others as f32, member_share)
+    }
+}
+
+///
+/// 合作捕猎的参数。追捕同一个猎物的同物种个体构成一个猎群，
+/// 空闲个体在 radius 内发现未满 max_size 的猎群时加入，
+/// 成员在距离猎物 flank_distance 之外时从不同方向包抄，
+/// 捕杀后 share_radius 内的成员按 sharing 分配能量
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PackConfig{
+    pub radius: f32,
+    pub max_size: usize,
+    pub flank_distance: f32,
+    pub share_radius: f32,
+    pub sharing: PackSharing,
+}
+impl Default for PackConfig{
+    fn default() -> Self{
+        PackConfig{
+            radius: 150.0,
+            max_size: 3,
+            flank_distance: 60.0,
+            share_radius: 50.0,
+            sharing: PackSharing::Equal,
+        }
+    }
+}
+
+type PackMember = (RngKey, Entity, Vec2);
+
+// 每个猎群（追捕者的物种与猎物）的追捕者数量或位置
+#[derive(Deref, DerefMut, Default)]
+pub struct PackSizeCache(HashMap<(Species, Entity), usize>);
+#[derive(Deref, DerefMut, Default)]
+pub struct PackMembersCache(HashMap<(Species, Entity), Vec<PackMember>>);
+
+type JoinerData<TH> = (Entity, &'static mut TH, &'static Species, &'static MyPosition, Has<Juvenile>, Option<&'static Pursuit>,
+                       &'static RngKey);
+
+// 应该被放在 find_prey 之前，使空闲个体优先加入附近的同物种猎群。只有声明了 pack 的物种合作捕猎。
+// 加入的决定按 RngKey 的顺序依次做出，先加入的个体占用猎群的名额。幼年个体、放弃追捕后休息的个体与当前不捕食的物种不加入猎群
+pub fn join_pack<TH>(mut hunter_query: Query<JoinerData<TH>>,
+                     prey_query: Query<(), With<MyPosition>>,
+                     index: Res<SpeciesIndex<TH>>,
+                     registry: Res<SpeciesRegistry>,
+                     modifiers: Res<SeasonalModifiers>,
+                     mut pack_sizes: Local<PackSizeCache>) where TH: Component + HunterAgent + TypeComponent
+{
+    pack_sizes.clear();
+    hunter_query.iter().for_each(|(_, agent, &species, ..)| {
+        if agent.is_hunting() || agent.is_attack_cooling() {
+            if let Some(prey) = agent.get_prey() {
+                *pack_sizes.entry((species, prey)).or_insert(0) += 1;
+            }
+        }
+    });
+    let mut idle: Vec<(RngKey, Entity, Species, Vec2)> = hunter_query.iter()
+        .filter(|(_, agent, _, _, juvenile, pursuit, _)| agent.is_idle() && !juvenile && !pursuit.is_some_and(|p| p.is_resting()))
+        .map(|(entity, _, &species, pos, _, _, &key)| (key, entity, species, pos.0))
+        .collect();
+    idle.sort_unstable_by_key(|&(key, ..)| key);
+    for (_, entity, species, pos) in idle {
+        let Some(config) = registry.get(species).pack.as_ref() else {
+            continue;
+        };
+        if modifiers.hunting(species) <= 0.0 {
+            continue;
+        }
+        let joined = index.get(species).get_in_radius(pos, config.radius).iter().find_map(|&(_, &other)| {
+            if other == entity {
+                return None;
+            }
+            let (_, agent, ..) = hunter_query.get(other).ok()?;
+            if !agent.is_hunting() {
+                return None;
+            }
+            let prey = agent.get_prey()?;
+            let size = pack_sizes.get(&(species, prey)).copied().unwrap_or(0);
+            (prey_query.contains(prey) && size < config.max_size).then_some(prey)
+        });
+        if let Some(prey) = joined {
+            *pack_sizes.entry((species, prey)).or_insert(0) += 1;
+            hunter_query.get_mut(entity).unwrap().1.switch_to_hunting(prey);
+        }
+    }
+}
+
+// 应该被放在 move_to_prey 之后，覆盖猎群成员的运动方向。
+// 成员按 RngKey 排列，以猎物指向第一个成员的方向为基准，均匀分布在猎物周围 flank_distance 处的包抄点上，
+// 到达包抄点附近后直接扑向猎物。存在导航网格时，看不到包抄点的成员沿 move_to_prey 规划的路径追向猎物
+pub fn flank_prey<TH>(mut hunter_query: Query<(Entity, &TH, &Species, &mut Movement, &MyPosition, &RngKey)>,
+                      prey_query: Query<&MyPosition>,
+                      registry: Res<SpeciesRegistry>,
+                      nav: Option<Res<NavigationGrid>>,
+                      bounds: Res<WorldBounds>,
+                      mut packs: Local<PackMembersCache>) where TH: Component + HunterAgent + TypeComponent
+{
+    packs.clear();
+    hunter_query.iter().for_each(|(entity, agent, &species, _, pos, &key)| {
+        if agent.is_hunting() && registry.get(species).pack.is_some() {
+            if let Some(prey) = agent.get_prey().filter(|prey| prey_query.contains(*prey)) {
+                packs.entry((species, prey)).or_default().push((key, entity, pos.0));
+            }
+        }
+    });
+    for ((species, prey), members) in packs.iter_mut() {
+        if members.len() < 2 {
+            continue;
+        }
+        let config = registry.get(*species).pack.as_ref().unwrap();
+        members.sort_unstable_by_key(|&(key, ..)| key);
+        let prey_pos = prey_query.get(*prey).unwrap().0;
+        let base = bounds.direction(prey_pos, members[0].2);
+        let base = if base == Vec2::ZERO { Vec2::X } else { base };
+        for (i, &(_, entity, pos)) in members.iter().enumerate() {
+            if bounds.distance(pos, prey_pos) <= config.flank_distance + ATTACK_DISTANCE {
+                continue;
+            }
+            let angle = TAU * i as f32 / members.len() as f32;
+            let (flank_point, _) = bounds.constrain(prey_pos + Vec2::from_angle(angle).rotate(base) * config.flank_distance);
+            if nav.as_ref().is_some_and(|nav| !nav.line_of_sight(pos, flank_point)) {
+                continue;
+            }
+            let (_, _, _, mut movement, ..) = hunter_query.get_mut(entity).unwrap();
+            movement.direction = bounds.direction(pos, flank_point);
+        }
+    }
+}
+
+// 应该被放在 attack 之后、move_to_prey 之前，此时同一猎群的其它成员还没有因为猎物消失而回到空闲。
+// 击杀者与 share_radius 内仍在追捕该猎物的同物种成员一起进食，按 sharing 分配击杀者从猎物身上得到的能量
+pub fn share_kill<TH>(mut death_events: EventReader<DeathEvent>,
+                      mut hunter_query: Query<(Entity, &mut TH, &Species, &MyPosition)>,
+                      registry: Res<SpeciesRegistry>,
+                      bounds: Res<WorldBounds>) where TH: Component + HunterAgent + TypeComponent
+{
+    for event in death_events.read() {
+        let Some(killer) = event.killer.filter(|_| event.cause == DeathCause::Predation) else {
+            continue;
+        };
+        let Ok((_, killer_agent, &killer_species, _)) = hunter_query.get(killer) else {
+            continue;
+        };
+        let definition = registry.get(killer_species);
+        let Some(config) = definition.pack.as_ref() else {
+            continue;
+        };
+        let energy_gain = killer_agent.get_last_prey_energy_gain();
+        let members: Vec<Entity> = hunter_query.iter()
+            .filter(|(entity, agent, &species, pos)| {
+                *entity != killer
+                    && species == killer_species
+                    && (agent.is_hunting() || agent.is_attack_cooling())
+                    && agent.get_prey() == Some(event.entity)
+                    && bounds.distance(pos.0, event.position) <= config.share_radius
+            })
+            .map(|(entity, ..)| entity)
+            .collect();
+        let (killer_share, member_share) = config.sharing.split(energy_gain, members.len());
+        hunter_query.get_mut(killer).unwrap().1.switch_to_eating(killer_share, definition.eating_time);
+        for member in members {
+            hunter_query.get_mut(member).unwrap().1.switch_to_eating(member_share, definition.eating_time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animal::{AnimalAgent, AnimalState};
+    use crate::config::Config;
+    use crate::lifecycle::LifecycleStatistics;
+    use crate::species::SpeciesDefinition;
+    use crate::test_app::{headless_app, place_animals};
+
+    const DEER: Species = Species::Animal(0);
+    const WOLF: Species = Species::Animal(1);
+
+    #[test]
+    fn split_conserves_energy() {
+        for sharing in [PackSharing::Equal, PackSharing::KillerShare { killer: 0.6 }, PackSharing::KillerShare { killer: 1.5 }] {
+            for others in 0..5 {
+                let (killer, member) = sharing.split(70.0, others);
+                assert!((killer + member * others as f32 - 70.0).abs() < 1e-4);
+                assert!(killer >= 0.0 && member >= 0.0);
+            }
+        }
+        assert_eq!(PackSharing::Equal.split(60.0, 2), (20.0, 20.0));
+        assert_eq!(PackSharing::Equal.split(60.0, 0), (60.0, 0.0));
+        let (killer, member) = PackSharing::KillerShare { killer: 0.5 }.split(60.0, 2);
+        assert!((killer - 30.0).abs() < 1e-4 && (member - 15.0).abs() < 1e-4);
+    }
+
+    // 一只站着不动、一口就能咬死的鹿，和三只声明了 pack 的狼。
+    // 第一只狼能直接看到鹿，另外两只看不到鹿，但都在第一只狼的猎群半径内
+    fn pack_app(max_size: usize) -> (App, Vec<Entity>) {
+        let deer = SpeciesDefinition {
+            name: "Deer".to_string(),
+            initial_count: 1,
+            energy: 1000.0,
+            health: 10.0,
+            gain: 60.0,
+            speed: 0.0,
+            ..default()
+        };
+        let wolf = SpeciesDefinition {
+            name: "Wolf".to_string(),
+            initial_count: 3,
+            energy: 1000.0,
+            damage: 10.0,
+            perception_radius: 100.0,
+            diet: vec!["Deer".to_string()],
+            pack: Some(PackConfig {
+                max_size,
+                share_radius: 300.0,
+                ..default()
+            }),
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(5),
+            species: vec![deer, wolf],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        place_animals(&mut app, DEER, &[Vec2::ZERO]);
+        let wolves = place_animals(&mut app, WOLF, &[Vec2::new(50.0, 0.0), Vec2::new(150.0, 50.0), Vec2::new(50.0, 140.0)]);
+        (app, wolves)
+    }
+
+    fn states(app: &App, wolves: &[Entity]) -> Vec<AnimalState> {
+        wolves.iter().map(|&wolf| app.world().get::<AnimalAgent>(wolf).unwrap().state).collect()
+    }
+
+    #[test]
+    fn idle_hunters_join_pack_up_to_max_size() {
+        let (mut app, wolves) = pack_app(3);
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(states(&app, &wolves), vec![AnimalState::Hunting; 3]);
+        let (mut app, wolves) = pack_app(2);
+        for _ in 0..3 {
+            app.update();
+        }
+        let hunting = states(&app, &wolves).iter().filter(|&&s| s == AnimalState::Hunting).count();
+        assert_eq!(hunting, 2);
+        assert_eq!(states(&app, &wolves)[0], AnimalState::Hunting);
+    }
+
+    #[test]
+    fn pack_shares_kill() {
+        let (mut app, wolves) = pack_app(3);
+        let mut frames = 0;
+        while app.world().resource::<LifecycleStatistics>().deaths(DEER, DeathCause::Predation) == 0 {
+            app.update();
+            frames += 1;
+            assert!(frames < 2000, "the pack never caught the deer");
+        }
+        // 三只狼同时进食，平分鹿的捕食收获
+        assert_eq!(states(&app, &wolves), vec![AnimalState::Eating; 3]);
+        for &wolf in wolves.iter() {
+            let gain = app.world().get::<AnimalAgent>(wolf).unwrap().get_last_prey_energy_gain();
+            assert!((gain - 20.0).abs() < 1e-4, "wolf gained {}", gain);
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use bevy::prelude::*;
+use crate::config::Config;
+use crate::terrain::TerrainMap;
+use crate::world_bounds::WorldBounds;
+
+// 相邻的八个格子，前四个是上下左右
+const NEIGHBORS: [(i64, i64); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+// 逃跑时探测前方是否通畅的距离，按格子对角线的倍数
+const PROBE_CELLS: f32 = 2.0;
+// 到达路点的距离
+const WAYPOINT_REACHED: f32 = 1.0;
+
+///
+/// 个体缓存的路径。目标离规划时的位置不远时沿用，目标走远了、
+/// 或者个体被推离路径看不到下一个路点时重新规划
+///
+#[derive(Component, Default)]
+pub struct NavPath {
+    // 规划时目标的位置
+    goal: Option<Vec2>,
+    // 目标不可达或超出寻路预算时沿直线前进，直到目标走远了再尝试
+    reachable: bool,
+    // 剩余的路点，倒序存放，最后一个是下一个要去的路点
+    waypoints: Vec<Vec2>,
+}
+impl NavPath {
+    pub fn clear(&mut self) {
+        self.goal = None;
+        self.reachable = false;
+        self.waypoints.clear();
+    }
+}
+
+// A* 的工作区，每个线程一份，在多次寻路之间复用。
+// stamp 与 generation 相同的格子本次寻路访问过，其余格子的 best 视为无穷大，因此不需要每次清空
+#[derive(Default)]
+struct SearchScratch {
+    generation: u32,
+    stamp: Vec<u32>,
+    best: Vec<f32>,
+    came_from: Vec<usize>,
+    open: BinaryHeap<OpenNode>,
+}
+impl SearchScratch {
+    fn begin(&mut self, cells: usize) {
+        if self.stamp.len() != cells || self.generation == u32::MAX {
+            self.stamp = vec![0; cells];
+            self.best = vec![f32::INFINITY; cells];
+            self.came_from = vec![usize::MAX; cells];
+            self.generation = 0;
+        }
+        self.generation += 1;
+        self.open.clear();
+    }
+    fn best(&self, cell: usize) -> f32 {
+        if self.stamp[cell] == self.generation { self.best[cell] } else { f32::INFINITY }
+    }
+    fn set(&mut self, cell: usize, g: f32, from: usize) {
+        self.stamp[cell] = self.generation;
+        self.best[cell] = g;
+        self.came_from[cell] = from;
+    }
+}
+
+thread_local! {
+    static SCRATCH: RefCell<SearchScratch> = RefCell::default();
+}
+
+// A* 开放列表中的节点，按估计总代价从小到大弹出，代价相同时取编号小的，保证结果可复现
+#[derive(PartialEq)]
+struct OpenNode {
+    estimate: f32,
+    cell: usize,
+}
+impl Eq for OpenNode {}
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate).then_with(|| other.cell.cmp(&self.cell))
+    }
+}
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+///
+/// 由地形图生成的导航网格，每个格子记录穿过它的代价，不可通行的格子为无穷大。
+/// 在八邻接的格子上用 A* 寻路，斜向移动时不能擦过不可通行格子的角；
+/// 区域首尾相接时路径也可以穿过边界
+///
+#[derive(Resource)]
+pub struct NavigationGrid {
+    width: usize,
+    height: usize,
+    costs: Vec<f32>,
+    // 可通行格子中最小的代价，使启发函数不会高估
+    min_cost: f32,
+    terrain_cell_size: Vec2,
+    bounds: WorldBounds,
+    cells: Vec<Vec2>,
+    node_budget: usize,
+    replan_distance: f32,
+}
+
+impl NavigationGrid {
+    pub fn new(terrain: &TerrainMap, bounds: &WorldBounds, node_budget: usize, replan_distance: f32) -> Self {
+        let (width, height) = (terrain.width(), terrain.height());
+        let mut costs = Vec::with_capacity(width * height);
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let cell = UVec2::new(x as u32, y as u32);
+                let center = terrain.cell_center(cell);
+                costs.push(if terrain.is_passable(center) { terrain.movement_cost(center) } else { f32::INFINITY });
+                cells.push(center);
+            }
+        }
+        let min_cost = costs.iter().copied().filter(|c| c.is_finite()).fold(f32::INFINITY, f32::min);
+        NavigationGrid {
+            width,
+            height,
+            costs,
+            min_cost: if min_cost.is_finite() { min_cost } else { 1.0 },
+            terrain_cell_size: terrain.cell_size(),
+            bounds: *bounds,
+            cells,
+            node_budget,
+            replan_distance: replan_distance.max(terrain.cell_size().length()),
+        }
+    }
+    fn index(&self, pos: Vec2) -> usize {
+        let rel = ((pos - self.bounds.min) / self.terrain_cell_size).floor();
+        let x = (rel.x as i64).clamp(0, self.width as i64 - 1) as usize;
+        let y = (rel.y as i64).clamp(0, self.height as i64 - 1) as usize;
+        (self.height - 1 - y) * self.width + x
+    }
+    fn is_passable(&self, pos: Vec2) -> bool {
+        self.costs[self.index(pos)].is_finite()
+    }
+    fn neighbor(&self, cell: usize, (dx, dy): (i64, i64)) -> Option<usize> {
+        let (x, y) = ((cell % self.width) as i64 + dx, (cell / self.width) as i64 + dy);
+        let (w, h) = (self.width as i64, self.height as i64);
+        if self.bounds.is_wrapping() {
+            Some((y.rem_euclid(h) * w + x.rem_euclid(w)) as usize)
+        } else if (0..w).contains(&x) && (0..h).contains(&y) {
+            Some((y * w + x) as usize)
+        } else {
+            None
+        }
+    }
+    ///
+    /// 两点之间的直线是否只经过可通行的格子，按半个格子的步长采样
+    ///
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let delta = self.bounds.delta(from, to);
+        let step = self.terrain_cell_size.min_element() / 2.0;
+        let samples = (delta.length() / step).ceil() as usize;
+        (1..=samples).all(|i| {
+            let (pos, _) = self.bounds.constrain(from + delta * (i as f32 / samples as f32));
+            self.is_passable(pos)
+        })
+    }
+    ///
+    /// 用 A* 寻找从 from 到 to 的路径，倒序返回沿途各格子的中心，第一个路点是 to 本身。
+    /// 起点与终点所在的格子即使不可通行也可以进出。找不到路径、或展开的格子超过预算时返回 None
+    ///
+    pub fn find_path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        SCRATCH.with_borrow_mut(|scratch| self.search(scratch, from, to))
+    }
+    fn search(&self, scratch: &mut SearchScratch, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let (start, goal) = (self.index(from), self.index(to));
+        let cost = |cell: usize| {
+            let cost = self.costs[cell];
+            if cost.is_infinite() && (cell == start || cell == goal) { self.min_cost } else { cost }
+        };
+        let heuristic = |cell: usize| self.bounds.distance(self.cells[cell], self.cells[goal]) * self.min_cost;
+        scratch.begin(self.costs.len());
+        scratch.set(start, 0.0, usize::MAX);
+        scratch.open.push(OpenNode { estimate: heuristic(start), cell: start });
+        let mut expanded = 0;
+        while let Some(OpenNode { estimate, cell }) = scratch.open.pop() {
+            if cell == goal {
+                break;
+            }
+            // 已经以更小的代价展开过
+            if estimate > scratch.best(cell) + heuristic(cell) {
+                continue;
+            }
+            expanded += 1;
+            if expanded > self.node_budget {
+                return None;
+            }
+            for (i, &offset) in NEIGHBORS.iter().enumerate() {
+                let Some(next) = self.neighbor(cell, offset) else {
+                    continue;
+                };
+                if cost(next).is_infinite() {
+                    continue;
+                }
+                // 斜向移动时两侧的格子都要可通行
+                if i >= 4 {
+                    let side_x = self.neighbor(cell, (offset.0, 0));
+                    let side_y = self.neighbor(cell, (0, offset.1));
+                    if [side_x, side_y].into_iter().any(|side| side.is_none_or(|side| cost(side).is_infinite())) {
+                        continue;
+                    }
+                }
+                let length = Vec2::new(offset.0 as f32, offset.1 as f32) * self.terrain_cell_size;
+                let g = scratch.best(cell) + length.length() * (cost(cell) + cost(next)) / 2.0;
+                if g < scratch.best(next) {
+                    scratch.set(next, g, cell);
+                    scratch.open.push(OpenNode { estimate: g + heuristic(next), cell: next });
+                }
+            }
+        }
+        if scratch.best(goal).is_infinite() {
+            return None;
+        }
+        let mut waypoints = vec![to];
+        let mut cell = scratch.came_from[goal];
+        while cell != start && cell != usize::MAX {
+            waypoints.push(self.cells[cell]);
+            cell = scratch.came_from[cell];
+        }
+        Some(waypoints)
+    }
+    ///
+    /// 从 from 前往 to 时应当移动的方向。能直接看到目标时走直线，否则沿缓存的路径前进，
+    /// 能看到更远的路点时跳过中间的路点。目标离规划时的位置超过 replan_distance 才重新规划；
+    /// 目标不可达或超出寻路预算时走直线，同样等目标走远了再尝试
+    ///
+    pub fn steer(&self, path: &mut NavPath, from: Vec2, to: Vec2) -> Vec2 {
+        let straight = self.bounds.direction(from, to);
+        if self.line_of_sight(from, to) {
+            path.clear();
+            return straight;
+        }
+        let moved = path.goal.is_none_or(|goal| self.bounds.distance(goal, to) > self.replan_distance);
+        let on_track = path.waypoints.last().is_some_and(|&next| self.line_of_sight(from, next));
+        if moved || (path.reachable && !on_track) {
+            path.goal = Some(to);
+            match self.find_path(from, to) {
+                Some(waypoints) => {
+                    path.reachable = true;
+                    path.waypoints = waypoints;
+                }
+                None => {
+                    path.reachable = false;
+                    path.waypoints.clear();
+                }
+            }
+        } else if let Some(last) = path.waypoints.first_mut() {
+            // 目标没有走远，只更新终点
+            *last = to;
+        }
+        while let Some(&next) = path.waypoints.last() {
+            let skip = match path.waypoints.len() {
+                1 => false,
+                n => self.line_of_sight(from, path.waypoints[n - 2]),
+            };
+            if skip || self.bounds.distance(from, next) < WAYPOINT_REACHED {
+                path.waypoints.pop();
+            } else {
+                break;
+            }
+        }
+        path.waypoints.last().map_or(straight, |&next| self.bounds.direction(from, next))
+    }
+    ///
+    /// 逃跑时绕开障碍：前方不通畅时依次尝试向两侧偏转 45°、90°、135° 的方向，都不通畅时保持原方向
+    ///
+    pub fn avoid(&self, from: Vec2, direction: Vec2) -> Vec2 {
+        let probe = self.terrain_cell_size.length() * PROBE_CELLS;
+        [0.0, 1.0, -1.0, 2.0, -2.0, 3.0, -3.0].into_iter()
+            .map(|k: f32| Vec2::from_angle(k * std::f32::consts::FRAC_PI_4).rotate(direction))
+            .find(|&d| self.line_of_sight(from, from + d * probe))
+            .unwrap_or(direction)
+    }
+}
+
+// 存在导航网格时沿路径前往目标，否则直接朝向目标
+pub fn steer_towards(nav: Option<&NavigationGrid>, path: &mut NavPath, bounds: &WorldBounds, from: Vec2, to: Vec2) -> Vec2 {
+    match nav {
+        Some(nav) => nav.steer(path, from, to),
+        None => bounds.direction(from, to),
+    }
+}
+
+///
+/// 存在地形图且开启了 pathfinding 时插入 NavigationGrid，需要在 TerrainPlugin 之后加入
+///
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding PathfindingPlugin");
+        let bounds = app.world().get_resource::<WorldBounds>()
+            .expect("WorldBounds must be inserted before adding PathfindingPlugin");
+        if !config.pathfinding {
+            return;
+        }
+        if let Some(terrain) = app.world().get_resource::<TerrainMap>() {
+            let nav = NavigationGrid::new(terrain, bounds, config.pathfinding_node_budget, config.pathfinding_replan_distance);
+            app.insert_resource(nav);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::{TerrainConfig, TerrainGrid, TerrainKind};
+    use crate::world_bounds::EdgeBehavior;
+
+    const CELL: f32 = 10.0;
+
+    // 由字符画构造地形，'.' 为草地，'#' 为岩石，第一行在最上方，每个格子边长为 CELL
+    fn build_terrain(rows: &[&str], edge_behavior: EdgeBehavior) -> (TerrainMap, WorldBounds) {
+        let (width, height) = (rows[0].len(), rows.len());
+        let cells = rows.iter()
+            .flat_map(|row| row.chars().map(|c| if c == '#' { TerrainKind::Rock } else { TerrainKind::Grass }))
+            .collect();
+        let bounds = WorldBounds::new(width as f32 * CELL, height as f32 * CELL, edge_behavior);
+        let grid = TerrainGrid { width, height, cells };
+        (TerrainMap::new(grid, TerrainConfig::default(), &bounds), bounds)
+    }
+
+    fn build_nav(rows: &[&str], edge_behavior: EdgeBehavior) -> (NavigationGrid, TerrainMap) {
+        let (terrain, bounds) = build_terrain(rows, edge_behavior);
+        (NavigationGrid::new(&terrain, &bounds, 10000, 0.0), terrain)
+    }
+
+    fn center(terrain: &TerrainMap, x: u32, y: u32) -> Vec2 {
+        terrain.cell_center(UVec2::new(x, y))
+    }
+
+    #[test]
+    fn path_goes_around_wall() {
+        let (nav, terrain) = build_nav(&[
+            "..#..",
+            "..#..",
+            "..#..",
+            "..#..",
+            ".....",
+        ], EdgeBehavior::Clamp);
+        let (from, to) = (center(&terrain, 0, 0), center(&terrain, 4, 0));
+        assert!(!nav.line_of_sight(from, to));
+        let path = nav.find_path(from, to).expect("a path through the gap");
+        assert_eq!(path[0], to);
+        assert!(path.iter().all(|&p| terrain.is_passable(p)));
+        assert!(path.contains(&center(&terrain, 2, 4)));
+    }
+
+    #[test]
+    fn walled_off_goal_is_unreachable() {
+        let (nav, terrain) = build_nav(&[
+            ".....",
+            ".###.",
+            ".#.#.",
+            ".###.",
+            ".....",
+        ], EdgeBehavior::Clamp);
+        assert!(nav.find_path(center(&terrain, 0, 0), center(&terrain, 2, 2)).is_none());
+    }
+
+    #[test]
+    fn node_budget_gives_up() {
+        let (terrain, bounds) = build_terrain(&[
+            ".....",
+            ".###.",
+            ".#.#.",
+            ".###.",
+            ".....",
+        ], EdgeBehavior::Clamp);
+        let nav = NavigationGrid::new(&terrain, &bounds, 2, 0.0);
+        assert!(nav.find_path(center(&terrain, 0, 0), center(&terrain, 4, 4)).is_none());
+    }
+
+    #[test]
+    fn diagonal_step_needs_both_sides_passable() {
+        let (nav, terrain) = build_nav(&[
+            ".#",
+            "#.",
+        ], EdgeBehavior::Clamp);
+        assert!(nav.find_path(center(&terrain, 0, 0), center(&terrain, 1, 1)).is_none());
+
+        let (nav, terrain) = build_nav(&[
+            ".#",
+            "..",
+        ], EdgeBehavior::Clamp);
+        let path = nav.find_path(center(&terrain, 0, 0), center(&terrain, 1, 1)).unwrap();
+        assert!(path.contains(&center(&terrain, 0, 1)));
+    }
+
+    #[test]
+    fn steer_goes_straight_in_line_of_sight() {
+        let (nav, terrain) = build_nav(&[
+            "...",
+            "...",
+            "...",
+        ], EdgeBehavior::Clamp);
+        let (from, to) = (center(&terrain, 0, 0), center(&terrain, 2, 1));
+        let mut path = NavPath::default();
+        assert_eq!(nav.steer(&mut path, from, to), (to - from).normalize());
+        assert!(path.waypoints.is_empty());
+    }
+
+    #[test]
+    fn path_wraps_across_world_edge() {
+        let rows = [
+            "..#..",
+            "..#..",
+            "..#..",
+        ];
+        let (nav, terrain) = build_nav(&rows, EdgeBehavior::Wrap);
+        let (from, to) = (center(&terrain, 1, 1), center(&terrain, 3, 1));
+        let path = nav.find_path(from, to).expect("a path across the seam");
+        assert!(path.iter().all(|&p| terrain.is_passable(p)));
+        // 向左经过最左一列与最右一列绕到墙的另一侧
+        assert!(path.iter().any(|p| terrain.cell(*p).x == 0));
+        assert!(path.iter().any(|p| terrain.cell(*p).x == 4));
+        let mut steer_path = NavPath::default();
+        assert_eq!(nav.steer(&mut steer_path, from, to), Vec2::NEG_X);
+
+        let (nav, terrain) = build_nav(&rows, EdgeBehavior::Clamp);
+        assert!(nav.find_path(center(&terrain, 1, 1), center(&terrain, 3, 1)).is_none());
+    }
+}
@@ -0,0 +1,220 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use bevy::prelude::*;
+use crate::aging::Age;
+use crate::animal::{AnimalAgent, AnimalState};
+use crate::calendar::SimCalendar;
+use crate::carcass::Carcass;
+use crate::config::Config;
+use crate::energy::Energy;
+use crate::genome::{Genome, TRAIT_NAMES};
+use crate::grass::Grass;
+use crate::health::Health;
+use crate::life_stage::{Juvenile, Pregnancy};
+use crate::lifecycle::Species;
+use crate::sex::Sex;
+use crate::species::SpeciesRegistry;
+use crate::water::Hydration;
+
+///
+/// 按模拟时间定时采样种群数据，写入 CSV 文件。
+/// 每行包含各物种数量、各动物物种各状态的数量，各物种的平均能量、生命值与剩余寿命，
+/// 各动物物种每个可遗传性状的均值与标准差，尸体的数量与总生物量，以及各动物物种中雄性所占的比例、幼年与妊娠个体的数量、平均水分（不口渴的物种为 0），最后是采样时的季节与昼夜。
+/// 动物物种的列名以小写的物种名开头，按物种表中的顺序排列
+///
+#[derive(Resource)]
+pub struct PopulationRecorder {
+    writer: BufWriter<File>,
+    timer: Timer,
+}
+
+impl PopulationRecorder {
+    pub fn create(path: &str, interval_secs: f32, registry: &SpeciesRegistry) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", Self::header(registry))?;
+        writer.flush()?;
+        Ok(PopulationRecorder {
+            writer,
+            timer: Timer::from_seconds(interval_secs, TimerMode::Repeating),
+        })
+    }
+    fn header(registry: &SpeciesRegistry) -> String {
+        let animals: Vec<String> = registry.iter().map(|(_, d)| d.name.to_lowercase()).collect();
+        let mut columns = vec![
+            "time".to_string(),
+            "grass_count".to_string(),
+        ];
+        columns.extend(animals.iter().map(|species| format!("{}_count", species)));
+        for species in animals.iter() {
+            columns.extend(AnimalState::ALL.iter().map(|s| format!("{}_{:?}", species, s)));
+        }
+        columns.push("grass_mean_health".to_string());
+        columns.push("grass_mean_remaining_age".to_string());
+        for species in animals.iter() {
+            columns.push(format!("{}_mean_energy", species));
+            columns.push(format!("{}_mean_health", species));
+            columns.push(format!("{}_mean_remaining_age", species));
+        }
+        for species in animals.iter() {
+            for name in TRAIT_NAMES {
+                columns.push(format!("{}_{}_mean", species, name));
+                columns.push(format!("{}_{}_std", species, name));
+            }
+        }
+        columns.push("carcass_count".to_string());
+        columns.push("carcass_biomass".to_string());
+        columns.extend(animals.iter().map(|species| format!("{}_male_ratio", species)));
+        for species in animals.iter() {
+            columns.push(format!("{}_juvenile_count", species));
+            columns.push(format!("{}_pregnant_count", species));
+        }
+        columns.extend(animals.iter().map(|species| format!("{}_mean_hydration", species)));
+        columns.push("season".to_string());
+        columns.push("day_phase".to_string());
+        columns.join(",")
+    }
+}
+
+pub struct PopulationRecorderPlugin;
+
+impl Plugin for PopulationRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding PopulationRecorderPlugin");
+        let registry = app.world().get_resource::<SpeciesRegistry>()
+            .expect("SpeciesRegistry must be inserted before adding PopulationRecorderPlugin");
+        if let Some(path) = config.record_path.clone() {
+            let recorder = PopulationRecorder::create(&path, config.record_interval_secs, registry)
+                .unwrap_or_else(|e| {
+                    panic!("Error creating record file {}: {}", path, e);
+                });
+            app.insert_resource(recorder)
+                .add_systems(FixedLast, record_population);
+        }
+    }
+}
+
+// 对一组值求平均与标准差，没有值时均为 0
+#[derive(Default, Clone, Copy)]
+struct Mean {
+    sum: f32,
+    sum_sq: f32,
+    count: usize,
+}
+impl Mean {
+    fn add(&mut self, value: f32) {
+        self.sum += value;
+        self.sum_sq += value * value;
+        self.count += 1;
+    }
+    fn get(&self) -> f32 {
+        if self.count > 0 {
+            self.sum / self.count as f32
+        } else {
+            0.0
+        }
+    }
+    fn std(&self) -> f32 {
+        if self.count > 0 {
+            let mean = self.get();
+            (self.sum_sq / self.count as f32 - mean * mean).max(0.0).sqrt()
+        } else {
+            0.0
+        }
+    }
+}
+
+// 一个动物物种的统计
+#[derive(Default)]
+struct AnimalStatistics {
+    count: usize,
+    // 状态按声明顺序计数，与 ALL 的顺序一致
+    states: [usize; AnimalState::ALL.len()],
+    energy: Mean,
+    health: Mean,
+    age: Mean,
+    traits: [Mean; TRAIT_NAMES.len()],
+    // 雄性计 1、雌性计 0 的平均值即雄性比例，没有性别的个体不参与统计
+    males: Mean,
+    juveniles: usize,
+    pregnant: usize,
+    hydration: Mean,
+}
+
+type AnimalRecord = (&'static AnimalAgent, &'static Species, &'static Energy, &'static Health, &'static Age, &'static Genome,
+                     Option<&'static Sex>, Has<Juvenile>, Has<Pregnancy>, Option<&'static Hydration>);
+
+// 应该被放在 FixedLast 里，此时本帧的移动与增删实体均已完成
+pub fn record_population(
+    time: Res<Time>,
+    mut recorder: ResMut<PopulationRecorder>,
+    registry: Res<SpeciesRegistry>,
+    calendar: Res<SimCalendar>,
+    grass_query: Query<(&Health, &Age), With<Grass>>,
+    animal_query: Query<AnimalRecord>,
+    carcass_query: Query<&Carcass>,
+) {
+    if !recorder.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let (mut grass_health, mut grass_age) = (Mean::default(), Mean::default());
+    grass_query.iter().for_each(|(health, age)| {
+        grass_health.add(health.0);
+        grass_age.add(age.remaining_secs());
+    });
+    let mut animals: Vec<AnimalStatistics> = registry.iter().map(|_| AnimalStatistics::default()).collect();
+    animal_query.iter().for_each(|(agent, &species, energy, health, age, genome, sex, juvenile, pregnant, hydration)| {
+        let Species::Animal(i) = species else {
+            return;
+        };
+        let statistics = &mut animals[i];
+        statistics.count += 1;
+        statistics.states[agent.state as usize] += 1;
+        statistics.traits.iter_mut().zip(genome.traits()).for_each(|(mean, value)| mean.add(value));
+        statistics.energy.add(energy.0);
+        statistics.health.add(health.0);
+        statistics.age.add(age.remaining_secs());
+        if let Some(&sex) = sex {
+            statistics.males.add(if sex == Sex::Male { 1.0 } else { 0.0 });
+        }
+        statistics.juveniles += juvenile as usize;
+        statistics.pregnant += pregnant as usize;
+        if let Some(hydration) = hydration {
+            statistics.hydration.add(hydration.0);
+        }
+    });
+    let mut row = vec![
+        time.elapsed_secs().to_string(),
+        grass_query.iter().len().to_string(),
+    ];
+    row.extend(animals.iter().map(|a| a.count.to_string()));
+    for statistics in animals.iter() {
+        row.extend(statistics.states.iter().map(|c| c.to_string()));
+    }
+    row.push(grass_health.get().to_string());
+    row.push(grass_age.get().to_string());
+    for statistics in animals.iter() {
+        for mean in [statistics.energy, statistics.health, statistics.age] {
+            row.push(mean.get().to_string());
+        }
+    }
+    for mean in animals.iter().flat_map(|a| a.traits.iter()) {
+        row.push(mean.get().to_string());
+        row.push(mean.std().to_string());
+    }
+    row.push(carcass_query.iter().len().to_string());
+    row.push(carcass_query.iter().fold(0.0, |sum, c| sum + c.biomass).to_string());
+    row.extend(animals.iter().map(|a| a.males.get().to_string()));
+    for statistics in animals.iter() {
+        row.push(statistics.juveniles.to_string());
+        row.push(statistics.pregnant.to_string());
+    }
+    row.extend(animals.iter().map(|a| a.hydration.get().to_string()));
+    row.push(format!("{:?}", calendar.season()));
+    row.push(format!("{:?}", calendar.phase()));
+    let result = writeln!(recorder.writer, "{}", row.join(","))
+        .and_then(|_| recorder.writer.flush());
+    if let Err(e) = result {
+        error!("Error in record_population, failed to write record: {}", e);
+    }
+}
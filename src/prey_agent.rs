@@ -1,12 +1,28 @@
-use std::collections::HashSet;
 use bevy::prelude::*;
+use bevy::ecs::entity::EntityHashSet;
+use bevy::utils::HashSet;
+use rand::Rng;
+use crate::aging::Age;
+use crate::calendar::SeasonalModifiers;
+use crate::chase::Pursuit;
+use crate::config::Config;
 use crate::energy::Energy;
+use crate::escape_system::{EscapeAgent, EscapeState};
+use crate::life_stage::Juvenile;
+use crate::lifecycle::{DeathCause, DeathEvent, Species};
 use crate::health::Health;
 use crate::movemement::{Movement, MyPosition};
-use crate::spatial_index::SpatialIndex;
+use crate::pathfinding::{steer_towards, NavPath, NavigationGrid};
+use crate::grass::Grass;
+use crate::spatial_index::{SpatialIndex, SpeciesIndex};
+use crate::species::SpeciesRegistry;
 use crate::type_component::TypeComponent;
+use crate::world_bounds::WorldBounds;
+use crate::sim_rng::{RngKey, SimRng, HUNTING_STREAM, WANDER_STREAM};
+use crate::wander::Wander;
+use crate::genome::Genome;
 
-const ATTACK_DISTANCE: f32 = 10.0;
+pub const ATTACK_DISTANCE: f32 = 10.0;
 
 pub trait HunterAgent
 {
@@ -20,79 +36,23 @@ pub trait HunterAgent
     fn switch_to_attack_cooling(&mut self, cooling_time: f32);
     fn switch_to_eating(&mut self, energy_gain: f32, eating_time: f32);
     fn get_last_prey_energy_gain(&self) -> f32;
+    // 取出正在消化的食物的能量并清零
+    fn take_last_prey_energy_gain(&mut self) -> f32;
     fn get_prey(&self) -> Option<Entity>;
     fn get_attack_cooling_timer(&mut self) -> &mut Timer;
     fn get_eating_timer(&mut self) -> &mut Timer;
 }
-#[derive(Resource)]
-pub struct Damage<T: TypeComponent>{
-    pub damage: f32,
-    _marker: std::marker::PhantomData<T>,
-}
-impl<T> Damage<T> where T: TypeComponent
-{
-    pub fn new(damage: f32) -> Self
-    {
-        Damage{
-            damage,
-            _marker: std::marker::PhantomData
-        }
-    }
-}
-#[derive(Resource)]
-pub struct EnergyGain<T: TypeComponent>{
-    pub energy_gain: f32,
-    _marker: std::marker::PhantomData<T>,
-}
-impl<T> EnergyGain<T> where T: TypeComponent
-{
-    pub fn new(energy_gain: f32) -> Self
-    {
-        EnergyGain{
-            energy_gain,
-            _marker: std::marker::PhantomData
-        }
-    }
-}
-#[derive(Resource)]
-pub struct AttackCoolingTime<T: TypeComponent>{
-    pub time: f32,
-    _marker: std::marker::PhantomData<T>,
-}
-impl<T> AttackCoolingTime<T> where T: TypeComponent
+pub fn move_to_prey<TH>(mut hunter_query: Query<(&mut TH, &mut Movement, &mut NavPath, &MyPosition)>,
+                        prey_query: Query<&MyPosition, With<Species>>,
+                        nav: Option<Res<NavigationGrid>>,
+                        bounds: Res<WorldBounds>) where TH: Component + HunterAgent
 {
-    pub fn new(time: f32) -> Self
-    {
-        AttackCoolingTime{
-            time,
-            _marker: std::marker::PhantomData
-        }
-    }
-}
-#[derive(Resource)]
-pub struct EatingTime<T: TypeComponent>{
-    pub time: f32,
-    _marker: std::marker::PhantomData<T>,
-}
-impl<T> EatingTime<T> where T: TypeComponent
-{
-    pub fn new(time: f32) -> Self
-    {
-        EatingTime{
-            time,
-            _marker: std::marker::PhantomData
-        }
-    }
-}
-pub fn move_to_prey<TH,TP>(mut hunter_query: Query<(&mut TH, &mut Movement, &MyPosition)>,
-                           prey_query: Query<(&TP, &MyPosition)>) where TH: Component + HunterAgent, TP: Component + TypeComponent
-{
-    hunter_query.par_iter_mut().for_each(|(mut hunter_agent, mut movement, hunter_pos)| {
+    hunter_query.par_iter_mut().for_each(|(mut hunter_agent, mut movement, mut path, hunter_pos)| {
         if hunter_agent.is_hunting()
         {
-            if let Ok((_, prey_pos)) = prey_query.get(hunter_agent.get_prey().unwrap())
+            if let Ok(prey_pos) = prey_query.get(hunter_agent.get_prey().unwrap())
             {
-                movement.direction = (prey_pos.0 - hunter_pos.0).normalize();
+                movement.direction = steer_towards(nav.as_deref(), &mut path, &bounds, hunter_pos.0, prey_pos.0);
             }
             else
             {
@@ -133,57 +93,157 @@ pub fn on_eating<TH>(mut hunter_query: Query<(&mut TH, &mut Energy, &mut Movemen
             timer.tick(time.delta());
             if timer.just_finished()
             {
-                energy.0 += hunter_agent.get_last_prey_energy_gain();
+                energy.0 += hunter_agent.take_last_prey_energy_gain();
                 hunter_agent.switch_to_idle();
             }
         }
     });
 }
 
-pub fn find_prey<TH,TP>(mut hunter_query:Query<(&mut TH, &MyPosition, &mut Movement)>,
-                        index: Res<SpatialIndex<TP>>) where TH: Component + HunterAgent, TP: Component + TypeComponent
+type SeekerData<TH> = (Entity, &'static RngKey, &'static mut TH, &'static Species, &'static MyPosition, &'static mut Movement,
+                       &'static mut Wander, Option<&'static Genome>, Has<Juvenile>, Option<&'static Pursuit>);
+
+// 空闲时在食谱中的各物种里寻找感知范围内最近的可捕食猎物，找不到则闲逛。
+// 跳过已被同物种其它个体追捕的猎物，以及在赶到之前就会老死的猎物。
+// 空闲个体按 RngKey 的顺序依次选择，先选中的猎物对之后的同物种个体同样算作已被追捕，同一帧里也不会两个个体追同一个猎物。
+// 幼年个体只吃草，不捕食其它动物。
+// 感知半径乘以日历给出的本物种捕食活跃度，活跃度不大于 0 时只闲逛，放弃追捕后休息的个体也只闲逛
+pub fn find_prey<TH>(mut hunter_query:Query<SeekerData<TH>>,
+                     prey_query: Query<(&Age, &MyPosition)>,
+                     grass_index: Res<SpatialIndex<Grass>>,
+                     animal_index: Res<SpeciesIndex<TH>>,
+                     registry: Res<SpeciesRegistry>,
+                     modifiers: Res<SeasonalModifiers>,
+                     bounds: Res<WorldBounds>,
+                     sim_rng: Res<SimRng>,
+                     time: Res<Time>) where TH: Component + HunterAgent + TypeComponent
 {
-    hunter_query.par_iter_mut().for_each(|(mut hunter_agent, hunter_pos, mut movement)| {
-        if hunter_agent.is_idle()
-        {
-            // 重置速度
-            if movement.direction != Vec2::ZERO {
-                movement.direction = Vec2::ZERO;
+    let mut claimed: HashSet<(Species, Entity)> = hunter_query.iter()
+        .filter(|(_, _, agent, ..)| agent.is_hunting())
+        .filter_map(|(_, _, agent, &species, ..)| agent.get_prey().map(|prey| (species, prey)))
+        .collect();
+    let mut idle: Vec<(RngKey, Entity)> = hunter_query.iter()
+        .filter(|(_, _, agent, ..)| agent.is_idle())
+        .map(|(entity, &key, ..)| (key, entity))
+        .collect();
+    idle.sort_unstable();
+    for (key, entity) in idle {
+        let (_, _, mut hunter_agent, &species, hunter_pos, mut movement, mut wander, genome, juvenile, pursuit) =
+            hunter_query.get_mut(entity).unwrap();
+        let definition = registry.get(species);
+        // 带有基因组的个体使用自身的感知半径
+        let radius = genome.map_or(definition.perception_radius, |g| g.perception_radius);
+        let activity = modifiers.hunting(species);
+        let radius = radius * activity;
+        let speed = movement.speed * modifiers.speed(species);
+        let pos = hunter_pos.0;
+        let reachable = |prey: Entity| {
+            if claimed.contains(&(species, prey)) {
+                return false;
+            }
+            let Ok((age, prey_pos)) = prey_query.get(prey) else {
+                return false;
+            };
+            speed > 0.0 && age.remaining_secs() > bounds.distance(pos, prey_pos.0) / speed
+        };
+        // 食谱中各物种最近的猎物里取最近的，距离相同时取食谱中靠前的物种
+        let resting = pursuit.is_some_and(|p| p.is_resting());
+        let nearest = if activity <= 0.0 || resting { None } else {
+            definition.prey.iter()
+                .filter(|&&prey_species| !juvenile || prey_species == Species::Grass)
+                .filter_map(|&prey_species| match prey_species {
+                    Species::Grass => grass_index.get_nearest_matching(pos, radius, reachable),
+                    _ => animal_index.get(prey_species).get_nearest_matching(pos, radius, reachable),
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, &prey)| prey)
+        };
+        match nearest {
+            Some(prey) => {
+                claimed.insert((species, prey));
+                hunter_agent.switch_to_hunting(prey);
             }
-            if let Some((_, &nearby)) = index.get_nearest(hunter_pos.0){
-                hunter_agent.switch_to_hunting(nearby);
+            None => {
+                let mut rng = sim_rng.stream(WANDER_STREAM, key);
+                wander.step(time.delta(), &mut movement, &mut rng);
             }
         }
-    });
+    }
 }
-pub fn attack<TH,TP>(mut hunter_query: Query<(&mut TH, &MyPosition)>,
-                     mut prey_query: Query<(&MyPosition, &mut Health),With<TP>>,
-                     damage: Res<Damage<TH>>,
-                     energy_gain: Res<EnergyGain<TP>>,
-                     cooling_time: Res<AttackCoolingTime<TH>>,
-                     eating_time: Res<EatingTime<TH>>,
-                     mut commands: Commands
-) where TH: Component + HunterAgent + TypeComponent, TP: Component + TypeComponent
+type AttackerData<TH> = (Entity, &'static mut TH, &'static Species, &'static MyPosition, &'static Movement, Option<&'static Pursuit>,
+                         &'static RngKey);
+type TargetData = (&'static MyPosition, &'static mut Health, &'static Species, &'static Age, Option<&'static mut Energy>, Option<&'static Movement>);
+
+// 伤害、攻击冷却与进食时间取捕食者物种的声明，捕食收获取猎物物种的声明。
+// 声明了 chase 的物种的攻击按速度优势、猎物是否在逃跑与追捕的疲劳程度以一定概率命中，未命中同样进入攻击冷却
+pub fn attack<TH>(mut hunter_query: Query<AttackerData<TH>>,
+                  mut prey_query: Query<TargetData>,
+                  registry: Res<SpeciesRegistry>,
+                  modifiers: Res<SeasonalModifiers>,
+                  bounds: Res<WorldBounds>,
+                  config: Res<Config>,
+                  sim_rng: Res<SimRng>,
+                  mut death_events: EventWriter<DeathEvent>,
+                  mut commands: Commands
+) where TH: Component + HunterAgent + EscapeAgent + TypeComponent
 {
-    let mut to_remove = HashSet::<Entity>::new();
-    hunter_query.iter_mut().for_each(|(mut hunter_agent, hunter_pos)| {
+    // 本帧开始时正在逃跑的个体
+    let fleeing: EntityHashSet = hunter_query.iter()
+        .filter(|(_, agent, ..)| matches!(agent.get_state(), EscapeState::Fleeing))
+        .map(|(entity, ..)| entity)
+        .collect();
+    let mut to_remove = EntityHashSet::default();
+    hunter_query.iter_mut().for_each(|(hunter, mut hunter_agent, &hunter_species, hunter_pos, hunter_movement, pursuit, &key)| {
         if hunter_agent.is_hunting()
         {
+            let definition = registry.get(hunter_species);
             let entity = hunter_agent.get_prey().unwrap();
-            if let Ok((prey_pos, mut prey_health)) = prey_query.get_mut(entity)
+            if let Ok((prey_pos, mut prey_health, &species, age, prey_energy, prey_movement)) = prey_query.get_mut(entity)
             {
                 // 检测猎物 entity 是否已经被删除
                 if !to_remove.contains(&entity) {
-                    if hunter_pos.0.distance(prey_pos.0) < ATTACK_DISTANCE
+                    if bounds.distance(hunter_pos.0, prey_pos.0) < ATTACK_DISTANCE
                     {
-                        prey_health.0 -= damage.damage;
+                        if let Some(chase) = definition.chase.as_ref() {
+                            let hunter_speed = hunter_movement.speed * modifiers.speed(hunter_species);
+                            let prey_speed = prey_movement.map_or(0.0, |m| m.speed * modifiers.speed(species));
+                            let probability = chase.strike_probability(hunter_speed, prey_speed, fleeing.contains(&entity),
+                                                                       &pursuit.copied().unwrap_or_default());
+                            let mut rng = sim_rng.stream(HUNTING_STREAM, key);
+                            if rng.gen::<f32>() >= probability {
+                                hunter_agent.switch_to_attack_cooling(definition.attack_cooling_time);
+                                return;
+                            }
+                        }
+                        prey_health.0 -= definition.damage;
                         if prey_health.0 <= 0.0
                         {
-                            hunter_agent.switch_to_eating(energy_gain.energy_gain, eating_time.time);
+                            // 封闭的养分循环中，捕食者吃掉的能量取自猎物，猎物剩下的能量留给尸体
+                            let mut gain = registry.energy_gain(species, &config);
+                            let mut remaining = 0.0;
+                            if let Some(mut prey_energy) = prey_energy {
+                                if config.nutrient_cycle {
+                                    gain = gain.min(prey_energy.0.max(0.0));
+                                    prey_energy.0 -= gain;
+                                }
+                                remaining = prey_energy.0;
+                            }
+                            hunter_agent.switch_to_eating(gain, definition.eating_time);
                             to_remove.insert(entity);
+                            // 按击杀的顺序删除，删除顺序不依赖实体编号
+                            commands.entity(entity).despawn();
+                            death_events.send(DeathEvent {
+                                entity,
+                                species,
+                                cause: DeathCause::Predation,
+                                position: prey_pos.0,
+                                age: age.elapsed_secs(),
+                                energy: remaining,
+                                killer: Some(hunter),
+                            });
 
                         } else {
-                            hunter_agent.switch_to_attack_cooling(cooling_time.time);
+                            hunter_agent.switch_to_attack_cooling(definition.attack_cooling_time);
                         }
                     }
                 }
@@ -191,8 +251,55 @@ pub fn attack<TH,TP>(mut hunter_query: Query<(&mut TH, &MyPosition)>,
             // 如果猎物不存在了，交由 move_to_prey 捕获并处理，此处不处理
         }
     });
-    to_remove.iter().for_each(|e| {
-        commands.entity(*e).despawn();
-    });
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animal::AnimalAgent;
+    use crate::config::Config;
+    use crate::species::SpeciesDefinition;
+    use crate::test_app::{headless_app, place_animals};
+
+    const DEER: Species = Species::Animal(0);
+    const WOLF: Species = Species::Animal(1);
+
+    #[test]
+    fn hunters_in_the_same_frame_pick_different_prey() {
+        let deer = SpeciesDefinition {
+            name: "Deer".to_string(),
+            initial_count: 2,
+            energy: 1000.0,
+            speed: 0.0,
+            ..default()
+        };
+        let wolf = SpeciesDefinition {
+            name: "Wolf".to_string(),
+            initial_count: 2,
+            energy: 1000.0,
+            perception_radius: 300.0,
+            diet: vec!["Deer".to_string()],
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(8),
+            species: vec![deer, wolf],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        let deer = place_animals(&mut app, DEER, &[Vec2::ZERO, Vec2::new(150.0, 0.0)]);
+        // 两只狼离第一只鹿都更近
+        let wolves = place_animals(&mut app, WOLF, &[Vec2::new(50.0, 0.0), Vec2::new(60.0, 0.0)]);
+        for &wolf in wolves.iter() {
+            HunterAgent::switch_to_idle(app.world_mut().get_mut::<AnimalAgent>(wolf).unwrap().as_mut());
+        }
+        app.update();
+        let prey: Vec<Option<Entity>> = wolves.iter()
+            .map(|&wolf| HunterAgent::get_prey(app.world().get::<AnimalAgent>(wolf).unwrap()))
+            .collect();
+        // RngKey 在前的狼先选中最近的鹿，另一只狼只能追第二只鹿
+        assert_eq!(prey, vec![Some(deer[0]), Some(deer[1])]);
+    }
+}
@@ -1,13 +1,19 @@
 use bevy::prelude::*;
-use bevy::utils::{HashMap};
-use crate::config::Config;
+use bevy::ecs::entity::EntityHashMap;
+use serde::Deserialize;
+use crate::aging::Age;
 use crate::energy::Energy;
-use crate::from_config::FromConfig;
+use crate::genome::Genome;
+use crate::life_stage::{GestationConfig, JuvenileConfig, Offspring, Pregnancy};
+use crate::sim_rng::{RngKey, SimRng, REPRODUCTION_STREAM};
+use crate::lifecycle::Species;
 use crate::movemement::{Movement, MyPosition};
-use crate::spatial_index::{euclidean, SpatialIndex};
+use crate::pathfinding::{steer_towards, NavPath, NavigationGrid};
+use crate::sex::{Sex, SexConfig};
+use crate::spatial_index::{SpatialIndex, SpeciesIndex};
+use crate::species::SpeciesRegistry;
 use crate::type_component::TypeComponent;
-use kdtree;
-use kdtree::KdTree;
+use crate::world_bounds::WorldBounds;
 
 pub enum ReproductionState{
     Idle,
@@ -26,9 +32,26 @@ pub trait ReproductionAgent: Component{
     fn switch_to_mating(&mut self, mating_time: f32);
     fn get_mate(&self) -> Option<Entity>;
     fn get_reproduction_timer(&mut self) -> &mut Timer;
+    ///
+    /// 年龄达到 maturity_age 即性成熟，幼年个体在此之前不能繁殖，也不能捕食其它动物
+    ///
+    fn is_mature(&self, age: &Age, maturity_age: f32) -> bool {
+        age.elapsed_secs() >= maturity_age
+    }
+    ///
+    /// 能否开始寻找配偶：已经性成熟，且处于可以切换到寻找配偶的状态
+    ///
+    fn is_eligible(&self, age: &Age, maturity_age: f32) -> bool {
+        matches!(self.get_state(), ReproductionState::Idle | ReproductionState::OtherCanMate)
+            && self.is_mature(age, maturity_age)
+    }
 }
-#[derive(Resource)]
-pub struct ReproductionConfig<T:ReproductionAgent + TypeComponent>{
+///
+/// 物种的繁殖能力，以及该物种的性别、妊娠与幼年设置
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ReproductionConfig{
     // 开始寻找配偶所需要的能量阈值
     pub energy_threshold: f32,
     // 繁殖所消耗的能量
@@ -39,89 +62,114 @@ pub struct ReproductionConfig<T:ReproductionAgent + TypeComponent>{
     pub reproduction_radius: f32,
     // 繁殖所需时间
     pub mating_time: f32,
-    pub _marker: std::marker::PhantomData<T>,
+    pub sex: SexConfig,
+    pub gestation: GestationConfig,
+    pub juvenile: JuvenileConfig,
 }
 
-impl<T:ReproductionAgent + TypeComponent> ReproductionConfig<T>{
-    pub fn new(energy_threshold: f32, energy_cost: f32, search_radius: f32, reproduction_radius: f32, mating_time: f32) -> Self{
+impl Default for ReproductionConfig{
+    fn default() -> Self{
         ReproductionConfig{
-            energy_threshold,
-            energy_cost,
-            search_radius,
-            reproduction_radius,
-            mating_time,
-            _marker: std::marker::PhantomData
+            energy_threshold: 100.0,
+            energy_cost: 40.0,
+            search_radius: 500.0,
+            reproduction_radius: 40.0,
+            mating_time: 5.0,
+            sex: SexConfig::default(),
+            gestation: GestationConfig::default(),
+            juvenile: JuvenileConfig::default(),
         }
     }
 }
 #[derive(Deref, DerefMut)]
-pub struct FindMateEntitiesMapCache(HashMap<Entity, Vec2>);
+pub struct FindMateEntitiesMapCache(EntityHashMap<(Vec2, Option<Sex>)>);
 impl Default for FindMateEntitiesMapCache {
     fn default() -> Self{
-        FindMateEntitiesMapCache(HashMap::default())
+        FindMateEntitiesMapCache(EntityHashMap::default())
     }
 }
 #[derive(Deref, DerefMut)]
-pub struct FindMateKdTreeCache(KdTree<f32, Entity, [f32; 2]>);
-impl Default for FindMateKdTreeCache {
+pub struct FindMateIndexCache<T: ReproductionAgent + TypeComponent>(SpatialIndex<T>);
+impl<T: ReproductionAgent + TypeComponent> Default for FindMateIndexCache<T> {
     fn default() -> Self{
-        FindMateKdTreeCache(KdTree::new(2))
+        FindMateIndexCache(SpatialIndex::default())
     }
 }
 
+type FindMateData<T> = (Entity, &'static mut T, &'static Species, &'static Energy, &'static MyPosition, &'static Age,
+                        Option<&'static Genome>, Option<&'static Sex>, Has<Pregnancy>, &'static RngKey);
+type MatingData<T> = (Entity, &'static mut T, &'static mut Energy, &'static MyPosition,
+                      &'static Species, &'static Genome, Option<&'static Sex>, &'static RngKey);
+
+// 只有同物种的一雄一雌才能配对，每个个体与距离最近的异性配对。幼年与妊娠中的个体不寻找配偶。
+// 各物种依次配对，个体按 RngKey 的顺序挑选配偶，使结果不依赖实体编号；每个物种配对结束时缓存均已清空
 pub fn find_mate_when_energy_enough_and_idle<T: ReproductionAgent + TypeComponent>(
-    mut query: Query<(Entity, &mut T, &Energy, &MyPosition)>,
-    reproduction_config: Res<ReproductionConfig<T>>,
+    mut query: Query<FindMateData<T>>,
+    registry: Res<SpeciesRegistry>,
+    bounds: Res<WorldBounds>,
     mut entities_map: Local<FindMateEntitiesMapCache>,
-    mut kdtree: Local<FindMateKdTreeCache>
+    mut index: Local<FindMateIndexCache<T>>
 ){
-    query.iter().for_each(|(entity, agent, energy, pos)|{
-        match agent.get_state(){
-            ReproductionState::Idle|ReproductionState::OtherCanMate => {
-                if energy.0 >= reproduction_config.energy_threshold{
-                    entities_map.insert(entity, pos.0);
-                    kdtree.add([pos.0.x, pos.0.y], entity).unwrap();
+    index.set_bounds(&bounds);
+    for (current, definition) in registry.iter() {
+        let Some(reproduction_config) = definition.reproduction.as_ref() else {
+            continue;
+        };
+        let maturity_age = definition.maturity_age();
+        let mut candidates = Vec::new();
+        query.iter().for_each(|(entity, agent, &species, energy, pos, age, genome, sex, pregnant, &key)|{
+            if species == current && !pregnant && agent.is_eligible(age, maturity_age) {
+                // 带有基因组的个体使用自身的繁殖阈值
+                let threshold = genome.map_or(reproduction_config.energy_threshold, |g| g.reproduction_threshold);
+                if energy.0 >= threshold{
+                    entities_map.insert(entity, (pos.0, sex.copied()));
+                    index.insert(entity, pos.0);
+                    candidates.push((key, entity));
                 }
             }
-            _ => {}
-        }
-    });
-    while entities_map.len() > 1{ //只有一个，没有找伴的意义
-        let (&entity, &pos) = entities_map.iter().next().unwrap();
-        // 先移除自身，避免在搜索最近点时获取的是自身
-        kdtree.remove(&[pos.x, pos.y], &entity).unwrap();
-        entities_map.remove(&entity);
-        let (distance, &nearest_entity) = kdtree.nearest(&[pos.x, pos.y], 1, &euclidean).unwrap()[0];
-        if distance <= reproduction_config.search_radius{
-            let (_, mut agent, _, _) = query.get_mut(entity).unwrap();
-            agent.switch_to_searching_mate(nearest_entity);
-            let (_, mut mate_agent, _, _) = query.get_mut(nearest_entity).unwrap();
-            mate_agent.switch_to_searching_mate(entity);
-            let mate_pos = entities_map.get(&nearest_entity).unwrap();
-            kdtree.remove(&[mate_pos.x, mate_pos.y], &nearest_entity).unwrap();
-            entities_map.remove(&nearest_entity);
+        });
+        candidates.sort_unstable();
+        for (_, entity) in candidates {
+            // 已经被先挑选的个体选为配偶
+            let Some((pos, sex)) = entities_map.remove(&entity) else {
+                continue;
+            };
+            // 先移除自身，避免在搜索最近点时获取的是自身
+            index.remove(entity);
+            if entities_map.is_empty() { //只剩自己，没有找伴的意义
+                break;
+            }
+            let compatible = |other: Entity| entities_map.get(&other)
+                .is_some_and(|&(_, other_sex)| Sex::can_mate(sex, other_sex));
+            let nearest = index.get_nearest_matching(pos, reproduction_config.search_radius, compatible)
+                .map(|(_, &nearest_entity)| nearest_entity);
+            if let Some(nearest_entity) = nearest{
+                let (_, mut agent, ..) = query.get_mut(entity).unwrap();
+                agent.switch_to_searching_mate(nearest_entity);
+                let (_, mut mate_agent, ..) = query.get_mut(nearest_entity).unwrap();
+                mate_agent.switch_to_searching_mate(entity);
+                index.remove(nearest_entity);
+                entities_map.remove(&nearest_entity);
+            }
         }
     }
-    if entities_map.len() == 1{
-        let (entity, pos) = entities_map.iter().next().unwrap();
-        kdtree.remove(&[pos.x, pos.y], entity).unwrap();
-        entities_map.clear();
-    }
 }
 
 #[derive(Deref, DerefMut)]
-pub struct SearchingMateMatingEntitiesCache(HashMap<Entity, Entity>);
+pub struct SearchingMateMatingEntitiesCache(EntityHashMap<Entity>);
 impl Default for SearchingMateMatingEntitiesCache{
     fn default() -> Self{
-        SearchingMateMatingEntitiesCache(HashMap::default())
+        SearchingMateMatingEntitiesCache(EntityHashMap::default())
     }
 }
+// 配对双方属于同一物种，繁殖距离与繁殖时间取该物种的声明
 pub fn searching_mate_conditions<T: ReproductionAgent + TypeComponent>(
-    mut query: Query<(Entity, &mut T, &MyPosition)>,
-    reproduction_config: Res<ReproductionConfig<T>>,
+    mut query: Query<(Entity, &mut T, &Species, &MyPosition)>,
+    registry: Res<SpeciesRegistry>,
+    bounds: Res<WorldBounds>,
     mut mating_entities: Local<SearchingMateMatingEntitiesCache>
 ) {
-    query.iter().for_each(|(entity, agent, _)|{
+    query.iter().for_each(|(entity, agent, ..)|{
         match agent.get_state(){
             ReproductionState::SearchingMate => {
                 mating_entities.insert(entity, agent.get_mate().unwrap());
@@ -132,13 +180,15 @@ pub fn searching_mate_conditions<T: ReproductionAgent + TypeComponent>(
     mating_entities.iter().for_each(|(entity, mate)| {
         if let Some(mates_mate) = mating_entities.get(mate){
             if *mates_mate == *entity{
-                let pos2 = query.get(*mate).unwrap().2.0;
-                let (_, mut agent, pos) = query.get_mut(*entity).unwrap();
+                let pos2 = query.get(*mate).unwrap().3.0;
+                let (_, mut agent, &species, pos) = query.get_mut(*entity).unwrap();
+                let reproduction_config = registry.get(species).reproduction.as_ref()
+                    .expect("Only species with reproduction can search for a mate");
                 match agent.get_state() {
                     ReproductionState::SearchingMate => {
-                        if pos.0.distance(pos2) <= reproduction_config.reproduction_radius {
+                        if bounds.distance(pos.0, pos2) <= reproduction_config.reproduction_radius {
                             agent.switch_to_mating(reproduction_config.mating_time);
-                            let (_, mut mate_agent, _) = query.get_mut(*mate).unwrap();
+                            let (_, mut mate_agent, ..) = query.get_mut(*mate).unwrap();
                             mate_agent.switch_to_mating(reproduction_config.mating_time);
                         }
                     }
@@ -152,7 +202,7 @@ pub fn searching_mate_conditions<T: ReproductionAgent + TypeComponent>(
         else
         {
             // 繁殖对象不处于寻找配偶状态或已经死亡，切换到 idle
-            if let Ok((_, mut agent, _)) = query.get_mut(*entity){
+            if let Ok((_, mut agent, ..)) = query.get_mut(*entity){
                 agent.switch_to_idle();
             }
         }
@@ -161,46 +211,70 @@ pub fn searching_mate_conditions<T: ReproductionAgent + TypeComponent>(
 }
 
 #[derive(Deref, DerefMut)]
-pub struct MatingEntitiesCache(HashMap<Entity, Entity>);
+pub struct MatingEntitiesCache(EntityHashMap<Entity>);
 impl Default for MatingEntitiesCache{
     fn default() -> Self{
-        MatingEntitiesCache(HashMap::default())
+        MatingEntitiesCache(EntityHashMap::default())
     }
 }
 
-pub fn mating_conditions<T: ReproductionAgent + TypeComponent, TB: Bundle + FromConfig>(
-    mut query: Query<(Entity, &mut T, &mut Energy, &MyPosition)>,
+// 繁殖完成时双亲按各自的性别付出繁殖消耗。有妊娠期时雌性（没有性别时为先访问到的一方）开始妊娠，
+// 否则一窝后代立即在双亲中间出生
+pub fn mating_conditions<T: ReproductionAgent + TypeComponent>(
+    mut query: Query<MatingData<T>>,
     time: Res<Time>,
-    mut commands: Commands,
-    reproduction_config: Res<ReproductionConfig<T>>,
-    app_config: Res<Config>,
+    bounds: Res<WorldBounds>,
+    sim_rng: Res<SimRng>,
+    mut offspring: Offspring,
     mut mating_entities: Local<MatingEntitiesCache>
 ){
-    query.iter().for_each(|(entity, agent, _, _)|{
+    // 按 RngKey 的顺序处理，先处理的一方决定后代的随机流，使结果不依赖实体编号
+    let mut order = Vec::new();
+    query.iter().for_each(|(entity, agent, .., &key)|{
         match agent.get_state(){
             ReproductionState::Mating => {
                 mating_entities.insert(entity, agent.get_mate().unwrap());
+                order.push((key, entity));
             }
             _ => {}
         }
     });
-    mating_entities.iter().for_each(|(entity, mate)| {
+    order.sort_unstable();
+    order.iter().for_each(|(_, entity)| {
+        let mate = &mating_entities[entity];
         if let Some(mates_mate) = mating_entities.get(mate){
             if *mates_mate == *entity{
-                let (_, mut agent, mut energy, pos) = query.get_mut(*entity).unwrap();
+                let (_, mut agent, mut energy, pos, &species, &genome, sex, &key) = query.get_mut(*entity).unwrap();
                 match agent.get_state() {
                     ReproductionState::Mating => {
                         let timer = agent.get_reproduction_timer();
                         timer.tick(time.delta());
                         if timer.just_finished() {
-                            energy.0 -= reproduction_config.energy_cost;
+                            let reproduction_config = offspring.registry.get(species).reproduction.as_ref()
+                                .expect("Only species with reproduction can mate");
+                            let gestation_time = reproduction_config.gestation.gestation_time;
+                            let cost = reproduction_config.sex.cost(sex.copied(), reproduction_config.energy_cost);
+                            energy.0 -= cost;
                             agent.switch_to_idle();
                             let pos = pos.0; // 解引用，避免重复借用
-                            let (_, mut mate_agent, mut mate_energy, mate_pos) = query.get_mut(*mate).unwrap();
+                            let is_male = sex == Some(&Sex::Male);
+                            let (_, mut mate_agent, mut mate_energy, mate_pos, _, &mate_genome, mate_sex, _) = query.get_mut(*mate).unwrap();
                             mate_agent.switch_to_idle();
-                            mate_energy.0 -= reproduction_config.energy_cost;
-                            let new_pos = (pos + mate_pos.0) / 2.0;
-                            commands.spawn(TB::from_config(&app_config, new_pos.x, new_pos.y));
+                            let mate_cost = reproduction_config.sex.cost(mate_sex.copied(), reproduction_config.energy_cost);
+                            mate_energy.0 -= mate_cost;
+                            // 封闭的养分循环中，后代的能量就是双亲付出的繁殖消耗
+                            let litter_energy = if offspring.app_config.nutrient_cycle { cost + mate_cost } else { 0.0 };
+                            if gestation_time > 0.0 {
+                                let (mother, father) = if is_male { (*mate, genome) } else { (*entity, mate_genome) };
+                                let timer = Timer::from_seconds(gestation_time, TimerMode::Once);
+                                offspring.commands.entity(mother).insert(Pregnancy { timer, father, energy: litter_energy });
+                            } else {
+                                // 在双方连线的中点出生，环面上取最短连线
+                                let (new_pos, _) = bounds.constrain(pos + bounds.delta(pos, mate_pos.0) / 2.0);
+                                // 后代的基因组由双亲的基因组遗传并突变得到
+                                let mut rng = sim_rng.stream(REPRODUCTION_STREAM, key);
+                                offspring.spawn_litter((&genome, &mate_genome), species, new_pos, litter_energy, &mut rng);
+                            }
                         }
                     }
                     ReproductionState::Idle => {
@@ -219,7 +293,7 @@ pub fn mating_conditions<T: ReproductionAgent + TypeComponent, TB: Bundle + From
         else
         {
             // 繁殖对象不处于繁殖状态或已经死亡，切换到 idle
-            if let Ok((_, mut agent, _, _)) = query.get_mut(*entity){
+            if let Ok((_, mut agent, ..)) = query.get_mut(*entity){
                 agent.switch_to_idle();
             }
         }
@@ -227,18 +301,20 @@ pub fn mating_conditions<T: ReproductionAgent + TypeComponent, TB: Bundle + From
     mating_entities.clear();
 }
 pub fn reproduction_state_running<T: ReproductionAgent + TypeComponent>(
-    mut query: Query<(Entity, &T, &mut Movement, &MyPosition)>,
-    index: Res<SpatialIndex<T>>,
+    mut query: Query<(&T, &Species, &mut Movement, &mut NavPath, &MyPosition)>,
+    index: Res<SpeciesIndex<T>>,
+    nav: Option<Res<NavigationGrid>>,
+    bounds: Res<WorldBounds>,
 ){
     // 状态运行
-    query.par_iter_mut().for_each(|(_, agent, mut movement, pos)| {
+    query.par_iter_mut().for_each(|(agent, &species, mut movement, mut path, pos)| {
         match agent.get_state() {
             ReproductionState::SearchingMate => {
                 // 寻找配偶状态下，不断更新配偶位置
                 let mate = agent.get_mate().unwrap();
                 // 配偶可能会刚好在状态机条件检查完，这段代码开始运行前被虎杀死，如果这种情况发生，不作为，由下一帧的状态机条件检查系统来处理。
-                if let Some(mate_pos) = index.get_pos(mate) {
-                    movement.direction = (mate_pos - pos.0).normalize();
+                if let Some(mate_pos) = index.get(species).get_pos(mate) {
+                    movement.direction = steer_towards(nav.as_deref(), &mut path, &bounds, pos.0, mate_pos);
                 }
                 else
                 {
@@ -253,4 +329,81 @@ pub fn reproduction_state_running<T: ReproductionAgent + TypeComponent>(
             _ => {}
         }
     });
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animal::{AnimalAgent, AnimalState};
+    use crate::config::Config;
+    use crate::species::SpeciesDefinition;
+    use crate::test_app::{headless_app, place_animals};
+
+    const DEER: Species = Species::Animal(0);
+
+    // 两只挨在一起、能量足够繁殖的鹿，性别由 sexes 指定
+    fn couple(sexes: [Sex; 2]) -> (App, Vec<Entity>) {
+        let deer = SpeciesDefinition {
+            name: "Deer".to_string(),
+            initial_count: 2,
+            energy: 200.0,
+            lifespan: 1000.0,
+            reproduction: Some(ReproductionConfig {
+                sex: SexConfig {
+                    male_cost: Some(10.0),
+                    female_cost: Some(60.0),
+                    ..default()
+                },
+                ..default()
+            }),
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(12),
+            species: vec![deer],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        let parents = place_animals(&mut app, DEER, &[Vec2::ZERO, Vec2::new(20.0, 0.0)]);
+        for (&parent, sex) in parents.iter().zip(sexes) {
+            *app.world_mut().get_mut::<Sex>(parent).unwrap() = sex;
+        }
+        (app, parents)
+    }
+
+    fn count(app: &mut App) -> usize {
+        app.world_mut().query::<&Species>().iter(app.world()).count()
+    }
+
+    #[test]
+    fn same_sex_pairs_never_mate() {
+        for sex in [Sex::Male, Sex::Female] {
+            let (mut app, parents) = couple([sex, sex]);
+            while app.world().resource::<Time<Fixed>>().elapsed_secs() < 20.0 {
+                app.update();
+                for &parent in parents.iter() {
+                    let state = app.world().get::<AnimalAgent>(parent).unwrap().state;
+                    assert!(!matches!(state, AnimalState::SearchingMate | AnimalState::Mating), "{:?}", sex);
+                }
+            }
+            assert_eq!(count(&mut app), 2);
+        }
+    }
+
+    #[test]
+    fn mating_cost_depends_on_sex() {
+        let (mut app, parents) = couple([Sex::Male, Sex::Female]);
+        let mut frames = 0;
+        while count(&mut app) == 2 {
+            app.update();
+            frames += 1;
+            assert!(frames < 2000, "the couple never mated");
+        }
+        let energy = |entity: Entity| app.world().get::<Energy>(entity).unwrap().0;
+        let elapsed = app.world().resource::<Time<Fixed>>().elapsed_secs();
+        // 双方的代谢消耗相同，差别只来自各自的繁殖消耗
+        assert!((energy(parents[0]) - energy(parents[1]) - 50.0).abs() < 1e-3);
+        assert!((energy(parents[0]) - (200.0 - 10.0 - elapsed)).abs() < 0.1, "male has {}", energy(parents[0]));
+    }
+}
@@ -0,0 +1,58 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// 动物的性别，只有一雄一雌才能配对繁殖
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Sex {
+    Male,
+    Female,
+}
+
+impl Sex {
+    // 以 male_ratio 的概率为雄性
+    pub fn random(male_ratio: f32, rng: &mut impl Rng) -> Self {
+        if rng.gen::<f32>() < male_ratio { Sex::Male } else { Sex::Female }
+    }
+    ///
+    /// 两个个体能否配对。没有性别的个体（例如从较早的快照中恢复的）可以与任何个体配对
+    ///
+    pub fn can_mate(a: Option<Sex>, b: Option<Sex>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => a != b,
+            _ => true,
+        }
+    }
+}
+
+///
+/// 与性别相关的繁殖参数：后代为雄性的概率，以及雄性、雌性各自付出的繁殖消耗，
+/// 不指定时取物种的繁殖消耗
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct SexConfig{
+    pub male_ratio: f32,
+    pub male_cost: Option<f32>,
+    pub female_cost: Option<f32>,
+}
+impl Default for SexConfig{
+    fn default() -> Self{
+        SexConfig{
+            male_ratio: 0.5,
+            male_cost: None,
+            female_cost: None,
+        }
+    }
+}
+impl SexConfig
+{
+    // 该性别付出的繁殖消耗，没有指定或没有性别时为 default
+    pub fn cost(&self, sex: Option<Sex>, default: f32) -> f32 {
+        match sex {
+            Some(Sex::Male) => self.male_cost.unwrap_or(default),
+            Some(Sex::Female) => self.female_cost.unwrap_or(default),
+            None => default,
+        }
+    }
+}
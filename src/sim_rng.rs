@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use crate::lifecycle::Species;
+
+// 各系统派生随机流时使用的流编号，保证不同系统之间的随机数互不相关
+pub const SETUP_STREAM: u64 = 1;
+pub const GRASS_REPRODUCTION_STREAM: u64 = 2;
+pub const WANDER_STREAM: u64 = 3;
+pub const REPRODUCTION_STREAM: u64 = 4;
+pub const HUNTING_STREAM: u64 = 5;
+
+///
+/// 全局随机数资源。所有随机数都由同一个种子派生，相同的种子与配置得到相同的模拟过程。
+///
+/// 并行循环中不能共享同一个随机数生成器，否则取数顺序取决于线程调度，
+/// 因此通过 stream 按 (流编号, 固定帧序号, 个体的 RngKey) 派生独立的随机流。
+///
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct SimRng {
+    seed: u64,
+    // 已经运行的固定帧数
+    tick: u64,
+    // 下一个出生的个体的 RngKey，较早的快照中没有，从 0 开始分配
+    #[serde(default)]
+    next_key: u64,
+}
+
+///
+/// 个体的随机流的键，出生时按出生顺序分配并保存在快照中。
+/// 实体编号在从快照恢复后会改变，随机流不能由实体编号派生
+///
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RngKey(pub u64);
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng { seed, tick: 0, next_key: 0 }
+    }
+    ///
+    /// 未指定种子时从系统熵源取一个种子，并打印出来以便复现
+    ///
+    pub fn from_optional_seed(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| rand::thread_rng().next_u64());
+        info!("Simulation seed: {}", seed);
+        SimRng::new(seed)
+    }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    ///
+    /// 派生一个只与种子、当前固定帧、流编号和个体有关的随机流
+    ///
+    pub fn stream(&self, stream: u64, key: RngKey) -> StdRng {
+        self.stream_with_key(stream, key.0)
+    }
+    ///
+    /// 同 stream，但由调用者给出任意的键，用于不对应单个实体的场合
+    ///
+    pub fn stream_with_key(&self, stream: u64, key: u64) -> StdRng {
+        let mut h = splitmix64(self.seed);
+        h = splitmix64(h ^ stream);
+        h = splitmix64(h ^ self.tick);
+        h = splitmix64(h ^ key);
+        StdRng::seed_from_u64(h)
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+// 草与动物出生时分配 RngKey，从快照恢复的个体已经带有保存时的键
+pub fn assign_rng_key(trigger: Trigger<OnAdd, Species>,
+                      query: Query<(), With<RngKey>>,
+                      mut sim_rng: ResMut<SimRng>,
+                      mut commands: Commands) {
+    if query.contains(trigger.entity()) {
+        return;
+    }
+    commands.entity(trigger.entity()).insert(RngKey(sim_rng.next_key));
+    sim_rng.next_key += 1;
+}
+
+// 应该被放在 FixedFirst 里，每个固定帧推进一次
+pub fn advance_sim_rng(mut rng: ResMut<SimRng>) {
+    rng.tick += 1;
+}
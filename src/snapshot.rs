@@ -0,0 +1,584 @@
+use std::path::Path;
+use std::time::Duration;
+use bevy::prelude::*;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::ecs::system::SystemParam;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::aging::Age;
+use crate::animal::{AnimalAgent, AnimalBundle, AnimalState};
+use crate::carcass::{Carcass, CarcassBundle};
+use crate::chase::Pursuit;
+use crate::config::Config;
+use crate::energy::Energy;
+use crate::escape_system::EscapeTimer;
+use crate::from_config::FromConfig;
+use crate::genome::Genome;
+use crate::grass::{Grass, GrassBundle};
+use crate::grass_reproduction::{GrassGrowthBonus, GrassNeighborCount, GrassReproductionTimer};
+use crate::health::Health;
+use crate::life_stage::{Juvenile, Pregnancy};
+use crate::lifecycle::{count_lifecycle_events, DeathCause, LifecycleStatistics, Species};
+use crate::movemement::{Movement, MyPosition};
+use crate::nutrient::{NutrientLedger, SoilNutrients};
+use crate::sex::Sex;
+use crate::sim_rng::{RngKey, SimRng};
+use crate::spatial_index::{SpatialIndex, SpatialIndexKind, SpeciesIndex};
+use crate::species::SpeciesRegistry;
+use crate::wander::Wander;
+use crate::water::{DrinkPoint, Hydration};
+use crate::world_bounds::WorldBounds;
+
+#[derive(Debug)]
+pub enum SnapshotError{
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+}
+impl std::fmt::Display for SnapshotError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "failed to access snapshot file: {}", e),
+            SnapshotError::Parse(e) => write!(f, "failed to parse snapshot file: {}", e),
+            SnapshotError::Serialize(e) => write!(f, "failed to serialize snapshot: {}", e),
+        }
+    }
+}
+impl std::error::Error for SnapshotError{}
+
+///
+/// 计时器的完整状态。finished 需要单独保存，否则恢复后已结束的 Once 计时器会再触发一次
+///
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct TimerSnapshot {
+    duration: Duration,
+    elapsed: Duration,
+    repeating: bool,
+    finished: bool,
+}
+impl TimerSnapshot {
+    pub fn capture(timer: &Timer) -> Self {
+        TimerSnapshot {
+            duration: timer.duration(),
+            elapsed: timer.elapsed(),
+            repeating: timer.mode() == TimerMode::Repeating,
+            finished: timer.finished(),
+        }
+    }
+    pub fn restore(&self) -> Timer {
+        let mode = if self.repeating { TimerMode::Repeating } else { TimerMode::Once };
+        let mut timer = Timer::new(self.duration, mode);
+        if self.finished && self.repeating && !self.duration.is_zero() {
+            // 重复计时器结束时 elapsed 已经折回，多加一整圈再做零时长的 tick 才能置上 finished
+            timer.set_elapsed(self.duration + self.elapsed);
+            timer.tick(Duration::ZERO);
+        } else {
+            timer.set_elapsed(self.elapsed);
+            if self.finished && !self.repeating {
+                // 零时长的 tick 只会置上 finished，下一次 tick 时 just_finished 即被清除
+                timer.tick(Duration::ZERO);
+            }
+        }
+        timer
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GrassSnapshot {
+    entity: u64,
+    position: [f32; 2],
+    health: f32,
+    age: TimerSnapshot,
+    reproduction_timer: TimerSnapshot,
+    neighbor_count: usize,
+    // 较早的快照中没有随机流的键与尸体带来的生长加速，恢复时重新分配键、不加速
+    #[serde(default)]
+    key: Option<u64>,
+    #[serde(default)]
+    growth_bonus: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CarcassSnapshot {
+    entity: u64,
+    position: [f32; 2],
+    species: String,
+    biomass: f32,
+}
+
+// Agent 中引用的实体按保存时的编号记录，恢复时重新映射
+#[derive(Serialize, Deserialize)]
+pub struct AgentSnapshot<S> {
+    state: S,
+    timer: TimerSnapshot,
+    target: Option<u64>,
+    last_energy_gain: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PregnancySnapshot {
+    timer: TimerSnapshot,
+    father: Genome,
+    energy: f32,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct PursuitSnapshot {
+    elapsed: f32,
+    distance: f32,
+    last_position: Option<[f32; 2]>,
+    rest: f32,
+}
+
+// 物种按名称记录，恢复时在当前的物种表中查找
+#[derive(Serialize, Deserialize)]
+pub struct AnimalSnapshot {
+    entity: u64,
+    species: String,
+    position: [f32; 2],
+    speed: f32,
+    direction: [f32; 2],
+    health: f32,
+    energy: f32,
+    age: TimerSnapshot,
+    wander: TimerSnapshot,
+    genome: Genome,
+    agent: AgentSnapshot<AnimalState>,
+    // 较早的快照中没有性别，恢复后的个体可以与任何个体配对
+    #[serde(default)]
+    sex: Option<Sex>,
+    #[serde(default)]
+    key: Option<u64>,
+    #[serde(default)]
+    juvenile: bool,
+    #[serde(default)]
+    pregnancy: Option<PregnancySnapshot>,
+    // 较早的快照中没有水分，恢复时口渴的物种从喝满开始
+    #[serde(default)]
+    hydration: Option<f32>,
+    #[serde(default)]
+    drink_point: Option<[f32; 2]>,
+    #[serde(default)]
+    pursuit: PursuitSnapshot,
+}
+
+///
+/// 整个模拟的快照：模拟时间、随机数状态、全局计时器、出生死亡统计以及所有实体与尸体。
+/// 物种均按名称记录，引用当前物种表中不存在的物种时恢复失败
+///
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub elapsed: Duration,
+    pub sim_rng: SimRng,
+    pub escape_timer: TimerSnapshot,
+    pub births: Vec<(String, usize)>,
+    pub deaths: Vec<(String, DeathCause, usize)>,
+    pub grass: Vec<GrassSnapshot>,
+    pub animals: Vec<AnimalSnapshot>,
+    // 较早的快照中没有尸体
+    #[serde(default)]
+    pub carcasses: Vec<CarcassSnapshot>,
+    // 开启养分循环时土壤各格的养分，以及养分账本第一次统计时的总量
+    #[serde(default)]
+    pub soil: Option<Vec<f64>>,
+    #[serde(default)]
+    pub nutrient_initial_total: Option<f64>,
+}
+
+impl Snapshot {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let text = std::fs::read_to_string(path).map_err(SnapshotError::Io)?;
+        ron::from_str(&text).map_err(SnapshotError::Parse)
+    }
+    ///
+    /// 先写入临时文件再替换，避免写到一半退出时损坏已有的快照
+    ///
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        let text = ron::to_string(self).map_err(SnapshotError::Serialize)?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, text).map_err(SnapshotError::Io)?;
+        std::fs::rename(&tmp, path).map_err(SnapshotError::Io)
+    }
+}
+
+///
+/// 可以保存到快照中的 Agent，在 Agent 组件与 AgentSnapshot 之间转换
+///
+pub trait SnapshotAgent: Component {
+    type State: Serialize + DeserializeOwned + Copy;
+    fn capture(&self) -> AgentSnapshot<Self::State>;
+    fn restore(snapshot: &AgentSnapshot<Self::State>, target: Option<Entity>) -> Self;
+}
+impl SnapshotAgent for AnimalAgent {
+    type State = AnimalState;
+    fn capture(&self) -> AgentSnapshot<AnimalState> {
+        AgentSnapshot {
+            state: self.state,
+            timer: TimerSnapshot::capture(&self.timer),
+            target: self.target.map(|e| e.to_bits()),
+            last_energy_gain: self.last_energy_gain,
+        }
+    }
+    fn restore(snapshot: &AgentSnapshot<AnimalState>, target: Option<Entity>) -> Self {
+        AnimalAgent {
+            state: snapshot.state,
+            timer: snapshot.timer.restore(),
+            target,
+            last_energy_gain: snapshot.last_energy_gain,
+        }
+    }
+}
+
+type AnimalData<T> = (Entity, &'static T, &'static Species, &'static MyPosition, &'static Movement, &'static Health,
+                      &'static Energy, &'static Age, &'static Wander, &'static Genome, Option<&'static Sex>,
+                      Has<Juvenile>, Option<&'static Pregnancy>, Option<&'static Hydration>,
+                      (&'static Pursuit, &'static RngKey, Option<&'static DrinkPoint>));
+type GrassData = (Entity, &'static MyPosition, &'static Health, &'static Age,
+                  &'static GrassReproductionTimer, &'static GrassNeighborCount, &'static GrassGrowthBonus, &'static RngKey);
+
+///
+/// 保存快照所需读取的全部资源与实体
+///
+#[derive(SystemParam)]
+pub struct SnapshotSource<'w, 's> {
+    time: Res<'w, Time<Fixed>>,
+    sim_rng: Res<'w, SimRng>,
+    escape_timer: Res<'w, EscapeTimer>,
+    statistics: Res<'w, LifecycleStatistics>,
+    registry: Res<'w, SpeciesRegistry>,
+    grass: Query<'w, 's, GrassData, With<Grass>>,
+    animals: Query<'w, 's, AnimalData<AnimalAgent>>,
+    carcasses: Query<'w, 's, (Entity, &'static Carcass, &'static MyPosition)>,
+    soil: Option<Res<'w, SoilNutrients>>,
+    ledger: Option<Res<'w, NutrientLedger>>,
+}
+
+impl SnapshotSource<'_, '_> {
+    pub fn capture(&self) -> Snapshot {
+        Snapshot {
+            elapsed: self.time.elapsed(),
+            sim_rng: self.sim_rng.clone(),
+            escape_timer: TimerSnapshot::capture(&self.escape_timer),
+            births: self.statistics.birth_counts().into_iter()
+                .map(|(species, count)| (self.registry.name(species).to_string(), count)).collect(),
+            deaths: self.statistics.death_counts().into_iter()
+                .map(|(species, cause, count)| (self.registry.name(species).to_string(), cause, count)).collect(),
+            grass: self.grass.iter().map(|(entity, pos, health, age, timer, count, bonus, key)| GrassSnapshot {
+                entity: entity.to_bits(),
+                position: pos.0.to_array(),
+                health: health.0,
+                age: TimerSnapshot::capture(age),
+                reproduction_timer: TimerSnapshot::capture(timer),
+                neighbor_count: count.0,
+                key: Some(key.0),
+                growth_bonus: bonus.0,
+            }).collect(),
+            animals: capture_animals(&self.animals, &self.registry),
+            carcasses: self.carcasses.iter().map(|(entity, carcass, pos)| CarcassSnapshot {
+                entity: entity.to_bits(),
+                position: pos.0.to_array(),
+                species: self.registry.name(carcass.species).to_string(),
+                biomass: carcass.biomass,
+            }).collect(),
+            soil: self.soil.as_ref().map(|soil| soil.cells().to_vec()),
+            nutrient_initial_total: self.ledger.as_ref().and_then(|ledger| ledger.initial_total),
+        }
+    }
+}
+
+fn capture_animals<T: SnapshotAgent<State = AnimalState>>(query: &Query<AnimalData<T>>, registry: &SpeciesRegistry) -> Vec<AnimalSnapshot> {
+    query.iter().map(|(entity, agent, &species, pos, movement, health, energy, age, wander, genome, sex, juvenile, pregnancy, hydration, (pursuit, key, drink_point))| AnimalSnapshot {
+        entity: entity.to_bits(),
+        species: registry.name(species).to_string(),
+        position: pos.0.to_array(),
+        speed: movement.speed,
+        direction: movement.direction.to_array(),
+        health: health.0,
+        energy: energy.0,
+        age: TimerSnapshot::capture(age),
+        wander: TimerSnapshot::capture(wander),
+        genome: *genome,
+        agent: agent.capture(),
+        sex: sex.copied(),
+        key: Some(key.0),
+        juvenile,
+        pregnancy: pregnancy.map(|p| PregnancySnapshot {
+            timer: TimerSnapshot::capture(&p.timer),
+            father: p.father,
+            energy: p.energy,
+        }),
+        hydration: hydration.map(|h| h.0),
+        drink_point: drink_point.and_then(|p| p.0).map(|p| p.to_array()),
+        pursuit: PursuitSnapshot {
+            elapsed: pursuit.elapsed,
+            distance: pursuit.distance,
+            last_position: pursuit.last_position.map(|p| p.to_array()),
+            rest: pursuit.rest,
+        },
+    }).collect()
+}
+
+///
+/// 定时保存快照
+///
+#[derive(Resource)]
+pub struct SnapshotWriter {
+    path: String,
+    timer: Timer,
+}
+
+pub struct SnapshotPlugin;
+
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding SnapshotPlugin");
+        let writer = config.snapshot_path.clone().map(|path| SnapshotWriter {
+            path,
+            timer: Timer::from_seconds(config.snapshot_interval_secs, TimerMode::Repeating),
+        });
+        let load_path = config.load_snapshot.clone();
+        if let Some(writer) = writer {
+            app.insert_resource(writer)
+                .add_systems(FixedLast, save_snapshot_periodically.after(count_lifecycle_events))
+                .add_systems(Last, save_snapshot_on_exit);
+        }
+        if let Some(path) = load_path {
+            let snapshot = Snapshot::from_file(&path).unwrap_or_else(|e| {
+                panic!("Error loading snapshot {}: {}", path, e);
+            });
+            info!("Restoring snapshot {} at {} simulated seconds", path, snapshot.elapsed.as_secs_f32());
+            app.insert_resource(PendingSnapshot(snapshot))
+                .add_systems(Startup, restore_snapshot);
+        }
+    }
+}
+
+fn write_snapshot(writer: &SnapshotWriter, source: &SnapshotSource) {
+    if let Err(e) = source.capture().save(&writer.path) {
+        error!("Error in write_snapshot, failed to save {}: {}", writer.path, e);
+    }
+}
+
+// 应该被放在 FixedLast 里并置于出生死亡统计之后，保存的是本帧结束时的状态
+pub fn save_snapshot_periodically(
+    time: Res<Time>,
+    mut writer: ResMut<SnapshotWriter>,
+    source: SnapshotSource,
+) {
+    if writer.timer.tick(time.delta()).just_finished() {
+        write_snapshot(&writer, &source);
+    }
+}
+
+// 退出时保存一次，无窗口模式下即为模拟结束时的状态
+pub fn save_snapshot_on_exit(
+    mut exit: EventReader<AppExit>,
+    writer: Res<SnapshotWriter>,
+    source: SnapshotSource,
+) {
+    if exit.read().next().is_some() {
+        write_snapshot(&writer, &source);
+    }
+}
+
+// 等待在 Startup 中恢复的快照
+#[derive(Resource)]
+pub struct PendingSnapshot(pub Snapshot);
+
+///
+/// 从快照恢复模拟状态，代替 setup 生成初始实体。
+/// 先为所有实体预留新的编号，再插入组件，这样 Agent 中对其他实体的引用可以直接映射到新编号；
+/// 保存时已经不存在的实体映射为 Entity::PLACEHOLDER，由各状态机按目标已死亡处理。
+/// 各空间索引重新创建，实体插入时由观察者重新填充。
+///
+pub fn restore_snapshot(
+    mut commands: Commands,
+    pending: Res<PendingSnapshot>,
+    config: Res<Config>,
+    registry: Res<SpeciesRegistry>,
+    bounds: Res<WorldBounds>,
+    nutrients: (Option<ResMut<SoilNutrients>>, Option<ResMut<NutrientLedger>>),
+    mut time: ResMut<Time<Fixed>>,
+) {
+    let snapshot = &pending.0;
+    let species = |name: &str| registry.find(name)
+        .unwrap_or_else(|| panic!("Error in restore_snapshot, unknown species {:?} in snapshot", name));
+    let births: Vec<_> = snapshot.births.iter().map(|(name, count)| (species(name), *count)).collect();
+    let deaths: Vec<_> = snapshot.deaths.iter().map(|(name, cause, count)| (species(name), *cause, *count)).collect();
+    time.advance_to(snapshot.elapsed);
+    commands.insert_resource(snapshot.sim_rng.clone());
+    commands.insert_resource(EscapeTimer(snapshot.escape_timer.restore()));
+    commands.insert_resource(LifecycleStatistics::from_counts(&births, &deaths));
+    commands.insert_resource(SpatialIndex::<Grass>::new(config.grass_spatial_index, &bounds));
+    commands.insert_resource(SpeciesIndex::<AnimalAgent>::new(&registry, &bounds));
+    commands.insert_resource(SpatialIndex::<Carcass>::new(SpatialIndexKind::KdTree, &bounds));
+    info!("Simulation seed: {}", snapshot.sim_rng.seed());
+    let (soil, ledger) = nutrients;
+    if let (Some(mut soil), Some(cells)) = (soil, snapshot.soil.as_ref()) {
+        soil.set_cells(cells);
+    }
+    // 较早的快照中没有养分总量时，由恢复后第一次统计重新确定
+    if let Some(mut ledger) = ledger {
+        ledger.initial_total = snapshot.nutrient_initial_total;
+    }
+
+    let mut entity_map = EntityHashMap::default();
+    let saved = snapshot.grass.iter().map(|g| g.entity)
+        .chain(snapshot.animals.iter().map(|a| a.entity))
+        .chain(snapshot.carcasses.iter().map(|c| c.entity));
+    for entity in saved {
+        entity_map.insert(Entity::from_bits(entity), commands.spawn_empty().id());
+    }
+    let remap = |entity: u64| entity_map.get(&Entity::from_bits(entity)).copied()
+        .unwrap_or(Entity::PLACEHOLDER);
+
+    for grass in snapshot.grass.iter() {
+        let pos = Vec2::from_array(grass.position);
+        let mut entity = commands.entity(remap(grass.entity));
+        // 键需要在物种之前插入，否则出生时会分配新的键
+        if let Some(key) = grass.key {
+            entity.insert(RngKey(key));
+        }
+        entity
+            .insert(GrassBundle::from_config(&config, pos.x, pos.y))
+            .insert((
+                Health(grass.health),
+                Age::from_timer(grass.age.restore()),
+                GrassReproductionTimer::from_timer(grass.reproduction_timer.restore()),
+                GrassGrowthBonus(grass.growth_bonus),
+            ));
+    }
+    restore_animals::<AnimalAgent>(&mut commands, &registry, &snapshot.animals, &remap);
+    for carcass in snapshot.carcasses.iter() {
+        let pos = Vec2::from_array(carcass.position);
+        commands.entity(remap(carcass.entity))
+            .insert(CarcassBundle::new(&config, species(&carcass.species), carcass.biomass, pos));
+    }
+    // 邻居计数在草插入时由观察者重新计算，最后覆盖为保存时的值
+    for grass in snapshot.grass.iter() {
+        commands.entity(remap(grass.entity)).insert(GrassNeighborCount(grass.neighbor_count));
+    }
+    commands.remove_resource::<PendingSnapshot>();
+}
+
+fn restore_animals<T: SnapshotAgent<State = AnimalState>>(
+    commands: &mut Commands,
+    registry: &SpeciesRegistry,
+    snapshots: &[AnimalSnapshot],
+    remap: &impl Fn(u64) -> Entity,
+) {
+    for animal in snapshots.iter() {
+        let pos = Vec2::from_array(animal.position);
+        let species = registry.find(&animal.species)
+            .unwrap_or_else(|| panic!("Error in restore_snapshot, unknown species {:?} in snapshot", animal.species));
+        let mut entity = commands.entity(remap(animal.entity));
+        if let Some(key) = animal.key {
+            entity.insert(RngKey(key));
+        }
+        if let Some(sex) = animal.sex {
+            entity.insert(sex);
+        }
+        if let Some(pregnancy) = animal.pregnancy.as_ref() {
+            entity.insert(Pregnancy {
+                timer: pregnancy.timer.restore(),
+                father: pregnancy.father,
+                energy: pregnancy.energy,
+            });
+        }
+        entity.insert(AnimalBundle::new(species, registry.get(species), animal.genome, pos.x, pos.y));
+        // 幼年个体插入时会按比例降低速度与生命值，随后被保存时的值覆盖
+        if animal.juvenile {
+            entity.insert(Juvenile);
+        }
+        let capacity = registry.get(species).thirst.as_ref().map(|t| t.capacity);
+        if let Some(hydration) = animal.hydration.or(capacity) {
+            entity.insert((Hydration(hydration), DrinkPoint(animal.drink_point.map(Vec2::from_array))));
+        }
+        entity
+            .insert((
+                T::restore(&animal.agent, animal.agent.target.map(remap)),
+                Movement {
+                    speed: animal.speed,
+                    direction: Vec2::from_array(animal.direction),
+                },
+                Health(animal.health),
+                Energy(animal.energy),
+                Age::from_timer(animal.age.restore()),
+                Wander(animal.wander.restore()),
+                Pursuit {
+                    elapsed: animal.pursuit.elapsed,
+                    distance: animal.pursuit.distance,
+                    last_position: animal.pursuit.last_position.map(Vec2::from_array),
+                    rest: animal.pursuit.rest,
+                },
+            ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use super::*;
+    use crate::test_app::{all_features_species, headless_app, run_until};
+    use crate::water::WaterSource;
+
+    fn config() -> Config {
+        Config {
+            seed: Some(11),
+            species: all_features_species(),
+            water_sources: vec![WaterSource { x: 0.0, y: 0.0, radius: 60.0 }, WaterSource { x: 300.0, y: -250.0, radius: 40.0 }],
+            nutrient_cycle: true,
+            nutrient_conservation_check: true,
+            ..default()
+        }
+    }
+
+    fn capture(app: &mut App) -> Snapshot {
+        app.world_mut().run_system_once(|source: SnapshotSource| source.capture()).unwrap()
+    }
+
+    // 恢复后实体编号不同，把快照中的实体编号换成随机流的键（尸体没有键，换成它在列表中的位置）再比较
+    fn normalize(mut snapshot: Snapshot) -> String {
+        let mut ids = bevy::utils::HashMap::new();
+        for grass in snapshot.grass.iter() {
+            ids.insert(grass.entity, grass.key.unwrap());
+        }
+        for animal in snapshot.animals.iter() {
+            ids.insert(animal.entity, animal.key.unwrap());
+        }
+        for (i, carcass) in snapshot.carcasses.iter().enumerate() {
+            ids.insert(carcass.entity, u64::MAX - i as u64);
+        }
+        let id = |entity: u64| ids.get(&entity).copied().unwrap_or(u64::MAX - u32::MAX as u64);
+        snapshot.grass.iter_mut().for_each(|g| g.entity = id(g.entity));
+        snapshot.carcasses.iter_mut().for_each(|c| c.entity = id(c.entity));
+        for animal in snapshot.animals.iter_mut() {
+            animal.entity = id(animal.entity);
+            animal.agent.target = animal.agent.target.map(id);
+        }
+        ron::to_string(&snapshot).unwrap()
+    }
+
+    // 在第 N 秒保存快照并恢复，继续运行 M 秒后与不中断的运行完全相同
+    #[test]
+    fn restored_run_matches_uninterrupted_run() {
+        let (save_at, end) = (60.0, 120.0);
+        let mut uninterrupted = headless_app(config());
+        run_until(&mut uninterrupted, save_at);
+        let path = std::env::temp_dir().join(format!("closed_ecosphere_snapshot_{}.ron", std::process::id()));
+        capture(&mut uninterrupted).save(&path).unwrap();
+        let mut restored = headless_app(Config {
+            load_snapshot: Some(path.to_str().unwrap().to_string()),
+            ..config()
+        });
+        std::fs::remove_file(&path).unwrap();
+        run_until(&mut uninterrupted, end);
+        run_until(&mut restored, end);
+        let expected = capture(&mut uninterrupted);
+        assert!(!expected.animals.is_empty() && !expected.carcasses.is_empty());
+        assert!(expected.nutrient_initial_total.is_some());
+        assert_eq!(normalize(expected), normalize(capture(&mut restored)));
+    }
+}
@@ -1,83 +1,148 @@
-use std::collections::HashMap;
 use bevy::math::Vec2;
 use bevy::prelude::*;
+use serde::Deserialize;
+use crate::grid_index::GridIndex;
+use crate::kd_tree_index::KdTreeIndex;
+use crate::lifecycle::Species;
 use crate::movemement::MyPosition;
+use crate::species::SpeciesRegistry;
 use crate::type_component::TypeComponent;
-use kdtree::KdTree;
-use num_traits::Float;
-
-pub fn euclidean<T: Float>(a: &[T], b: &[T]) -> T {
-    debug_assert_eq!(a.len(), b.len());
-    a.iter()
-        .zip(b.iter())
-        .map(|(x, y)| ((*x) - (*y)) * ((*x) - (*y)))
-        .fold(T::zero(), ::std::ops::Add::add)
-        .sqrt()
+use crate::world_bounds::WorldBounds;
+
+///
+/// 空间索引后端需要提供的操作。
+/// 环面区域上距离按环绕后的最短距离计算；返回多个实体的查询结果都按距离从近到远排列，
+/// 距离相同时按实体排列
+///
+pub trait SpatialBackend: Send + Sync {
+    fn set_bounds(&mut self, bounds: &WorldBounds);
+    fn insert(&mut self, entity: Entity, pos: Vec2);
+    fn remove(&mut self, entity: Entity);
+    fn update(&mut self, entity: Entity, pos: Vec2);
+    fn get_nearest(&self, pos: Vec2) -> Option<(f32,&Entity)>;
+    ///
+    /// 若 index 内包含了实体自身，通过这个方法获得第二近的实体
+    ///
+    fn get_second_nearest(&self, pos: Vec2) -> Option<(f32,&Entity)>;
+    fn get_in_radius(&self, pos: Vec2, radius: f32) -> Vec<(f32,&Entity)>;
+    fn get_k_nearest(&self, pos: Vec2, k: usize) -> Vec<(f32,&Entity)>;
+    fn get_pos(&self, entity: Entity) -> Option<Vec2>;
+    ///
+    /// 距离不超过 max_distance 的实体中，满足 predicate 的最近的一个。
+    /// 逐步扩大 k 近邻查询的 k，直到找到满足条件的实体或超出距离
+    ///
+    fn get_nearest_matching(&self, pos: Vec2, max_distance: f32, predicate: &dyn Fn(Entity) -> bool) -> Option<(f32,&Entity)> {
+        let mut k = 8;
+        loop {
+            let nearest = self.get_k_nearest(pos, k);
+            for &(distance, entity) in nearest.iter() {
+                if distance > max_distance {
+                    return None;
+                }
+                if predicate(*entity) {
+                    return Some((distance, entity));
+                }
+            }
+            if nearest.len() < k {
+                return None;
+            }
+            k *= 4;
+        }
+    }
+}
+
+// 空间索引后端的选择，可在配置文件中按物种指定
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub enum SpatialIndexKind {
+    #[default]
+    KdTree,
+    // 均匀网格，cell_size 为格子边长，取与常用查询半径相近的值较好
+    Grid { cell_size: f32 },
 }
 
 #[derive(Resource)]
 pub struct SpatialIndex<T: Component + TypeComponent> {
-    kd_tree: KdTree<f32, Entity, [f32;2]>,
-    entity_map: HashMap<Entity, Vec2>,
+    backend: Box<dyn SpatialBackend>,
     _marker: std::marker::PhantomData<T>,
 }
 
 impl<T: Component + TypeComponent> SpatialIndex<T> {
+    pub fn new(kind: SpatialIndexKind, bounds: &WorldBounds) -> Self {
+        let mut backend: Box<dyn SpatialBackend> = match kind {
+            SpatialIndexKind::KdTree => Box::new(KdTreeIndex::default()),
+            SpatialIndexKind::Grid { cell_size } => Box::new(GridIndex::new(cell_size)),
+        };
+        backend.set_bounds(bounds);
+        SpatialIndex {
+            backend,
+            _marker: std::marker::PhantomData,
+        }
+    }
+    pub fn set_bounds(&mut self, bounds: &WorldBounds) {
+        self.backend.set_bounds(bounds);
+    }
     pub fn remove(&mut self, entity: Entity) {
-        self.kd_tree.remove(&self.entity_map[&entity].into(), &entity).unwrap();
-        self.entity_map.remove(&entity).unwrap();
+        self.backend.remove(entity);
     }
     pub fn insert(&mut self, entity: Entity, pos: Vec2) {
-        self.kd_tree.add([pos.x, pos.y], entity).unwrap();
-        self.entity_map.insert(entity, pos);
+        self.backend.insert(entity, pos);
     }
+    ///
+    /// 距离不超过 radius 的所有实体，按距离从近到远排列
+    ///
     pub fn get_in_radius(&self, pos: Vec2, radius: f32) -> Vec<(f32,&Entity)> {
-        self.kd_tree.within(&[pos.x, pos.y], radius, &euclidean).unwrap_or_else(|e| {
-            panic!("Error in get_in_radius: {:?}", e);
-        })
+        self.backend.get_in_radius(pos, radius)
+    }
+    ///
+    /// 最近的 k 个实体，按距离从近到远排列
+    ///
+    pub fn get_k_nearest(&self, pos: Vec2, k: usize) -> Vec<(f32,&Entity)> {
+        self.backend.get_k_nearest(pos, k)
+    }
+    ///
+    /// 距离不超过 max_distance 且满足 predicate 的最近的实体
+    ///
+    pub fn get_nearest_matching(&self, pos: Vec2, max_distance: f32, predicate: impl Fn(Entity) -> bool) -> Option<(f32,&Entity)> {
+        self.backend.get_nearest_matching(pos, max_distance, &predicate)
     }
     pub fn get_nearest(&self, pos: Vec2) -> Option<(f32,&Entity)> {
-        let nearest = self.kd_tree.nearest(&[pos.x, pos.y], 1, &euclidean).unwrap_or_else(|e| {
-            panic!("Error in get_nearest: {:?}", e);
-        });
-        if nearest.len() > 0 {
-            Some(nearest[0])
-        } else {
-            None
-        }
+        self.backend.get_nearest(pos)
     }
     ///
     /// 若 index 内包含了实体自身，通过这个方法获得第二近的实体
     ///
     pub fn get_second_nearest(&self, pos: Vec2) -> Option<(f32,&Entity)> {
-        let nearest = self.kd_tree.nearest(&[pos.x, pos.y], 2, &euclidean).unwrap_or_else(|e| {
-            panic!("Error in get_second_nearest: {:?}", e);
-        });
-        if nearest.len() > 1 {
-            Some(nearest[1])
-        } else {
-            None
-        }
+        self.backend.get_second_nearest(pos)
     }
     pub fn update(&mut self, entity: Entity, pos: Vec2) {
-        let old_pos = self.entity_map.get(&entity).unwrap();
-        self.kd_tree.remove(&[old_pos.x,old_pos.y], &entity).unwrap();
-        self.kd_tree.add([pos.x, pos.y], entity).unwrap();
-        self.entity_map.insert(entity, pos);
+        self.backend.update(entity, pos);
     }
     pub fn get_pos(&self, entity: Entity) -> Option<Vec2> {
-        self.entity_map.get(&entity).copied()
+        self.backend.get_pos(entity)
     }
 }
 impl<T: Component + TypeComponent> Default for SpatialIndex<T>{
     fn default() -> Self {
         SpatialIndex {
-            kd_tree: KdTree::new(2),
-            entity_map: HashMap::default(),
+            backend: Box::new(KdTreeIndex::default()),
             _marker: std::marker::PhantomData,
         }
     }
 }
+///
+/// 按距离排序，距离相同时按实体排序使结果确定
+///
+pub fn sort_by_distance(result: &mut [(f32,&Entity)]) {
+    result.sort_unstable_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(b.1)));
+}
+///
+/// 同 sort_by_distance，并且同一实体从不同方向被找到多次时只保留最近的一次
+///
+pub fn sort_and_dedup(result: &mut Vec<(f32,&Entity)>) {
+    sort_by_distance(result);
+    let mut seen = bevy::ecs::entity::EntityHashSet::default();
+    result.retain(|(_, e)| seen.insert(**e));
+}
 pub fn on_entity_birth<T: TypeComponent>(
     trigger: Trigger<OnAdd, T>,
     query: Query<(Entity, &MyPosition),With<T>>,
@@ -93,3 +158,158 @@ pub fn on_entity_death<T: TypeComponent>(
 ){
     index.remove(trigger.entity());
 }
+
+///
+/// 按物种分开的空间索引，每个动物物种一个，后端由物种声明中的 spatial_index 决定
+///
+#[derive(Resource)]
+pub struct SpeciesIndex<T: Component + TypeComponent> {
+    indices: Vec<SpatialIndex<T>>,
+}
+
+impl<T: Component + TypeComponent> SpeciesIndex<T> {
+    pub fn new(registry: &SpeciesRegistry, bounds: &WorldBounds) -> Self {
+        SpeciesIndex {
+            indices: registry.iter().map(|(_, d)| SpatialIndex::new(d.spatial_index, bounds)).collect(),
+        }
+    }
+    ///
+    /// 该动物物种的空间索引，species 为草时 panic
+    ///
+    pub fn get(&self, species: Species) -> &SpatialIndex<T> {
+        match species {
+            Species::Animal(i) => &self.indices[i],
+            Species::Grass => panic!("SpeciesIndex only indexes animals"),
+        }
+    }
+    pub fn get_mut(&mut self, species: Species) -> &mut SpatialIndex<T> {
+        match species {
+            Species::Animal(i) => &mut self.indices[i],
+            Species::Grass => panic!("SpeciesIndex only indexes animals"),
+        }
+    }
+}
+
+pub fn on_animal_birth<T: TypeComponent>(
+    trigger: Trigger<OnAdd, T>,
+    query: Query<(&Species, &MyPosition), With<T>>,
+    mut index: ResMut<SpeciesIndex<T>>
+){
+    let (&species, pos) = query.get(trigger.entity()).unwrap();
+    index.get_mut(species).insert(trigger.entity(), pos.0);
+}
+
+pub fn on_animal_death<T: TypeComponent>(
+    trigger: Trigger<OnRemove, T>,
+    query: Query<&Species, With<T>>,
+    mut index: ResMut<SpeciesIndex<T>>
+){
+    if let Ok(&species) = query.get(trigger.entity()) {
+        index.get_mut(species).remove(trigger.entity());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use super::*;
+    use crate::world_bounds::EdgeBehavior;
+
+    const SIZE: f32 = 1000.0;
+
+    fn random_point(rng: &mut StdRng) -> Vec2 {
+        Vec2::new(rng.gen_range(-SIZE / 2.0..SIZE / 2.0), rng.gen_range(-SIZE / 2.0..SIZE / 2.0))
+    }
+
+    // 两个后端插入同一组点
+    fn build(edge: EdgeBehavior, count: u32, rng: &mut StdRng) -> (KdTreeIndex, GridIndex) {
+        let bounds = WorldBounds::new(SIZE, SIZE, edge);
+        let mut kd_tree = KdTreeIndex::default();
+        let mut grid = GridIndex::new(60.0);
+        kd_tree.set_bounds(&bounds);
+        grid.set_bounds(&bounds);
+        for i in 0..count {
+            let pos = random_point(rng);
+            kd_tree.insert(Entity::from_raw(i), pos);
+            grid.insert(Entity::from_raw(i), pos);
+        }
+        (kd_tree, grid)
+    }
+
+    fn assert_same(kd_tree: &[(f32,&Entity)], grid: &[(f32,&Entity)]) {
+        assert_eq!(kd_tree.iter().map(|r| *r.1).collect::<Vec<_>>(),
+                   grid.iter().map(|r| *r.1).collect::<Vec<_>>());
+        for (a, b) in kd_tree.iter().zip(grid.iter()) {
+            assert!((a.0 - b.0).abs() < 1e-3, "distance {} != {}", a.0, b.0);
+        }
+    }
+
+    fn assert_queries_match(kd_tree: &KdTreeIndex, grid: &GridIndex, pos: Vec2) {
+        assert_same(kd_tree.get_nearest(pos).as_slice(), grid.get_nearest(pos).as_slice());
+        assert_same(kd_tree.get_second_nearest(pos).as_slice(), grid.get_second_nearest(pos).as_slice());
+        assert_same(&kd_tree.get_k_nearest(pos, 10), &grid.get_k_nearest(pos, 10));
+        assert_same(&kd_tree.get_in_radius(pos, 80.0), &grid.get_in_radius(pos, 80.0));
+        let predicate = |entity: Entity| entity.index().is_multiple_of(7);
+        assert_same(kd_tree.get_nearest_matching(pos, 150.0, &predicate).as_slice(),
+                    grid.get_nearest_matching(pos, 150.0, &predicate).as_slice());
+    }
+
+    #[test]
+    fn backends_agree() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (kd_tree, grid) = build(EdgeBehavior::Clamp, 500, &mut rng);
+        for _ in 0..100 {
+            assert_queries_match(&kd_tree, &grid, random_point(&mut rng));
+        }
+    }
+
+    // 环面上靠近接缝与角落的查询会找到区域另一侧的实体
+    #[test]
+    fn backends_agree_across_wrap_seam() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let (kd_tree, grid) = build(EdgeBehavior::Wrap, 500, &mut rng);
+        let mut crossed = false;
+        for _ in 0..100 {
+            let edge = SIZE / 2.0 - rng.gen_range(0.0..20.0);
+            let along = rng.gen_range(-SIZE / 2.0..SIZE / 2.0);
+            for pos in [Vec2::new(edge, along), Vec2::new(along, -edge), Vec2::new(-edge, edge)] {
+                assert_queries_match(&kd_tree, &grid, pos);
+                crossed |= grid.get_in_radius(pos, 80.0).iter()
+                    .any(|&(_, &e)| grid.get_pos(e).unwrap().distance(pos) > SIZE / 2.0);
+            }
+        }
+        assert!(crossed);
+    }
+
+    #[test]
+    fn backends_agree_after_remove_and_update() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for edge in [EdgeBehavior::Clamp, EdgeBehavior::Wrap] {
+            let (mut kd_tree, mut grid) = build(edge, 300, &mut rng);
+            for i in 0..300 {
+                let entity = Entity::from_raw(i);
+                match i % 3 {
+                    0 => {
+                        kd_tree.remove(entity);
+                        grid.remove(entity);
+                    }
+                    1 => {
+                        let pos = random_point(&mut rng);
+                        kd_tree.update(entity, pos);
+                        grid.update(entity, pos);
+                    }
+                    _ => {}
+                }
+            }
+            for i in 0..300 {
+                let entity = Entity::from_raw(i);
+                assert_eq!(kd_tree.get_pos(entity), grid.get_pos(entity));
+                assert_eq!(grid.get_pos(entity).is_none(), i % 3 == 0);
+            }
+            for _ in 0..100 {
+                assert_queries_match(&kd_tree, &grid, random_point(&mut rng));
+            }
+        }
+    }
+}
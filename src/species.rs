@@ -0,0 +1,249 @@
+use std::path::Path;
+use bevy::prelude::*;
+use serde::Deserialize;
+use crate::calendar::SeasonalCurve;
+use crate::carcass::ScavengeConfig;
+use crate::chase::ChaseConfig;
+use crate::config::{ColorConfig, Config, ConfigError, ShapeConfig};
+use crate::escape_system::EscapeConfig;
+use crate::herding::HerdingConfig;
+use crate::lifecycle::Species;
+use crate::pack_hunting::PackConfig;
+use crate::reproduction::ReproductionConfig;
+use crate::spatial_index::SpatialIndexKind;
+use crate::water::{AmbushConfig, ThirstConfig};
+
+// 草在食谱与捕食者列表中的名称，动物物种不能使用
+pub const GRASS_NAME: &str = "Grass";
+
+///
+/// 动物物种的声明：名称、模型与颜色、各项数值、食谱、躲避的捕食者，以及各项可选的能力。
+/// 能力为 None 时该物种没有这项能力，例如没有 flee 的物种不会逃跑。
+/// 缺省字段取 Default 中的值，句柄与解析后的物种引用不参与反序列化
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct SpeciesDefinition {
+    // 物种名称，在其它物种的食谱与捕食者列表中引用
+    pub name: String,
+    pub initial_count: usize,
+    // 模型，以及空闲时的颜色
+    pub shape: ShapeConfig,
+    pub color: ColorConfig,
+    pub health: f32,
+    // 寿命，按秒
+    pub lifespan: f32,
+    // 初始能量
+    pub energy: f32,
+    // 被捕食时捕食者得到的能量
+    pub gain: f32,
+    pub damage: f32,
+    pub attack_cooling_time: f32,
+    pub eating_time: f32,
+    pub speed: f32,
+    // 速度随季节与昼夜变化的倍率
+    pub speed_curve: SeasonalCurve,
+    // 捕食活跃度随季节与昼夜变化的倍率，按比例缩放寻找猎物的感知半径，为 0 时不捕食
+    pub hunting_curve: SeasonalCurve,
+    // 感知猎物的半径
+    pub perception_radius: f32,
+    // 闲逛时更换方向的间隔
+    pub wander_interval: f32,
+    pub spatial_index: SpatialIndexKind,
+    // 死后尸体的生物量，为 0 时不留下尸体
+    pub carcass_biomass: f32,
+    // 捕食的物种，草为 "Grass"
+    pub diet: Vec<String>,
+    // 逃跑时躲避的物种
+    pub predators: Vec<String>,
+    // 能力
+    pub flee: Option<EscapeConfig>,
+    pub reproduction: Option<ReproductionConfig>,
+    pub herding: Option<HerdingConfig>,
+    pub pack: Option<PackConfig>,
+    pub scavenge: Option<ScavengeConfig>,
+    pub thirst: Option<ThirstConfig>,
+    pub ambush: Option<AmbushConfig>,
+    pub chase: Option<ChaseConfig>,
+    #[serde(skip)]
+    pub shape_handle: Handle<Mesh>,
+    #[serde(skip)]
+    pub material: Handle<ColorMaterial>,
+    // 由 SpeciesRegistry 根据 diet 与 predators 解析
+    #[serde(skip)]
+    pub prey: Vec<Species>,
+    #[serde(skip)]
+    pub predator_species: Vec<Species>,
+}
+
+impl Default for SpeciesDefinition {
+    fn default() -> Self {
+        SpeciesDefinition {
+            name: String::new(),
+            initial_count: 0,
+            shape: ShapeConfig::Circle{ radius: 10.0 },
+            color: ColorConfig(1.0, 1.0, 1.0),
+            health: 50.0,
+            lifespan: 100.0,
+            energy: 50.0,
+            gain: 50.0,
+            damage: 10.0,
+            attack_cooling_time: 1.0,
+            eating_time: 2.0,
+            speed: 20.0,
+            speed_curve: SeasonalCurve::default(),
+            hunting_curve: SeasonalCurve::default(),
+            perception_radius: 200.0,
+            wander_interval: 3.0,
+            spatial_index: SpatialIndexKind::KdTree,
+            carcass_biomass: 0.0,
+            diet: Vec::new(),
+            predators: Vec::new(),
+            flee: None,
+            reproduction: None,
+            herding: None,
+            pack: None,
+            scavenge: None,
+            thirst: None,
+            ambush: None,
+            chase: None,
+            shape_handle: Handle::default(),
+            material: Handle::default(),
+            prey: Vec::new(),
+            predator_species: Vec::new(),
+        }
+    }
+}
+
+impl SpeciesDefinition {
+    // 性成熟年龄，不能繁殖的物种出生即成年
+    pub fn maturity_age(&self) -> f32 {
+        self.reproduction.as_ref().map_or(0.0, |r| r.juvenile.maturity_age)
+    }
+}
+
+///
+/// 内置的物种：吃草、躲避虎的牛，以及吃牛、食腐的虎。没有指定物种文件时使用
+///
+pub fn default_species() -> Vec<SpeciesDefinition> {
+    vec![
+        SpeciesDefinition {
+            name: "Cow".to_string(),
+            initial_count: 20,
+            shape: ShapeConfig::Rectangle{ width: 20.0, height: 20.0 },
+            carcass_biomass: 80.0,
+            diet: vec![GRASS_NAME.to_string()],
+            predators: vec!["Tiger".to_string()],
+            flee: Some(EscapeConfig::default()),
+            reproduction: Some(ReproductionConfig::default()),
+            ..default()
+        },
+        SpeciesDefinition {
+            name: "Tiger".to_string(),
+            initial_count: 2,
+            shape: ShapeConfig::RegularPolygon{ circumradius: 20.0, sides: 6 },
+            health: 100.0,
+            lifespan: 200.0,
+            energy: 100.0,
+            gain: 100.0,
+            damage: 20.0,
+            attack_cooling_time: 2.0,
+            eating_time: 5.0,
+            speed: 40.0,
+            perception_radius: 400.0,
+            wander_interval: 5.0,
+            carcass_biomass: 150.0,
+            diet: vec!["Cow".to_string()],
+            reproduction: Some(ReproductionConfig {
+                energy_threshold: 200.0,
+                energy_cost: 80.0,
+                search_radius: 1000.0,
+                reproduction_radius: 80.0,
+                mating_time: 10.0,
+                ..default()
+            }),
+            scavenge: Some(ScavengeConfig::default()),
+            ..default()
+        },
+    ]
+}
+
+///
+/// 从 RON 物种文件读取物种列表
+///
+pub fn load_species(path: impl AsRef<Path>) -> Result<Vec<SpeciesDefinition>, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+    ron::from_str(&text).map_err(ConfigError::Parse)
+}
+
+///
+/// 运行时的物种表。动物物种按声明顺序编号为 Species::Animal(i)，
+/// 创建时把食谱与捕食者列表中的名称解析为物种，名称重复或引用了不存在的物种时 panic
+///
+#[derive(Resource)]
+pub struct SpeciesRegistry {
+    species: Vec<SpeciesDefinition>,
+}
+
+impl SpeciesRegistry {
+    pub fn new(definitions: &[SpeciesDefinition]) -> Self {
+        let names: Vec<&str> = definitions.iter().map(|d| d.name.as_str()).collect();
+        for (i, name) in names.iter().enumerate() {
+            if name.is_empty() || *name == GRASS_NAME || names[..i].contains(name) {
+                panic!("Invalid species name {:?}: names must be non-empty, unique and not {:?}", name, GRASS_NAME);
+            }
+        }
+        let resolve = |name: &String| -> Species {
+            if name == GRASS_NAME {
+                return Species::Grass;
+            }
+            names.iter().position(|n| n == name).map(Species::Animal)
+                .unwrap_or_else(|| panic!("Unknown species {:?} in species definitions", name))
+        };
+        let species = definitions.iter().map(|definition| {
+            let mut definition = definition.clone();
+            definition.prey = definition.diet.iter().map(resolve).collect();
+            definition.predator_species = definition.predators.iter().map(resolve).collect();
+            definition
+        }).collect();
+        SpeciesRegistry { species }
+    }
+    pub fn len(&self) -> usize {
+        self.species.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.species.is_empty()
+    }
+    // 按编号顺序列出所有动物物种
+    pub fn iter(&self) -> impl Iterator<Item = (Species, &SpeciesDefinition)> {
+        self.species.iter().enumerate().map(|(i, d)| (Species::Animal(i), d))
+    }
+    ///
+    /// 动物物种的声明，species 为草时 panic
+    ///
+    pub fn get(&self, species: Species) -> &SpeciesDefinition {
+        match species {
+            Species::Animal(i) => &self.species[i],
+            Species::Grass => panic!("Grass has no species definition"),
+        }
+    }
+    pub fn find(&self, name: &str) -> Option<Species> {
+        if name == GRASS_NAME {
+            return Some(Species::Grass);
+        }
+        self.species.iter().position(|d| d.name == name).map(Species::Animal)
+    }
+    pub fn name(&self, species: Species) -> &str {
+        match species {
+            Species::Grass => GRASS_NAME,
+            Species::Animal(i) => &self.species[i].name,
+        }
+    }
+    // 捕食该物种的个体得到的能量
+    pub fn energy_gain(&self, species: Species, config: &Config) -> f32 {
+        match species {
+            Species::Grass => config.grass_gain,
+            species => self.get(species).gain,
+        }
+    }
+}
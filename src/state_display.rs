@@ -1,62 +1,41 @@
 use bevy::prelude::*;
+use crate::animal::{AnimalAgent, AnimalState};
 use crate::config::Config;
-use crate::cow_agent::{CowAgent, CowState};
-use crate::tiger_agent::{TigerAgent, TigerState};
+use crate::lifecycle::Species;
+use crate::species::SpeciesRegistry;
 
-pub fn cow_state_display(
-    mut query: Query<(&mut MeshMaterial2d<ColorMaterial>,&CowAgent)>,
+// 空闲时显示物种自身的颜色，其它状态的颜色所有物种共用
+pub fn animal_state_display(
+    mut query: Query<(&mut MeshMaterial2d<ColorMaterial>, &AnimalAgent, &Species)>,
     config: Res<Config>,
+    registry: Res<SpeciesRegistry>,
 ) {
-    query.par_iter_mut().for_each(|(mut mesh_material2d,cow_agent)| {
-        match cow_agent.state {
-            CowState::Idle => {
-                mesh_material2d.0 = config.idle_color.clone();
+    query.par_iter_mut().for_each(|(mut mesh_material2d, agent, &species)| {
+        match agent.state {
+            AnimalState::Idle => {
+                mesh_material2d.0 = registry.get(species).material.clone();
             }
-            CowState::Hunting => {
+            AnimalState::Hunting | AnimalState::Scavenging => {
                 mesh_material2d.0 = config.hunting_color.clone();
             }
-            CowState::AttackCooling => {
+            AnimalState::AttackCooling => {
                 mesh_material2d.0 = config.attack_cooling_color.clone();
             }
-            CowState::Eating => {
+            AnimalState::Eating => {
                 mesh_material2d.0 = config.eating_color.clone();
             }
-            CowState::SearchingMate => {
+            AnimalState::SearchingMate => {
                 mesh_material2d.0 = config.searching_mate_color.clone();
             }
-            CowState::Mating => {
+            AnimalState::Mating => {
                 mesh_material2d.0 = config.mating_color.clone();
             }
-            CowState::Fleeing => {
+            AnimalState::Fleeing => {
                 mesh_material2d.0 = config.escaping_color.clone();
             }
-        }
-    });
-}
-pub fn tiger_state_display(
-    mut query: Query<(&mut MeshMaterial2d<ColorMaterial>,&TigerAgent)>,
-    config: Res<Config>,
-) {
-    query.par_iter_mut().for_each(|(mut mesh_material2d,tiger_agent)| {
-        match tiger_agent.state {
-            TigerState::Idle => {
-                mesh_material2d.0 = config.idle_color.clone();
-            }
-            TigerState::Hunting => {
-                mesh_material2d.0 = config.hunting_color.clone();
-            }
-            TigerState::AttackCooling => {
-                mesh_material2d.0 = config.attack_cooling_color.clone();
-            }
-            TigerState::Eating => {
-                mesh_material2d.0 = config.eating_color.clone();
-            }
-            TigerState::SearchingMate => {
-                mesh_material2d.0 = config.searching_mate_color.clone();
-            }
-            TigerState::Mating => {
-                mesh_material2d.0 = config.mating_color.clone();
+            AnimalState::SeekingWater | AnimalState::Drinking => {
+                mesh_material2d.0 = config.drinking_color.clone();
             }
         }
     });
-}
\ No newline at end of file
+}
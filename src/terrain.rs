@@ -0,0 +1,461 @@
+use std::path::Path;
+use bevy::asset::RenderAssetUsages;
+use bevy::image::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use serde::Deserialize;
+use crate::config::{ColorConfig, Config, ConfigError};
+use crate::world_bounds::WorldBounds;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TerrainKind {
+    Grass,
+    Forest,
+    Water,
+    Rock,
+}
+impl TerrainKind {
+    pub const ALL: [TerrainKind; 4] = [TerrainKind::Grass, TerrainKind::Forest, TerrainKind::Water, TerrainKind::Rock];
+}
+
+///
+/// 一种地形的参数。地形图中的每个像素归入颜色最接近的地形，灰度图可以把各地形的颜色设为不同的灰度。
+/// fertility 为草在此处繁殖成功的概率，movement_cost 为穿过此处所需时间的倍数，
+/// passable 为 false 时动物不能进入
+///
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct TerrainType {
+    pub color: ColorConfig,
+    pub fertility: f32,
+    pub movement_cost: f32,
+    pub passable: bool,
+}
+impl Default for TerrainType {
+    fn default() -> Self {
+        TerrainType {
+            color: ColorConfig(0.0, 0.0, 0.0),
+            fertility: 1.0,
+            movement_cost: 1.0,
+            passable: true,
+        }
+    }
+}
+
+///
+/// 四种地形各自的参数
+///
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct TerrainConfig {
+    pub grass: TerrainType,
+    pub forest: TerrainType,
+    pub water: TerrainType,
+    pub rock: TerrainType,
+}
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        TerrainConfig {
+            grass: TerrainType {
+                color: ColorConfig(0.0, 1.0, 0.0),
+                fertility: 1.0,
+                movement_cost: 1.0,
+                passable: true,
+            },
+            forest: TerrainType {
+                color: ColorConfig(0.0, 0.4, 0.0),
+                fertility: 0.5,
+                movement_cost: 2.0,
+                passable: true,
+            },
+            water: TerrainType {
+                color: ColorConfig(0.0, 0.0, 1.0),
+                fertility: 0.0,
+                movement_cost: 1.0,
+                passable: false,
+            },
+            rock: TerrainType {
+                color: ColorConfig(0.5, 0.5, 0.5),
+                fertility: 0.0,
+                movement_cost: 1.0,
+                passable: false,
+            },
+        }
+    }
+}
+impl TerrainConfig {
+    pub fn get(&self, kind: TerrainKind) -> &TerrainType {
+        match kind {
+            TerrainKind::Grass => &self.grass,
+            TerrainKind::Forest => &self.forest,
+            TerrainKind::Water => &self.water,
+            TerrainKind::Rock => &self.rock,
+        }
+    }
+    // 颜色最接近的地形，距离相同时取 ALL 中靠前的
+    fn classify(&self, rgb: [u8; 3]) -> TerrainKind {
+        let color = Vec3::new(rgb[0] as f32, rgb[1] as f32, rgb[2] as f32) / 255.0;
+        let distance = |kind: TerrainKind| {
+            let ColorConfig(r, g, b) = self.get(kind).color;
+            color.distance_squared(Vec3::new(r, g, b))
+        };
+        TerrainKind::ALL.into_iter()
+            .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))
+            .unwrap()
+    }
+}
+
+///
+/// 从地形图读取的格子，按行存储，第一行是图像的最上方一行
+///
+#[derive(Clone, Debug)]
+pub struct TerrainGrid {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<TerrainKind>,
+}
+
+impl TerrainGrid {
+    ///
+    /// 读取 PNG 地形图，按 types 中各地形的颜色为每个像素分类
+    ///
+    pub fn load(path: impl AsRef<Path>, types: &TerrainConfig) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let buffer = std::fs::read(path).map_err(ConfigError::Io)?;
+        TerrainGrid::from_png(&buffer, types)
+            .map_err(|e| ConfigError::Terrain(format!("{}: {}", path.display(), e)))
+    }
+    // 解码内存中的 PNG 地形图，失败时返回错误信息
+    pub fn from_png(buffer: &[u8], types: &TerrainConfig) -> Result<Self, String> {
+        let image = Image::from_buffer(
+            buffer,
+            ImageType::Extension("png"),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::Default,
+            RenderAssetUsages::MAIN_WORLD,
+        ).map_err(|e| e.to_string())?;
+        let pixels = image.try_into_dynamic()
+            .map_err(|e| e.to_string())?
+            .into_rgb8();
+        let (width, height) = (pixels.width() as usize, pixels.height() as usize);
+        if width == 0 || height == 0 {
+            return Err("image is empty".to_string());
+        }
+        let cells = pixels.pixels().map(|p| types.classify(p.0)).collect();
+        Ok(TerrainGrid { width, height, cells })
+    }
+}
+
+///
+/// 铺满整个区域的地形，每个格子对应地形图中的一个像素
+///
+#[derive(Resource)]
+pub struct TerrainMap {
+    grid: TerrainGrid,
+    types: TerrainConfig,
+    min: Vec2,
+    cell_size: Vec2,
+}
+
+impl TerrainMap {
+    pub fn new(grid: TerrainGrid, types: TerrainConfig, bounds: &WorldBounds) -> Self {
+        let cell_size = bounds.size() / Vec2::new(grid.width as f32, grid.height as f32);
+        TerrainMap {
+            grid,
+            types,
+            min: bounds.min,
+            cell_size,
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.grid.width
+    }
+    pub fn height(&self) -> usize {
+        self.grid.height
+    }
+    pub fn cell_size(&self) -> Vec2 {
+        self.cell_size
+    }
+    // 位置所在的格子，区域外的位置取最近的格子。y 轴朝上，第 0 行在区域的最上方
+    pub fn cell(&self, pos: Vec2) -> UVec2 {
+        let rel = ((pos - self.min) / self.cell_size).floor();
+        let x = (rel.x as i64).clamp(0, self.grid.width as i64 - 1);
+        let y = (rel.y as i64).clamp(0, self.grid.height as i64 - 1);
+        UVec2::new(x as u32, (self.grid.height as i64 - 1 - y) as u32)
+    }
+    pub fn cell_center(&self, cell: UVec2) -> Vec2 {
+        let y = self.grid.height as u32 - 1 - cell.y;
+        self.min + (Vec2::new(cell.x as f32, y as f32) + 0.5) * self.cell_size
+    }
+    pub fn kind_at_cell(&self, cell: UVec2) -> TerrainKind {
+        self.grid.cells[cell.y as usize * self.grid.width + cell.x as usize]
+    }
+    pub fn kind(&self, pos: Vec2) -> TerrainKind {
+        self.kind_at_cell(self.cell(pos))
+    }
+    pub fn terrain_type(&self, pos: Vec2) -> &TerrainType {
+        self.types.get(self.kind(pos))
+    }
+    pub fn fertility(&self, pos: Vec2) -> f32 {
+        self.terrain_type(pos).fertility
+    }
+    pub fn movement_cost(&self, pos: Vec2) -> f32 {
+        self.terrain_type(pos).movement_cost
+    }
+    pub fn is_passable(&self, pos: Vec2) -> bool {
+        self.terrain_type(pos).passable
+    }
+    ///
+    /// 从 from 移动到 to 时实际到达的位置。位移按不超过半个格子的长度分段前进，一步走得再远也不会穿过一格厚的墙。
+    /// 某一段的终点不可通行时沿位移较大的一个轴滑动，滑不动则停在上一段的终点，第一段就走不通时返回 None。
+    /// 已经身处不可通行格子的个体可以自由离开
+    ///
+    pub fn step(&self, from: Vec2, to: Vec2, bounds: &WorldBounds) -> Option<Vec2> {
+        if !self.is_passable(from) {
+            return Some(to);
+        }
+        let delta = bounds.delta(from, to);
+        let segments = (delta.abs() / (self.cell_size * 0.5)).max_element().ceil().max(1.0) as u32;
+        let segment = delta / segments as f32;
+        let mut pos = from;
+        let mut slid = false;
+        for i in 1..=segments {
+            // 没有滑动过时最后一段正好走到 to，滑动过的个体每段都从当前位置前进一段，继续沿墙滑动
+            let target = if i == segments && !slid {
+                to
+            } else {
+                bounds.constrain(pos + segment).0
+            };
+            match self.step_once(pos, target) {
+                Some(next) => {
+                    slid |= next != target;
+                    pos = next;
+                }
+                None if i == 1 => return None,
+                None => break,
+            }
+        }
+        Some(pos)
+    }
+    fn step_once(&self, from: Vec2, to: Vec2) -> Option<Vec2> {
+        if self.is_passable(to) {
+            return Some(to);
+        }
+        let slide_x = Vec2::new(to.x, from.y);
+        let slide_y = Vec2::new(from.x, to.y);
+        let slides = if (to.x - from.x).abs() >= (to.y - from.y).abs() {
+            [slide_x, slide_y]
+        } else {
+            [slide_y, slide_x]
+        };
+        slides.into_iter().find(|&pos| pos != from && self.is_passable(pos))
+    }
+    // 按地形颜色生成的图像，用于显示
+    fn to_image(&self) -> Image {
+        let data = self.grid.cells.iter()
+            .flat_map(|&kind| {
+                let ColorConfig(r, g, b) = self.types.get(kind).color;
+                [r, g, b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8).into_iter().chain([255])
+            })
+            .collect();
+        let mut image = Image::new(
+            Extent3d {
+                width: self.grid.width as u32,
+                height: self.grid.height as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        image.sampler = ImageSampler::nearest();
+        image
+    }
+}
+
+///
+/// 配置中带有地形图时插入 TerrainMap，需要在插入 WorldBounds 之后加入。
+/// 没有地形图时整个区域都是可通行的草地
+///
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding TerrainPlugin");
+        let bounds = app.world().get_resource::<WorldBounds>()
+            .expect("WorldBounds must be inserted before adding TerrainPlugin");
+        if let Some(grid) = config.terrain_map.clone() {
+            let terrain = TerrainMap::new(grid, config.terrain, bounds);
+            app.insert_resource(terrain);
+        }
+    }
+}
+
+// 在所有实体下方铺一张与区域等大的地形图
+pub fn setup_terrain_display(
+    mut commands: Commands,
+    terrain: Option<Res<TerrainMap>>,
+    bounds: Res<WorldBounds>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(terrain) = terrain else {
+        return;
+    };
+    let center = (bounds.min + bounds.max) / 2.0;
+    commands.spawn((
+        Sprite {
+            image: images.add(terrain.to_image()),
+            custom_size: Some(bounds.size()),
+            ..default()
+        },
+        Transform::from_xyz(center.x, center.y, -1.0),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_bounds::EdgeBehavior;
+
+    const CELL: f32 = 10.0;
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in bytes {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    fn adler32(bytes: &[u8]) -> u32 {
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in bytes {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        (b << 16) | a
+    }
+
+    // 按行编码 RGB 的 PNG，图像数据放在一个不压缩的 deflate 块中
+    fn encode_png(rows: &[&[[u8; 3]]]) -> Vec<u8> {
+        let (width, height) = (rows[0].len() as u32, rows.len() as u32);
+        let raw: Vec<u8> = rows.iter()
+            .flat_map(|row| std::iter::once(0).chain(row.iter().flatten().copied()))
+            .collect();
+        let mut zlib = vec![0x78, 0x01, 0x01];
+        zlib.extend((raw.len() as u16).to_le_bytes());
+        zlib.extend((!(raw.len() as u16)).to_le_bytes());
+        zlib.extend(&raw);
+        zlib.extend(adler32(&raw).to_be_bytes());
+        let mut ihdr = Vec::new();
+        ihdr.extend(width.to_be_bytes());
+        ihdr.extend(height.to_be_bytes());
+        ihdr.extend([8, 2, 0, 0, 0]);
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        for (kind, data) in [(b"IHDR", ihdr), (b"IDAT", zlib), (b"IEND", Vec::new())] {
+            png.extend((data.len() as u32).to_be_bytes());
+            let chunk: Vec<u8> = kind.iter().chain(data.iter()).copied().collect();
+            png.extend(&chunk);
+            png.extend(crc32(&chunk).to_be_bytes());
+        }
+        png
+    }
+
+    // 由字符画构造地形，'.' 为草地，'#' 为岩石，第一行在最上方，每个格子边长为 CELL
+    fn build_terrain(rows: &[&str]) -> (TerrainMap, WorldBounds) {
+        let (width, height) = (rows[0].len(), rows.len());
+        let cells = rows.iter()
+            .flat_map(|row| row.chars().map(|c| if c == '#' { TerrainKind::Rock } else { TerrainKind::Grass }))
+            .collect();
+        let bounds = WorldBounds::new(width as f32 * CELL, height as f32 * CELL, EdgeBehavior::Clamp);
+        (TerrainMap::new(TerrainGrid { width, height, cells }, TerrainConfig::default(), &bounds), bounds)
+    }
+
+    #[test]
+    fn png_pixels_map_to_nearest_terrain() {
+        let png = encode_png(&[
+            &[[0, 255, 0], [0, 102, 0], [0, 0, 255]],
+            &[[128, 128, 128], [20, 230, 10], [100, 110, 120]],
+        ]);
+        let types = TerrainConfig::default();
+        let grid = TerrainGrid::from_png(&png, &types).expect("a valid png");
+        assert_eq!((grid.width, grid.height), (3, 2));
+        let bounds = WorldBounds::new(3.0 * CELL, 2.0 * CELL, EdgeBehavior::Clamp);
+        let terrain = TerrainMap::new(grid, types, &bounds);
+        let expected = [
+            (0, 0, TerrainKind::Grass, 1.0, 1.0, true),
+            (1, 0, TerrainKind::Forest, 0.5, 2.0, true),
+            (2, 0, TerrainKind::Water, 0.0, 1.0, false),
+            (0, 1, TerrainKind::Rock, 0.0, 1.0, false),
+            (1, 1, TerrainKind::Grass, 1.0, 1.0, true),
+            (2, 1, TerrainKind::Rock, 0.0, 1.0, false),
+        ];
+        for (x, y, kind, fertility, movement_cost, passable) in expected {
+            let pos = terrain.cell_center(UVec2::new(x, y));
+            assert_eq!(terrain.cell(pos), UVec2::new(x, y));
+            assert_eq!(terrain.kind(pos), kind, "cell ({}, {})", x, y);
+            assert_eq!(terrain.fertility(pos), fertility, "cell ({}, {})", x, y);
+            assert_eq!(terrain.movement_cost(pos), movement_cost, "cell ({}, {})", x, y);
+            assert_eq!(terrain.is_passable(pos), passable, "cell ({}, {})", x, y);
+        }
+        // 第 0 行在区域的最上方
+        assert!(terrain.cell_center(UVec2::new(0, 0)).y > terrain.cell_center(UVec2::new(0, 1)).y);
+    }
+
+    #[test]
+    fn invalid_png_is_rejected() {
+        assert!(TerrainGrid::from_png(b"not a png", &TerrainConfig::default()).is_err());
+    }
+
+    #[test]
+    fn step_slides_along_wall() {
+        let (terrain, bounds) = build_terrain(&[
+            "..#.",
+            "..#.",
+            "..#.",
+        ]);
+        let from = terrain.cell_center(UVec2::new(1, 1));
+        // 斜着撞向墙时沿墙滑动，不穿过墙
+        let to = from + Vec2::new(8.0, 3.0);
+        assert!(!terrain.is_passable(to));
+        assert_eq!(terrain.step(from, to, &bounds), Some(Vec2::new(from.x + 4.0, to.y)));
+        let to = from + Vec2::new(3.0, -8.0);
+        assert_eq!(terrain.step(from, to, &bounds), Some(to));
+        // 正对着墙时走到墙前的最后一段停下，紧贴着墙时原地不动
+        assert_eq!(terrain.step(from, from + Vec2::new(8.0, 0.0), &bounds), Some(from + Vec2::new(4.0, 0.0)));
+        let near = from + Vec2::new(4.0, 0.0);
+        assert_eq!(terrain.step(near, near + Vec2::new(4.0, 0.0), &bounds), None);
+        // 已经在墙里的个体可以自由离开
+        let inside = terrain.cell_center(UVec2::new(2, 1));
+        assert_eq!(terrain.step(inside, from, &bounds), Some(from));
+    }
+
+    #[test]
+    fn fast_step_does_not_tunnel_through_wall() {
+        let (terrain, bounds) = build_terrain(&[
+            "...#...",
+            "...#...",
+            "...#...",
+        ]);
+        let from = terrain.cell_center(UVec2::new(1, 1));
+        // 终点在墙另一侧的草地上，一步跨过整面墙
+        let to = terrain.cell_center(UVec2::new(5, 1));
+        assert!(terrain.is_passable(to));
+        let reached = terrain.step(from, to, &bounds).expect("moves up to the wall");
+        assert!(terrain.cell(reached).x < 3, "tunnelled to {:?}", reached);
+        assert!(reached.x > from.x);
+        // 斜着跨过墙时停在墙前并沿墙滑动
+        let reached = terrain.step(from, to + Vec2::new(0.0, 12.0), &bounds).expect("slides along the wall");
+        assert!(terrain.cell(reached).x < 3, "tunnelled to {:?}", reached);
+        assert!(reached.y > from.y);
+        // 没有墙时一步走到终点
+        let open = terrain.cell_center(UVec2::new(4, 0));
+        assert_eq!(terrain.step(to, open + Vec2::new(2.0, 0.0), &bounds), Some(open + Vec2::new(2.0, 0.0)));
+    }
+}
@@ -0,0 +1,87 @@
+//! 测试用的无窗口 App。
+
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use crate::animal::AnimalAgent;
+use crate::chase::ChaseConfig;
+use crate::config::Config;
+use crate::herding::HerdingConfig;
+use crate::life_stage::GestationConfig;
+use crate::lifecycle::Species;
+use crate::movemement::MyPosition;
+use crate::pack_hunting::PackConfig;
+use crate::sim_rng::RngKey;
+use crate::spatial_index::SpeciesIndex;
+use crate::species::{default_species, SpeciesDefinition};
+use crate::water::{AmbushConfig, ThirstConfig};
+use crate::EcospherePlugin;
+
+///
+/// 与 HeadlessPlugin 相同地每次 update 推进一个固定时间步长，但不加入日志与自动退出
+///
+pub fn headless_app(config: Config) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Time::<Fixed>::default().timestep()))
+        .insert_resource(config)
+        .add_plugins(EcospherePlugin);
+    app
+}
+
+///
+/// 运行到模拟时间不少于 secs 秒
+///
+pub fn run_until(app: &mut App, secs: f32) {
+    while app.world().resource::<Time<Fixed>>().elapsed_secs() < secs {
+        app.update();
+    }
+}
+
+///
+/// 按 RngKey 的顺序把 species 的前 positions.len() 个个体放到给定的位置，并立即更新空间索引。
+/// 返回被放置的个体
+///
+pub fn place_animals(app: &mut App, species: Species, positions: &[Vec2]) -> Vec<Entity> {
+    let world = app.world_mut();
+    let mut animals: Vec<(RngKey, Entity)> = world.query::<(&RngKey, Entity, &Species)>()
+        .iter(world)
+        .filter(|(_, _, &s)| s == species)
+        .map(|(&key, entity, _)| (key, entity))
+        .collect();
+    animals.sort_unstable();
+    assert!(animals.len() >= positions.len(), "not enough animals of {:?} to place", species);
+    let placed: Vec<Entity> = animals.iter().zip(positions).map(|(&(_, entity), &pos)| {
+        world.get_mut::<MyPosition>(entity).unwrap().0 = pos;
+        world.resource_mut::<SpeciesIndex<AnimalAgent>>().get_mut(species).update(entity, pos);
+        entity
+    }).collect();
+    placed
+}
+
+///
+/// 内置的牛与虎，并开启所有可选的能力：牛有群体行为、妊娠、幼年期与口渴，虎口渴、合作捕猎、在水边伏击并会放弃追捕
+///
+pub fn all_features_species() -> Vec<SpeciesDefinition> {
+    let mut species = default_species();
+    for definition in species.iter_mut() {
+        let reproduction = definition.reproduction.as_mut().unwrap();
+        reproduction.gestation = GestationConfig {
+            gestation_time: 8.0,
+            litter_sizes: vec![(1, 1.0), (2, 1.0)],
+        };
+        reproduction.juvenile.maturity_age = 15.0;
+        definition.thirst = Some(ThirstConfig::default());
+    }
+    species[0].herding = Some(HerdingConfig {
+        separation_weight: 0.5,
+        alignment_weight: 0.3,
+        cohesion_weight: 0.3,
+        vigilance: true,
+        ..default()
+    });
+    species[1].initial_count = 5;
+    species[1].pack = Some(PackConfig::default());
+    species[1].ambush = Some(AmbushConfig::default());
+    species[1].chase = Some(ChaseConfig::default());
+    species
+}
@@ -0,0 +1,25 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+use bevy::prelude::*;
+use rand::Rng;
+use crate::movemement::Movement;
+
+///
+/// 闲逛：空闲且感知不到猎物时，朝随机方向行走，每隔一段时间换一个方向
+///
+#[derive(Component, Deref, DerefMut)]
+pub struct Wander(pub Timer);
+
+impl Wander {
+    pub fn from_interval(interval: f32) -> Self {
+        Wander(Timer::from_seconds(interval, TimerMode::Repeating))
+    }
+    ///
+    /// 推进计时器，到时或当前静止时换一个随机方向
+    ///
+    pub fn step(&mut self, delta: Duration, movement: &mut Movement, rng: &mut impl Rng) {
+        if self.tick(delta).just_finished() || movement.direction == Vec2::ZERO {
+            movement.direction = Vec2::from_angle(rng.gen::<f32>() * TAU);
+        }
+    }
+}
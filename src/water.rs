@@ -0,0 +1,464 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use crate::aging::Age;
+use crate::calendar::SeasonalModifiers;
+use crate::config::Config;
+use crate::energy::{energy_system, Energy};
+use crate::kd_tree_index::KdTreeIndex;
+use crate::life_stage::Juvenile;
+use crate::lifecycle::{DeathCause, DeathEvent, Species};
+use crate::movemement::{Movement, MyPosition};
+use crate::pathfinding::{steer_towards, NavPath, NavigationGrid};
+use crate::prey_agent::{HunterAgent, ATTACK_DISTANCE};
+use crate::spatial_index::SpatialBackend;
+use crate::species::SpeciesRegistry;
+use crate::terrain::{TerrainKind, TerrainMap};
+use crate::world_bounds::WorldBounds;
+
+// 到达饮水点的距离
+pub const DRINK_DISTANCE: f32 = ATTACK_DISTANCE;
+// 水边的饮水点离水面的距离，按格子边长的比例，使饮水点落在岸上的格子里
+const SHORE_OFFSET: f32 = 0.05;
+
+#[derive(Component, Clone, Copy)]
+#[require(DrinkPoint)]
+pub struct Hydration(pub f32);
+
+// 出发找水时选定的饮水点，到达或放弃后清空
+#[derive(Component, Default, Clone, Copy)]
+pub struct DrinkPoint(pub Option<Vec2>);
+
+///
+/// 饮水者：口渴时前往最近的水源饮水，饮水时对捕食者的警觉降低
+///
+pub trait DrinkerAgent {
+    // 处于可以去找水的状态
+    fn can_seek_water(&self) -> bool;
+    fn is_seeking_water(&self) -> bool;
+    fn is_drinking(&self) -> bool;
+    fn switch_to_seeking_water(&mut self);
+    fn switch_to_drinking(&mut self);
+    fn stop_drinking(&mut self);
+}
+
+///
+/// 口渴的参数。水分从 capacity 开始每秒减少 drain_rate，降到 0 时渴死；
+/// 空闲时水分低于 threshold 则前往 search_radius 内最近的水源，到达后每秒喝 drink_rate 直到喝满。
+/// 饮水时发现捕食者的距离乘以 drinking_vigilance
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ThirstConfig {
+    pub capacity: f32,
+    pub drain_rate: f32,
+    pub threshold: f32,
+    pub search_radius: f32,
+    pub drink_rate: f32,
+    pub drinking_vigilance: f32,
+}
+impl Default for ThirstConfig {
+    fn default() -> Self {
+        ThirstConfig {
+            capacity: 100.0,
+            drain_rate: 1.0,
+            threshold: 50.0,
+            search_radius: 500.0,
+            drink_rate: 20.0,
+            drinking_vigilance: 0.5,
+        }
+    }
+}
+
+///
+/// 伏击的参数：空闲且感知范围内没有猎物时，前往 radius 内最近的水源，在 lurk_distance 内守候
+///
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AmbushConfig {
+    pub radius: f32,
+    pub lurk_distance: f32,
+}
+impl Default for AmbushConfig {
+    fn default() -> Self {
+        AmbushConfig {
+            radius: 600.0,
+            lurk_distance: 30.0,
+        }
+    }
+}
+
+// 配置中声明的圆形水源，水源本身不阻挡移动
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct WaterSource {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+///
+/// 所有水源：配置中声明的圆形水源，以及地形图中水面与可通行地形相邻处的饮水点。
+/// 岸边的饮水点很多，放在空间索引中，第 i 个饮水点对应的键为 Entity::from_raw(i)
+///
+#[derive(Resource, Default)]
+pub struct WaterMap {
+    sources: Vec<WaterSource>,
+    shore: Vec<Vec2>,
+    shore_index: KdTreeIndex,
+}
+
+impl WaterMap {
+    pub fn new(sources: &[WaterSource], terrain: Option<&TerrainMap>, bounds: &WorldBounds) -> Self {
+        let mut shore = Vec::new();
+        if let Some(terrain) = terrain {
+            let (width, height) = (terrain.width() as i64, terrain.height() as i64);
+            for y in 0..height {
+                for x in 0..width {
+                    let cell = UVec2::new(x as u32, y as u32);
+                    if terrain.kind_at_cell(cell) != TerrainKind::Water {
+                        continue;
+                    }
+                    let water = terrain.cell_center(cell);
+                    for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                        let (nx, ny) = (x + dx, y + dy);
+                        if !(0..width).contains(&nx) || !(0..height).contains(&ny) {
+                            continue;
+                        }
+                        let land = terrain.cell_center(UVec2::new(nx as u32, ny as u32));
+                        if terrain.kind(land) != TerrainKind::Water && terrain.is_passable(land) {
+                            shore.push(land + (water - land) * (0.5 - SHORE_OFFSET));
+                        }
+                    }
+                }
+            }
+        }
+        let mut shore_index = KdTreeIndex::default();
+        shore_index.set_bounds(bounds);
+        for (i, &point) in shore.iter().enumerate() {
+            shore_index.insert(Entity::from_raw(i as u32), point);
+        }
+        WaterMap {
+            sources: sources.to_vec(),
+            shore,
+            shore_index,
+        }
+    }
+    pub fn sources(&self) -> &[WaterSource] {
+        &self.sources
+    }
+    ///
+    /// radius 内最近的饮水点：圆形水源取水边离 pos 最近的点，已经在水中时就是 pos 本身。
+    /// 距离相同时取先声明的水源，圆形水源优先于岸边
+    ///
+    pub fn nearest(&self, pos: Vec2, radius: f32, bounds: &WorldBounds) -> Option<Vec2> {
+        let sources = self.sources.iter().map(|source| {
+            let delta = bounds.delta(pos, Vec2::new(source.x, source.y));
+            let length = delta.length();
+            if length <= source.radius {
+                pos
+            } else {
+                bounds.constrain(pos + delta * (1.0 - source.radius / length)).0
+            }
+        });
+        let shore = self.shore_index.get_nearest(pos)
+            .map(|(_, key)| self.shore[key.index() as usize]);
+        sources.chain(shore)
+            .map(|point| (bounds.distance(pos, point), point))
+            .filter(|&(distance, _)| distance <= radius)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, point)| point)
+    }
+}
+
+///
+/// 水源与口渴。存在地形图时需要在 TerrainPlugin 之后加入
+///
+pub struct WaterPlugin;
+
+impl Plugin for WaterPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().get_resource::<Config>()
+            .expect("Config must be inserted before adding WaterPlugin");
+        let bounds = app.world().get_resource::<WorldBounds>()
+            .expect("WorldBounds must be inserted before adding WaterPlugin");
+        let water = WaterMap::new(&config.water_sources, app.world().get_resource::<TerrainMap>(), bounds);
+        app.insert_resource(water)
+            .add_systems(FixedUpdate, hydration_system.after(energy_system));
+    }
+}
+
+// 声明了 thirst 的物种的水分随时间减少，降到 0 时渴死。删除实体的命令按查询顺序串行发出
+pub fn hydration_system(time: Res<Time>,
+                        registry: Res<SpeciesRegistry>,
+                        mut query: Query<(Entity, &mut Hydration, &Species, &MyPosition, &Age, &Energy)>,
+                        mut death_events: EventWriter<DeathEvent>,
+                        mut commands: Commands) {
+    query.iter_mut().for_each(|(entity, mut hydration, &species, pos, age, energy)| {
+        let Some(thirst) = registry.get(species).thirst.as_ref() else {
+            return;
+        };
+        hydration.0 -= thirst.drain_rate * time.delta_secs();
+        if hydration.0 <= 0.0 {
+            death_events.send(DeathEvent {
+                entity,
+                species,
+                cause: DeathCause::Dehydration,
+                position: pos.0,
+                age: age.elapsed_secs(),
+                energy: energy.0,
+                killer: None,
+            });
+            commands.entity(entity).despawn();
+        }
+    });
+}
+
+// 应该被放在寻找配偶、食物与尸体之前，空闲时口渴优先。出发时选定最近的饮水点
+pub fn find_water<T: Component + DrinkerAgent>(mut query: Query<(&mut T, &mut DrinkPoint, &Hydration, &Species, &MyPosition)>,
+                                               registry: Res<SpeciesRegistry>,
+                                               water: Res<WaterMap>,
+                                               bounds: Res<WorldBounds>) {
+    query.par_iter_mut().for_each(|(mut agent, mut target, hydration, &species, pos)| {
+        let Some(thirst) = registry.get(species).thirst.as_ref() else {
+            return;
+        };
+        if agent.can_seek_water() && hydration.0 < thirst.threshold {
+            if let Some(point) = water.nearest(pos.0, thirst.search_radius, &bounds) {
+                agent.switch_to_seeking_water();
+                target.0 = Some(point);
+            }
+        }
+    });
+}
+
+type DrinkerData<T> = (&'static mut T, &'static mut Hydration, &'static mut DrinkPoint, &'static Species,
+                       &'static mut Movement, &'static mut NavPath, &'static MyPosition);
+
+// 走向选定的饮水点，到达后原地饮水直到喝满。没有选定的饮水点（例如从快照恢复）时重新查找，找不到水源时回到 idle
+pub fn drink<T: Component + DrinkerAgent>(mut query: Query<DrinkerData<T>>,
+                                          registry: Res<SpeciesRegistry>,
+                                          water: Res<WaterMap>,
+                                          nav: Option<Res<NavigationGrid>>,
+                                          bounds: Res<WorldBounds>,
+                                          time: Res<Time>) {
+    query.par_iter_mut().for_each(|(mut agent, mut hydration, mut target, &species, mut movement, mut path, pos)| {
+        let Some(thirst) = registry.get(species).thirst.as_ref() else {
+            return;
+        };
+        if agent.is_seeking_water() {
+            if target.0.is_none() {
+                target.0 = water.nearest(pos.0, thirst.search_radius, &bounds);
+            }
+            match target.0 {
+                Some(point) if bounds.distance(pos.0, point) < DRINK_DISTANCE => {
+                    agent.switch_to_drinking();
+                    target.0 = None;
+                    movement.direction = Vec2::ZERO;
+                }
+                Some(point) => {
+                    movement.direction = steer_towards(nav.as_deref(), &mut path, &bounds, pos.0, point);
+                }
+                None => agent.stop_drinking(),
+            }
+        } else if agent.is_drinking() {
+            if movement.direction != Vec2::ZERO {
+                movement.direction = Vec2::ZERO;
+            }
+            hydration.0 = (hydration.0 + thirst.drink_rate * time.delta_secs()).min(thirst.capacity);
+            if hydration.0 >= thirst.capacity {
+                agent.stop_drinking();
+            }
+        }
+    });
+}
+
+type LurkerData<T> = (&'static T, &'static Species, &'static mut Movement, &'static mut NavPath, &'static MyPosition, Has<Juvenile>);
+
+// 应该被放在 find_prey 之后，覆盖没有找到猎物的伏击者的闲逛方向：前往最近的水源，在水边守候。
+// 幼年个体与当前不捕食的物种不伏击
+pub fn lurk_at_water<T: Component + HunterAgent>(mut query: Query<LurkerData<T>>,
+                                                 registry: Res<SpeciesRegistry>,
+                                                 modifiers: Res<SeasonalModifiers>,
+                                                 water: Res<WaterMap>,
+                                                 nav: Option<Res<NavigationGrid>>,
+                                                 bounds: Res<WorldBounds>) {
+    query.par_iter_mut().for_each(|(agent, &species, mut movement, mut path, pos, juvenile)| {
+        let Some(ambush) = registry.get(species).ambush.as_ref() else {
+            return;
+        };
+        if !agent.is_idle() || juvenile || modifiers.hunting(species) <= 0.0 {
+            return;
+        }
+        if let Some(point) = water.nearest(pos.0, ambush.radius, &bounds) {
+            movement.direction = if bounds.distance(pos.0, point) <= ambush.lurk_distance {
+                Vec2::ZERO
+            } else {
+                steer_towards(nav.as_deref(), &mut path, &bounds, pos.0, point)
+            };
+        }
+    });
+}
+
+// 在地形图之上画出配置中声明的圆形水源
+pub fn setup_water_display(
+    mut commands: Commands,
+    water: Res<WaterMap>,
+    config: Res<Config>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for source in water.sources() {
+        commands.spawn((
+            Mesh2d(meshes.add(Circle::new(source.radius))),
+            MeshMaterial2d(config.water_material.clone()),
+            Transform::from_xyz(source.x, source.y, -0.5),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animal::{AnimalAgent, AnimalState};
+    use crate::lifecycle::LifecycleStatistics;
+    use crate::species::SpeciesDefinition;
+    use crate::terrain::{TerrainConfig, TerrainGrid};
+    use crate::test_app::{headless_app, place_animals, run_until};
+    use crate::world_bounds::EdgeBehavior;
+
+    // 附近没有水源的个体在 capacity / drain_rate 秒后渴死
+    #[test]
+    fn dies_of_dehydration_without_water() {
+        let thirst = ThirstConfig {
+            capacity: 10.0,
+            drain_rate: 2.0,
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(1),
+            species: vec![SpeciesDefinition {
+                name: "Camel".to_string(),
+                initial_count: 1,
+                energy: 1000.0,
+                thirst: Some(thirst),
+                ..default()
+            }],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        let camel = Species::Animal(0);
+        let count = |app: &mut App| app.world_mut().query::<&Hydration>().iter(app.world()).count();
+        run_until(&mut app, 4.9);
+        assert_eq!(count(&mut app), 1);
+        assert_eq!(app.world().resource::<LifecycleStatistics>().deaths(camel, DeathCause::Dehydration), 0);
+        run_until(&mut app, 5.1);
+        assert_eq!(count(&mut app), 0);
+        let statistics = app.world().resource::<LifecycleStatistics>();
+        assert_eq!(statistics.deaths(camel, DeathCause::Dehydration), 1);
+        assert_eq!(statistics.deaths(camel, DeathCause::Starvation), 0);
+    }
+
+    // 口渴的个体走到水源边喝满水，活过没有水时会渴死的时间
+    #[test]
+    fn thirsty_animal_drinks_at_nearest_source() {
+        let thirst = ThirstConfig {
+            capacity: 60.0,
+            drain_rate: 2.0,
+            threshold: 50.0,
+            ..default()
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(2),
+            water_sources: vec![
+                WaterSource { x: 150.0, y: 0.0, radius: 20.0 },
+                WaterSource { x: -300.0, y: 0.0, radius: 20.0 },
+            ],
+            species: vec![SpeciesDefinition {
+                name: "Camel".to_string(),
+                initial_count: 1,
+                energy: 1000.0,
+                lifespan: 1000.0,
+                thirst: Some(thirst),
+                ..default()
+            }],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        let camel = place_animals(&mut app, Species::Animal(0), &[Vec2::ZERO])[0];
+        let mut states = Vec::new();
+        while app.world().resource::<Time<Fixed>>().elapsed_secs() < 60.0 {
+            app.update();
+            let entity = app.world().entity(camel);
+            let state = entity.get::<AnimalAgent>().unwrap().state;
+            if state == AnimalState::Drinking {
+                // 在较近的水源边饮水
+                let pos = entity.get::<MyPosition>().unwrap().0;
+                assert!(pos.distance(Vec2::new(150.0, 0.0)) < 20.0 + DRINK_DISTANCE);
+            }
+            if states.last() != Some(&state) {
+                states.push(state);
+            }
+        }
+        assert!(states.windows(2).any(|w| w == [AnimalState::SeekingWater, AnimalState::Drinking]));
+        assert!(states.windows(2).any(|w| w == [AnimalState::Drinking, AnimalState::Idle]));
+        assert!(app.world().get::<Hydration>(camel).is_some_and(|h| h.0 > 0.0));
+        assert_eq!(app.world().resource::<LifecycleStatistics>().deaths(Species::Animal(0), DeathCause::Dehydration), 0);
+    }
+
+    // 没有猎物时伏击者到最近的水边守候
+    #[test]
+    fn hunter_lurks_at_water_without_prey() {
+        let ambush = AmbushConfig {
+            radius: 600.0,
+            lurk_distance: 30.0,
+        };
+        let config = Config {
+            initial_grass_count: 0,
+            seed: Some(2),
+            water_sources: vec![WaterSource { x: 300.0, y: 100.0, radius: 20.0 }],
+            species: vec![SpeciesDefinition {
+                name: "Croc".to_string(),
+                initial_count: 1,
+                energy: 1000.0,
+                lifespan: 1000.0,
+                diet: vec!["Grass".to_string()],
+                ambush: Some(ambush),
+                ..default()
+            }],
+            ..default()
+        };
+        let mut app = headless_app(config);
+        app.update();
+        let croc = place_animals(&mut app, Species::Animal(0), &[Vec2::ZERO])[0];
+        run_until(&mut app, 30.0);
+        let entity = app.world().entity(croc);
+        let pos = entity.get::<MyPosition>().unwrap().0;
+        assert!(pos.distance(Vec2::new(300.0, 100.0)) <= 20.0 + 30.0 + 1.0, "lurking at {}", pos);
+        assert_eq!(entity.get::<Movement>().unwrap().direction, Vec2::ZERO);
+        assert_eq!(entity.get::<AnimalAgent>().unwrap().state, AnimalState::Idle);
+    }
+
+    // 岸边的饮水点在水面旁边可通行的格子里，靠近水面一侧
+    #[test]
+    fn shore_points_lie_on_land_next_to_water() {
+        let (cell, size) = (10.0, 4);
+        let mut cells = vec![TerrainKind::Grass; size * size];
+        cells[size + 1] = TerrainKind::Water;
+        cells[size + 2] = TerrainKind::Rock;
+        let bounds = WorldBounds::new(size as f32 * cell, size as f32 * cell, EdgeBehavior::Clamp);
+        let grid = TerrainGrid { width: size, height: size, cells };
+        let terrain = TerrainMap::new(grid, TerrainConfig::default(), &bounds);
+        let water = WaterMap::new(&[], Some(&terrain), &bounds);
+        // 水面右侧是岩石，只有另外三侧有饮水点
+        assert_eq!(water.shore.len(), 3);
+        let lake = terrain.cell_center(UVec2::new(1, 1));
+        for &point in water.shore.iter() {
+            assert!(terrain.is_passable(point));
+            assert!(point.distance(lake) < cell * 0.5 + 1.0);
+        }
+        let left = terrain.cell_center(UVec2::new(0, 1));
+        assert_eq!(water.nearest(left, 100.0, &bounds), Some(left + (lake - left) * (0.5 - SHORE_OFFSET)));
+        assert_eq!(water.nearest(left, 1.0, &bounds), None);
+    }
+}
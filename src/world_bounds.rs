@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use crate::config::Config;
+
+// 实体越过区域边界时的处理方式
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdgeBehavior {
+    // 停在边界上
+    Clamp,
+    // 像光线一样被边界反射，运动方向随之反向
+    Reflect,
+    // 从另一侧穿出，区域首尾相接成环面
+    Wrap,
+}
+
+///
+/// 模拟区域，以原点为中心，宽高取自配置
+///
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct WorldBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub edge_behavior: EdgeBehavior,
+}
+
+impl WorldBounds {
+    pub fn new(width: f32, height: f32, edge_behavior: EdgeBehavior) -> Self {
+        let half = Vec2::new(width, height) / 2.0;
+        WorldBounds {
+            min: -half,
+            max: half,
+            edge_behavior,
+        }
+    }
+    pub fn from_config(config: &Config) -> Self {
+        WorldBounds::new(config.width, config.height, config.edge_behavior)
+    }
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+    pub fn is_wrapping(&self) -> bool {
+        self.edge_behavior == EdgeBehavior::Wrap
+    }
+    ///
+    /// 把位置约束回区域内。返回新位置，以及各轴上运动方向是否需要反向（仅 Reflect 时可能为真）
+    ///
+    pub fn constrain(&self, pos: Vec2) -> (Vec2, BVec2) {
+        match self.edge_behavior {
+            EdgeBehavior::Clamp => (pos.clamp(self.min, self.max), BVec2::FALSE),
+            EdgeBehavior::Reflect => {
+                let mut pos = pos;
+                let mut flip = BVec2::FALSE;
+                if pos.x < self.min.x {
+                    pos.x = 2.0 * self.min.x - pos.x;
+                    flip.x = true;
+                } else if pos.x > self.max.x {
+                    pos.x = 2.0 * self.max.x - pos.x;
+                    flip.x = true;
+                }
+                if pos.y < self.min.y {
+                    pos.y = 2.0 * self.min.y - pos.y;
+                    flip.y = true;
+                } else if pos.y > self.max.y {
+                    pos.y = 2.0 * self.max.y - pos.y;
+                    flip.y = true;
+                }
+                // 一步越过整个区域时反射后仍可能在区域外，此时直接截断
+                (pos.clamp(self.min, self.max), flip)
+            }
+            EdgeBehavior::Wrap => {
+                let size = self.size();
+                let pos = Vec2::new(
+                    self.min.x + (pos.x - self.min.x).rem_euclid(size.x),
+                    self.min.y + (pos.y - self.min.y).rem_euclid(size.y),
+                );
+                (pos, BVec2::FALSE)
+            }
+        }
+    }
+    ///
+    /// 从 from 指向 to 的位移。环面上取最短的那一个
+    ///
+    pub fn delta(&self, from: Vec2, to: Vec2) -> Vec2 {
+        let d = to - from;
+        if self.is_wrapping() {
+            let size = self.size();
+            Vec2::new(
+                d.x - size.x * (d.x / size.x).round(),
+                d.y - size.y * (d.y / size.y).round(),
+            )
+        } else {
+            d
+        }
+    }
+    pub fn distance(&self, a: Vec2, b: Vec2) -> f32 {
+        self.delta(a, b).length()
+    }
+    ///
+    /// 从 from 指向 to 的单位方向，两点重合时为零向量
+    ///
+    pub fn direction(&self, from: Vec2, to: Vec2) -> Vec2 {
+        self.delta(from, to).normalize_or_zero()
+    }
+}
+
+impl Default for WorldBounds {
+    fn default() -> Self {
+        WorldBounds::from_config(&Config::default())
+    }
+}